@@ -1,3 +1,4 @@
 pub mod logging;
+pub mod timing;
 pub mod tui;
 pub mod utils;
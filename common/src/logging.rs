@@ -1,7 +1,14 @@
-use std::sync::{LazyLock, OnceLock};
+use std::{
+    cell::{Cell, RefCell},
+    ffi::OsString,
+    path::PathBuf,
+    sync::{LazyLock, OnceLock, RwLock},
+};
 
 use anyhow::Context as _;
 
+use crate::{timing::Timings, utils::ResultExt as _};
+
 fn parse_env<T: std::str::FromStr<Err: std::error::Error + Send + Sync + 'static>>(
     name: &str,
 ) -> anyhow::Result<T> {
@@ -18,40 +25,195 @@ pub enum ProcKind {
 
 pub const LOG_NAME_ENV_VAR: &str = "BAR_PROC_LOG_NAME";
 
+/// Env var with the log spec, e.g. `info,example_bar::clients::tray=trace`.
+/// Falls back to `RUST_LOG`.
+///
+/// The targets of records from panels are prefixed with [`PANEL_TARGET_PREFIX`] and the
+/// panel's name, so e.g. `panel::MENU=trace` or `panel::BAR@DP-1=off` set the level for
+/// everything that a panel logs.
+pub const LOG_SPEC_VAR: &str = "BAR_LOG";
+/// See [`LOG_SPEC_VAR`] and [`log_from_panel`].
+pub const PANEL_TARGET_PREFIX: &str = "panel";
+/// Env var that enables log files. Either `on`, for [`default_log_dir`], or a directory.
+pub const LOG_FILES_VAR: &str = "BAR_LOG_FILES";
+
 const COLOR_VAR: &str = "COLOR";
 
+/// Log files are rotated once they are larger than this.
+const LOG_FILE_MAX_SIZE: u64 = 10 * 1024 * 1024;
+/// Number of rotated log files to keep per process.
+const LOG_FILE_KEEP: usize = 5;
+
+/// How and where to log. Panels inherit the config of the controller, see [`child_env`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogConfig {
+    /// Per-target filters, see [`flexi_logger::LogSpecification::parse`].
+    pub spec: String,
+    /// Whether to use colors for output to the terminal.
+    pub color: bool,
+    /// If set, logs are also written to rotated files in this directory.
+    pub log_dir: Option<PathBuf>,
+    /// Where the timings of [`crate::timing::span`]s go.
+    pub timings: Timings,
+}
+impl LogConfig {
+    pub fn default_spec() -> &'static str {
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "info"
+        }
+    }
+
+    /// Reads the config from [`LOG_SPEC_VAR`], `COLOR`, [`LOG_FILES_VAR`] and
+    /// [`crate::timing::TIMINGS_VAR`].
+    pub fn from_env() -> Self {
+        let spec = [LOG_SPEC_VAR, "RUST_LOG"]
+            .into_iter()
+            .find_map(|name| std::env::var(name).ok().filter(|it| !it.trim().is_empty()))
+            .unwrap_or_else(|| Self::default_spec().to_owned());
+        let color = match std::env::var(COLOR_VAR).as_deref().unwrap_or("auto") {
+            "never" | "no" | "off" | "false" => false,
+            "always" | "yes" | "on" | "true" => true,
+            _ => std::io::IsTerminal::is_terminal(&std::io::stderr()),
+        };
+        let log_dir = match std::env::var_os(LOG_FILES_VAR) {
+            None => None,
+            Some(val) => match val.to_str() {
+                Some("" | "never" | "no" | "off" | "false" | "0") => None,
+                Some("always" | "yes" | "on" | "true" | "1") => default_log_dir(),
+                _ => Some(val.into()),
+            },
+        };
+        Self {
+            spec,
+            color,
+            log_dir,
+            timings: Timings::from_env(),
+        }
+    }
+
+    /// Env vars that make [`Self::from_env`] return this config.
+    pub fn to_env(&self) -> Vec<(&'static str, OsString)> {
+        vec![
+            (LOG_SPEC_VAR, self.spec.clone().into()),
+            (
+                COLOR_VAR,
+                if self.color { "always" } else { "never" }.into(),
+            ),
+            (
+                LOG_FILES_VAR,
+                self.log_dir
+                    .as_ref()
+                    .map_or_else(|| "off".into(), |dir| dir.clone().into_os_string()),
+            ),
+            self.timings.to_env(),
+        ]
+    }
+}
+
+/// `$XDG_STATE_HOME/bar/logs`, or `~/.local/state/bar/logs` if it is not set.
+pub fn default_log_dir() -> Option<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|it| it.is_absolute())
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".local/state")))?;
+    Some(state_home.join("bar").join("logs"))
+}
+
 static PROC_NAME: OnceLock<String> = OnceLock::new();
+static CONFIG: OnceLock<LogConfig> = OnceLock::new();
+
+type LogForwarder = Box<dyn Fn(&log::Record) -> bool + Send + Sync>;
+static FORWARDER: RwLock<Option<LogForwarder>> = RwLock::new(None);
+
+thread_local! {
+    /// Set while forwarding a record, so that records logged by the forwarder itself
+    /// are not forwarded.
+    static FORWARDING: Cell<bool> = const { Cell::new(false) };
+    /// The name and pid of the panel whose record is being logged, see [`log_from_panel`].
+    static PANEL_PROC: RefCell<Option<(String, u32)>> = const { RefCell::new(None) };
+}
+
+/// Makes `forward` handle the records of this process instead of the logger, e.g. to send
+/// them to the controller. If it returns `false`, the record is logged normally.
+pub fn set_log_forwarder(forward: impl Fn(&log::Record) -> bool + Send + Sync + 'static) {
+    *FORWARDER.write().unwrap_or_else(|it| it.into_inner()) = Some(Box::new(forward));
+}
+
+/// Logs a record that was forwarded by the panel `proc_name` (see [`set_log_forwarder`]),
+/// as if it was logged by that process. The target is prefixed as described in
+/// [`LOG_SPEC_VAR`].
+pub fn log_from_panel(proc_name: &str, pid: u32, record: &log::Record) {
+    let target = format!("{PANEL_TARGET_PREFIX}::{proc_name}::{}", record.target());
+    PANEL_PROC.set(Some((proc_name.to_owned(), pid)));
+    log::logger().log(
+        &log::Record::builder()
+            .args(*record.args())
+            .level(record.level())
+            .target(&target)
+            .module_path(record.module_path())
+            .file(record.file())
+            .line(record.line())
+            .build(),
+    );
+    PANEL_PROC.set(None);
+}
+
+struct ForwardFilter;
+impl flexi_logger::filter::LogLineFilter for ForwardFilter {
+    fn write(
+        &self,
+        now: &mut flexi_logger::DeferredNow,
+        record: &log::Record,
+        log_line_writer: &dyn flexi_logger::filter::LogLineWriter,
+    ) -> std::io::Result<()> {
+        // Timings are not forwarded, since forwarding is itself timed
+        if !FORWARDING.get() && record.target() != crate::timing::TIMING_TARGET {
+            FORWARDING.set(true);
+            let forwarded = FORWARDER
+                .read()
+                .unwrap_or_else(|it| it.into_inner())
+                .as_ref()
+                .is_some_and(|forward| forward(record));
+            FORWARDING.set(false);
+            if forwarded {
+                return Ok(());
+            }
+        }
+        log_line_writer.write(now, record)
+    }
+}
+
+/// Env vars for child processes, so that they log like this one.
+/// Empty if the logger has not been initialized.
+pub fn child_env() -> Vec<(&'static str, OsString)> {
+    CONFIG.get().map(LogConfig::to_env).unwrap_or_default()
+}
 
 fn format_log(
     w: &mut dyn std::io::Write,
     now: &mut flexi_logger::DeferredNow,
     record: &log::Record,
+    color: bool,
 ) -> Result<(), std::io::Error> {
     struct Format {
-        color: bool,
         pid: u32,
         proc_name: String,
     }
     static FORMAT: LazyLock<Format> = LazyLock::new(|| Format {
         pid: std::process::id(),
-        // FIXME: This is only OK for the controller. The panels should instead use the settings
-        // of the controller.
-        color: match std::env::var(COLOR_VAR).as_deref().unwrap_or("auto") {
-            "never" | "no" | "off" | "false" => false,
-            "always" | "yes" | "on" | "true" => true,
-            _ => std::io::IsTerminal::is_terminal(&std::io::stderr()),
-        },
         proc_name: PROC_NAME
             .get()
             .map(|s| &**s)
             .unwrap_or("UNKNOWN")
             .to_owned(),
     });
-    let Format {
-        color,
-        pid,
-        ref proc_name,
-    } = *FORMAT;
+    let panel_proc = PANEL_PROC.take();
+    let (proc_name, pid) = match &panel_proc {
+        Some((proc_name, pid)) => (proc_name, *pid),
+        None => (&FORMAT.proc_name, FORMAT.pid),
+    };
 
     let line_display = record.line();
     let line_display = if let Some(line) = &line_display {
@@ -77,17 +239,34 @@ fn format_log(
         format_args!("{level}")
     };
 
-    write!(
+    let res = write!(
         w,
         "[{now_display}] {proc_name} ({pid}) {level_display} [{}:{line_display}] {}",
         record.file().unwrap_or("<unknown>"),
         record.args(),
-    )
+    );
+    // The record may be written more than once, e.g. to a file and to stderr
+    PANEL_PROC.set(panel_proc);
+    res
+}
+fn format_colored(
+    w: &mut dyn std::io::Write,
+    now: &mut flexi_logger::DeferredNow,
+    record: &log::Record,
+) -> Result<(), std::io::Error> {
+    format_log(w, now, record, true)
+}
+fn format_plain(
+    w: &mut dyn std::io::Write,
+    now: &mut flexi_logger::DeferredNow,
+    record: &log::Record,
+) -> Result<(), std::io::Error> {
+    format_log(w, now, record, false)
 }
 
-pub fn init_logger(proc_kind: ProcKind, log_name: String) {
+pub fn init_logger(proc_kind: ProcKind, log_name: String, config: LogConfig) {
     _ = PROC_NAME.set(log_name);
-    match try_init_logger(proc_kind) {
+    match try_init_logger(proc_kind, config) {
         Ok(_) => log::info!("Started logger"),
         Err(err) => {
             let err = err.context(format!(
@@ -102,28 +281,118 @@ pub fn init_logger(proc_kind: ProcKind, log_name: String) {
 
 // FIXME: Move most of this function to controller and mgr respectively?
 // I.e. only keep the formatter around in common
-fn try_init_logger(proc_kind: ProcKind) -> anyhow::Result<()> {
+fn try_init_logger(proc_kind: ProcKind, config: LogConfig) -> anyhow::Result<()> {
     static PROC_KIND: OnceLock<ProcKind> = OnceLock::new();
     let proc_kind = PROC_KIND.get_or_init(|| proc_kind);
 
     use flexi_logger::*;
 
-    let log_spec: LogSpecification = if cfg!(debug_assertions) {
-        flexi_logger::LevelFilter::Debug.into()
+    let config = CONFIG.get_or_init(|| config);
+
+    let (log_spec, spec_err) = match LogSpecification::parse(&config.spec) {
+        Ok(spec) => (spec, None),
+        Err(err) => (
+            LogSpecification::parse(LogConfig::default_spec())?,
+            Some(err),
+        ),
+    };
+    let log_spec = match proc_kind {
+        // Records from panels were already filtered by the panel
+        ProcKind::Controller
+            if log_spec
+                .level_for_module(Some(PANEL_TARGET_PREFIX))
+                .is_none() =>
+        {
+            LogSpecBuilder::from_module_filters(log_spec.module_filters())
+                .module(PANEL_TARGET_PREFIX, LevelFilter::Trace)
+                .build_with_textfilter(log_spec.text_filter().cloned())
+        }
+        ProcKind::Controller => log_spec,
+        ProcKind::Panel => {
+            // A filter for the panel's prefixed target applies to everything it logs
+            let target = format!(
+                "{PANEL_TARGET_PREFIX}::{}",
+                PROC_NAME.get().map_or("", |it| it)
+            );
+            let panel_level = log_spec.module_filters().iter().find_map(|filter| {
+                let name = filter.module_name.as_deref()?;
+                target.starts_with(name).then_some(filter.level_filter)
+            });
+            match panel_level {
+                Some(level) => LogSpecBuilder::new().default(level).build(),
+                None => log_spec,
+            }
+        }
+    };
+
+    let term_format: FormatFunction = if config.color {
+        format_colored
     } else {
-        flexi_logger::LevelFilter::Info.into()
+        format_plain
+    };
+    // Where output for the terminal goes
+    let term_file = match proc_kind {
+        ProcKind::Controller => None,
+        ProcKind::Panel => parse_env::<std::os::fd::RawFd>("KITTY_STDIO_FORWARDED")
+            .ok()
+            .map(|fd| FileSpec::try_from(format!("/proc/self/fd/{fd}")))
+            .transpose()?,
     };
 
-    let logger = Logger::with(log_spec).o_append(true).format(format_log);
-    let logger = match proc_kind {
-        ProcKind::Controller => logger.log_to_stderr(),
-        ProcKind::Panel => match parse_env::<std::os::fd::RawFd>("KITTY_STDIO_FORWARDED") {
-            Ok(fd) => logger.log_to_file(FileSpec::try_from(format!("/proc/self/fd/{fd}"))?),
-            Err(_) => logger.log_to_stderr(),
-        },
+    let logger = Logger::with(log_spec)
+        .o_append(true)
+        .format(term_format)
+        .filter(Box::new(ForwardFilter));
+    let logger = match (&config.log_dir, term_file) {
+        (None, None) => logger.log_to_stderr(),
+        (None, Some(term_file)) => logger.log_to_file(term_file),
+        (Some(log_dir), term_file) => {
+            let name = PROC_NAME
+                .get()
+                .map_or("unknown".into(), |it| it.to_lowercase())
+                .replace(['/', ' '], "_");
+            let file_spec = FileSpec::default()
+                .directory(log_dir)
+                .basename(name)
+                .suppress_timestamp();
+            let logger = logger.format_for_files(format_plain).rotate(
+                Criterion::Size(LOG_FILE_MAX_SIZE),
+                Naming::Numbers,
+                Cleanup::KeepLogFiles(LOG_FILE_KEEP),
+            );
+            match term_file {
+                None => logger
+                    .log_to_file(file_spec)
+                    .duplicate_to_stderr(Duplicate::All)
+                    .format_for_stderr(term_format),
+                Some(term_file) => {
+                    let term_writer = writers::FileLogWriter::builder(term_file)
+                        .append()
+                        .format(term_format)
+                        .try_build()?;
+                    logger.log_to_file_and_writer(file_spec, Box::new(term_writer))
+                }
+            }
+        }
     };
     std::mem::forget(logger.start()?);
 
+    if let Some(err) = spec_err {
+        log::error!(
+            "Invalid log spec {:?}, using {:?}: {err}",
+            config.spec,
+            LogConfig::default_spec()
+        );
+    }
+
+    crate::timing::init(
+        &config.timings,
+        *proc_kind,
+        PROC_NAME.get().map_or("unknown", |it| it),
+    )
+    .context("Failed to start recording timings")
+    .ok_or_log();
+
     let hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
         log::error!("{info}");
@@ -0,0 +1,215 @@
+//! Timings of latency-sensitive sections, such as rendering and IPC.
+//!
+//! Timings are disabled by default and are enabled with [`TIMINGS_VAR`] (see
+//! [`crate::logging::LogConfig::timings`]). They are either logged or written to a
+//! trace file in the [Trace Event Format], which can be opened in e.g. Perfetto.
+//!
+//! [Trace Event Format]: https://docs.google.com/document/d/1CvAClvFfyA1R-PhYUmn5OE5XQ5n-ciSLEcUgHh5NHA8
+
+use std::{
+    cell::Cell,
+    ffi::OsString,
+    io::Write as _,
+    path::PathBuf,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context as _;
+
+use crate::logging::ProcKind;
+
+/// Env var that enables timings. Either `log`, to log them with target [`TIMING_TARGET`]
+/// at debug level, or the path of a trace file. All processes write to the same file.
+pub const TIMINGS_VAR: &str = "BAR_TIMINGS";
+
+/// The log target of timings, e.g. for `BAR_LOG=info,timing=debug`.
+///
+/// Timings of panels are not forwarded to the controller (see
+/// [`crate::logging::set_log_forwarder`]), since forwarding is itself timed.
+pub const TIMING_TARGET: &str = "timing";
+
+/// Where timings go.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Timings {
+    #[default]
+    Off,
+    Log,
+    TraceFile(PathBuf),
+}
+impl Timings {
+    pub(crate) fn from_env() -> Self {
+        match std::env::var_os(TIMINGS_VAR) {
+            None => Self::Off,
+            Some(val) => match val.to_str() {
+                Some("" | "never" | "no" | "off" | "false" | "0") => Self::Off,
+                Some("log") => Self::Log,
+                _ => Self::TraceFile(val.into()),
+            },
+        }
+    }
+
+    pub(crate) fn to_env(&self) -> (&'static str, OsString) {
+        (
+            TIMINGS_VAR,
+            match self {
+                Self::Off => "off".into(),
+                Self::Log => "log".into(),
+                Self::TraceFile(path) => path.clone().into_os_string(),
+            },
+        )
+    }
+}
+
+enum Output {
+    Log,
+    TraceFile(Mutex<std::fs::File>),
+}
+static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+/// Starts recording timings. The controller starts a new trace file, panels append to it.
+pub(crate) fn init(timings: &Timings, proc_kind: ProcKind, proc_name: &str) -> anyhow::Result<()> {
+    let output = match timings {
+        Timings::Off => return Ok(()),
+        Timings::Log => Output::Log,
+        Timings::TraceFile(path) => {
+            let mut opts = std::fs::OpenOptions::new();
+            match proc_kind {
+                ProcKind::Controller => opts.write(true).create(true).truncate(true),
+                ProcKind::Panel => opts.append(true),
+            };
+            let mut file = opts
+                .open(path)
+                .with_context(|| format!("Failed to open trace file {path:?}"))?;
+            if proc_kind == ProcKind::Controller {
+                // The closing bracket is optional
+                file.write_all(b"[\n")?;
+            }
+            file.write_all(
+                format!(
+                    "{{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":{},\"args\":{{\"name\":{proc_name:?}}}}},\n",
+                    std::process::id(),
+                )
+                .as_bytes(),
+            )?;
+            Output::TraceFile(Mutex::new(file))
+        }
+    };
+    _ = OUTPUT.set(output);
+    Ok(())
+}
+
+/// Starts timing a section named `name`. The timing is recorded when the span is
+/// dropped or [finished](Span::finish).
+#[must_use = "The span is recorded when it is dropped"]
+pub fn span(name: &'static str) -> Span {
+    Span {
+        name,
+        start: Instant::now(),
+        done: false,
+    }
+}
+
+#[derive(Debug)]
+pub struct Span {
+    name: &'static str,
+    start: Instant,
+    done: bool,
+}
+impl Span {
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Records the span and returns its duration, which is also available if timings
+    /// are disabled.
+    pub fn finish(mut self) -> Duration {
+        self.done = true;
+        self.record()
+    }
+
+    fn record(&self) -> Duration {
+        let dur = self.start.elapsed();
+        match OUTPUT.get() {
+            None => {}
+            Some(Output::Log) => {
+                log::debug!(target: TIMING_TARGET, "{} took {:.3}ms", self.name, dur.as_secs_f64() * 1000.0);
+            }
+            Some(Output::TraceFile(file)) => {
+                let ts = SystemTime::now()
+                    .checked_sub(dur)
+                    .and_then(|it| it.duration_since(SystemTime::UNIX_EPOCH).ok())
+                    .unwrap_or_default();
+                write_event(
+                    file,
+                    &format!(
+                        "{{\"name\":{:?},\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":{},\"tid\":{}}},\n",
+                        self.name,
+                        ts.as_micros(),
+                        dur.as_micros(),
+                        std::process::id(),
+                        thread_id(),
+                    ),
+                );
+            }
+        }
+        dur
+    }
+}
+impl Drop for Span {
+    fn drop(&mut self) {
+        if !self.done {
+            self.record();
+        }
+    }
+}
+
+/// Records the current value of a counter named `name`, e.g. how many frames were
+/// dropped so far, which trace viewers show as a graph.
+pub fn counter(name: &'static str, value: u64) {
+    match OUTPUT.get() {
+        None => {}
+        Some(Output::Log) => log::debug!(target: TIMING_TARGET, "{name} is {value}"),
+        Some(Output::TraceFile(file)) => {
+            let ts = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            write_event(
+                file,
+                &format!(
+                    "{{\"name\":{name:?},\"ph\":\"C\",\"ts\":{},\"pid\":{},\"args\":{{\"value\":{value}}}}},\n",
+                    ts.as_micros(),
+                    std::process::id(),
+                ),
+            );
+        }
+    }
+}
+
+fn write_event(file: &Mutex<std::fs::File>, event: &str) {
+    // Each event is written at once, so that processes do not interleave
+    if let Err(err) = file
+        .lock()
+        .unwrap_or_else(|it| it.into_inner())
+        .write_all(event.as_bytes())
+    {
+        log::debug!("Failed to write to trace file: {err}");
+    }
+}
+
+/// A small number that identifies the current thread in traces.
+fn thread_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: Cell<u64> = const { Cell::new(0) };
+    }
+    ID.with(|id| {
+        if id.get() == 0 {
+            id.set(NEXT.fetch_add(1, Ordering::Relaxed));
+        }
+        id.get()
+    })
+}
@@ -75,18 +75,99 @@ impl Axis {
 
 #[derive(Debug)]
 pub struct RenderedLayout {
+    /// In the order they were rendered, so enclosing elements come first.
     pub(super) widgets: Vec<(Area, InteractElem)>,
     pub(super) last_mouse_pos: Option<Vec2<u16>>,
+    /// The innermost element under the pointer.
     pub(super) last_hover_elem: Option<InteractElem>,
+    /// See [`SizingArgs::states`].
+    pub(super) states: WidgetStates,
+    /// The scroll elements that can be scrolled, in the order they were rendered.
+    pub(super) scroll_areas: Vec<ScrollArea>,
+    /// A scroll element was scrolled, so the element under the pointer may have
+    /// changed without the pointer moving.
+    pub(super) scrolled: bool,
+    /// The elements that a mouse button was pressed on, which receive the events until
+    /// the button is released.
+    pub(super) pressed: Option<Press>,
+    pub(super) last_click: Option<LastClick>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Press {
+    button: MouseButton,
+    /// The cell that the button was pressed on.
+    pub(super) start: Vec2<u16>,
+    /// See [`RenderedLayout::elems_at`].
+    pub(super) elems: Vec<(Area, InteractElem)>,
+    dragging: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct ScrollArea {
+    pub(super) area: Area,
+    pub(super) key: WidgetKey,
+    pub(super) axis: Axis,
+    pub(super) offset: usize,
+    pub(super) max_offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct LastClick {
+    button: MouseButton,
+    pos: Vec2<u16>,
+    time: std::time::Instant,
+    count: u8,
 }
 
 pub struct MouseEventResult {
-    pub interact: InteractArgs,
-    pub callback: Option<InteractCallback>,
+    pub kind: InteractKind,
+    /// The interactive elements that the event is for, innermost first. Each passes
+    /// the event on to the next if its callback calls [`InteractArgs::bubble`].
+    pub targets: Vec<InteractTarget>,
     pub empty: bool,
     pub changed: bool,
     pub rerender: bool,
+    /// A scroll element was scrolled, which moves other elements under the pointer.
+    /// Pass a [`crossterm::event::MouseEventKind::Moved`] at the same position once
+    /// it is re-rendered to update what is hovered.
+    pub scrolled: bool,
+    /// The cell under the pointer.
+    pub pointer: Vec2<u16>,
+    /// The pointer in pixels. This is the center of [`Self::pointer`] unless the
+    /// terminal reports pixels (see [`TermFeatures::pixel_mouse`]).
+    pub pix_pointer: Vec2<u32>,
+}
+
+pub struct InteractTarget {
+    pub interact: InteractArgs,
+    pub callback: InteractCallback,
+    /// The center of the element in pixels.
     pub pix_location: Vec2<u32>,
+    /// The size of the element in pixels.
+    pub pix_size: Vec2<u32>,
+}
+impl InteractTarget {
+    /// Calls the callback, continuing if it passed the event on.
+    pub fn call(self) -> std::ops::ControlFlow<Option<OpenMenu>> {
+        let bubble = self.interact.bubble.clone();
+        let menu = self.callback.call(self.interact);
+        if bubble.load(std::sync::atomic::Ordering::Relaxed) {
+            std::ops::ControlFlow::Continue(())
+        } else {
+            std::ops::ControlFlow::Break(menu)
+        }
+    }
+}
+
+/// Switches mouse reporting to SGR-Pixels (mode 1016), which reports the position in
+/// pixels instead of cells. Requires mouse capture to be enabled.
+#[derive(Debug, Clone, Copy)]
+pub struct EnablePixelMouse;
+impl crossterm::Command for EnablePixelMouse {
+    fn write_ansi(&self, f: &mut impl std::fmt::Write) -> std::fmt::Result {
+        f.write_str("\x1b[?1016h")
+    }
 }
 
 impl RenderedLayout {
@@ -94,6 +175,53 @@ impl RenderedLayout {
         self.widgets.push((area, elem.clone()));
     }
 
+    /// The state that the layout was rendered with.
+    pub fn states(&self) -> &WidgetStates {
+        &self.states
+    }
+
+    /// The interactive elements that contain `pos`, innermost first.
+    pub(super) fn elems_at(&self, pos: Vec2<u16>) -> Vec<(Area, InteractElem)> {
+        // Elements are nested or disjoint, so these are ancestors of each other
+        self.widgets
+            .iter()
+            .rev()
+            .filter(|(r, _)| r.contains(pos))
+            .cloned()
+            .collect()
+    }
+
+    /// Scrolls the innermost scroll element at `pos` that can be scrolled in the
+    /// direction, returning whether there is one.
+    fn scroll_at(&mut self, pos: Vec2<u16>, direction: Direction) -> bool {
+        let (axis, forward) = match direction {
+            Direction::Up => (Axis::Y, false),
+            Direction::Down => (Axis::Y, true),
+            Direction::Left => (Axis::X, false),
+            Direction::Right => (Axis::X, true),
+        };
+        let Some(scroll) = self.scroll_areas.iter_mut().rev().find(|it| {
+            it.axis == axis
+                && it.area.contains(pos)
+                && if forward {
+                    it.offset < it.max_offset
+                } else {
+                    it.offset > 0
+                }
+        }) else {
+            return false;
+        };
+        scroll.offset = if forward {
+            scroll.offset + 1
+        } else {
+            scroll.offset - 1
+        };
+        let offset = scroll.offset;
+        self.states
+            .update(scroll.key.clone(), |it: &mut ScrollOffset| it.0 = offset);
+        true
+    }
+
     pub fn ext_focus_loss(&mut self) -> bool {
         let changed = self
             .last_hover_elem
@@ -101,24 +229,48 @@ impl RenderedLayout {
             .is_some_and(|it| it.hovered.is_some());
         self.last_mouse_pos = None;
         self.last_hover_elem = None;
+        // The release will not be reported
+        self.pressed = None;
         changed
     }
 
+    /// Interprets a mouse event of the terminal, whose position is in pixels if
+    /// `sizing` has [`TermFeatures::pixel_mouse`]. Clicks of the same button on the
+    /// same cell count as one multi-click if they are at most `multi_click_interval`
+    /// apart.
     pub fn interpret_mouse_event(
         &mut self,
         event: crossterm::event::MouseEvent,
-        font_size: Vec2<u16>,
+        sizing: &SizingArgs,
+        multi_click_interval: std::time::Duration,
     ) -> MouseEventResult {
         use crossterm::event::*;
 
+        let _span = crate::timing::span("tui::interpret_mouse_event");
+
         let MouseEvent {
             kind,
             column,
             row,
-            modifiers: _,
+            modifiers,
         } = event;
 
-        let pos = Vec2 { x: column, y: row };
+        let font_size = sizing.font_size;
+        let (pos, pix_pointer) = if sizing.features.pixel_mouse {
+            let pix = Vec2 { x: column, y: row };
+            (
+                pix.combine(font_size, |p, f| p.checked_div(f).unwrap_or(0)),
+                pix.combine(font_size, |p, _| u32::from(p)),
+            )
+        } else {
+            let pos = Vec2 { x: column, y: row };
+            (
+                pos,
+                pos.combine(font_size, |p, f| {
+                    u32::from(p) * u32::from(f) + u32::from(f) / 2
+                }),
+            )
+        };
 
         self.last_mouse_pos = Some(pos);
 
@@ -126,58 +278,179 @@ impl RenderedLayout {
         type IK = InteractKind;
         type MK = crossterm::event::MouseEventKind;
 
-        let kind = match kind {
-            MK::Down(button) => IK::Click(button.into()),
-            MK::ScrollDown => IK::Scroll(DR::Down),
-            MK::ScrollUp => IK::Scroll(DR::Up),
-            MK::ScrollLeft => IK::Scroll(DR::Left),
-            MK::ScrollRight => IK::Scroll(DR::Right),
-            MK::Moved | MK::Up(_) | MK::Drag(_) => IK::Hover,
+        let under_pointer = self.elems_at(pos);
+
+        // Drags and releases go to the elements that the button was pressed on, even if
+        // the pointer has left them.
+        let (kind, targets) = match kind {
+            MK::Down(button) => {
+                let button = self::MouseButton::from(button);
+                self.pressed = (!under_pointer.is_empty()).then(|| Press {
+                    button: button.clone(),
+                    start: pos,
+                    elems: under_pointer.clone(),
+                    dragging: false,
+                });
+                (IK::Click(button), under_pointer)
+            }
+            MK::Drag(button) => match &mut self.pressed {
+                Some(press) if press.button == button.into() => {
+                    let kind = if std::mem::replace(&mut press.dragging, true) {
+                        IK::Drag(button.into())
+                    } else {
+                        IK::DragStart(button.into())
+                    };
+                    (kind, press.elems.clone())
+                }
+                _ => (IK::Hover, under_pointer),
+            },
+            MK::Up(button) => match self.pressed.take() {
+                Some(press) if press.button == button.into() => {
+                    let kind = if press.dragging {
+                        IK::DragEnd(button.into())
+                    } else {
+                        IK::Release(button.into())
+                    };
+                    (kind, press.elems)
+                }
+                _ => (IK::Release(button.into()), under_pointer),
+            },
+            MK::ScrollDown => (IK::Scroll(DR::Down), under_pointer),
+            MK::ScrollUp => (IK::Scroll(DR::Up), under_pointer),
+            MK::ScrollLeft => (IK::Scroll(DR::Left), under_pointer),
+            MK::ScrollRight => (IK::Scroll(DR::Right), under_pointer),
+            MK::Moved => (IK::Hover, under_pointer),
         };
 
-        let interact = InteractArgs { kind };
+        // Scroll elements take precedence, since the items in them would swallow the
+        // event otherwise
+        if let IK::Scroll(direction) = &kind
+            && self.scroll_at(pos, *direction)
+        {
+            self.scrolled = true;
+            return MouseEventResult {
+                kind,
+                targets: Vec::new(),
+                empty: false,
+                changed: false,
+                // Updating the state re-renders
+                rerender: false,
+                scrolled: true,
+                pointer: pos,
+                pix_pointer,
+            };
+        }
+
+        let clicks = match &kind {
+            IK::Click(button) => {
+                let now = std::time::Instant::now();
+                let count = match &self.last_click {
+                    Some(last)
+                        if last.button == *button
+                            && last.pos == pos
+                            && now.duration_since(last.time) <= multi_click_interval =>
+                    {
+                        last.count.saturating_add(1)
+                    }
+                    _ => 1,
+                };
+                self.last_click = Some(LastClick {
+                    button: button.clone(),
+                    pos,
+                    time: now,
+                    count,
+                });
+                count
+            }
+            _ => 0,
+        };
 
         let font_w = u32::from(font_size.x);
         let font_h = u32::from(font_size.y);
+        let scrolled = std::mem::take(&mut self.scrolled);
 
-        let Some((area, elem)) = self.widgets.iter().find(|(r, _)| r.contains(pos)) else {
+        let Some((_, innermost)) = targets.first() else {
             let cur = self.last_hover_elem.take();
             return MouseEventResult {
-                interact,
+                kind,
+                targets: Vec::new(),
                 empty: true,
-                callback: None,
-                pix_location: Vec2 {
-                    x: u32::from(pos.x) * font_w,
-                    y: u32::from(pos.y) * font_h,
-                },
-                changed: cur.is_some(),
+                pointer: pos,
+                pix_pointer,
+                changed: scrolled || cur.is_some(),
                 rerender: cur.is_some_and(|it| it.hovered.is_some()),
+                scrolled: false,
             };
         };
 
-        let pix_location = {
-            Vec2 {
-                x: u32::from(area.pos.x) * font_w + u32::from(area.size.x) * font_w / 2,
-                y: u32::from(area.pos.y) * font_h + u32::from(area.size.y) * font_h / 2,
-            }
-        };
-
-        let prev = self.last_hover_elem.replace(elem.clone());
+        let prev = self.last_hover_elem.replace(innermost.clone());
 
-        let changed = !prev
-            .as_ref()
-            .is_some_and(|it| it.inner.is_identical(&elem.inner));
+        let changed = scrolled
+            || !prev
+                .as_ref()
+                .is_some_and(|it| it.inner.is_identical(&innermost.inner));
 
         let rerender = changed
-            && (prev.as_ref().is_some_and(|it| it.hovered.is_some()) || elem.hovered.is_some());
+            && (prev.as_ref().is_some_and(|it| it.hovered.is_some())
+                || innermost.hovered.is_some());
+
+        let targets = targets
+            .into_iter()
+            .map(|(area, elem)| {
+                let pix_pos = Vec2 {
+                    x: u32::from(area.pos.x) * font_w,
+                    y: u32::from(area.pos.y) * font_h,
+                };
+                let pix_size = Vec2 {
+                    x: u32::from(area.size.x) * font_w,
+                    y: u32::from(area.size.y) * font_h,
+                };
+                InteractTarget {
+                    interact: InteractArgs {
+                        kind: kind.clone(),
+                        modifiers: modifiers.into(),
+                        clicks,
+                        pos: pix_pointer
+                            .combine(pix_pos, u32::saturating_sub)
+                            .combine(pix_size, u32::min),
+                        size: pix_size,
+                        states: self.states.clone(),
+                        bubble: Default::default(),
+                    },
+                    callback: elem.callback,
+                    pix_location: pix_pos.combine(pix_size, |pos, size| pos + size / 2),
+                    pix_size,
+                }
+            })
+            .collect();
 
         MouseEventResult {
-            interact,
-            callback: Some(elem.callback.clone()),
+            kind,
+            targets,
             empty: false,
             changed,
             rerender,
-            pix_location,
+            scrolled: false,
+            pointer: pos,
+            pix_pointer,
+        }
+    }
+}
+
+/// Modifier keys that were held during a mouse event. Terminals cannot report others.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+impl From<crossterm::event::KeyModifiers> for Modifiers {
+    fn from(value: crossterm::event::KeyModifiers) -> Self {
+        type KM = crossterm::event::KeyModifiers;
+        Self {
+            shift: value.contains(KM::SHIFT),
+            ctrl: value.contains(KM::CONTROL),
+            alt: value.contains(KM::ALT),
         }
     }
 }
@@ -201,6 +474,13 @@ impl From<crossterm::event::MouseButton> for MouseButton {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum InteractKind {
     Click(MouseButton),
+    /// The button was released without dragging.
+    Release(MouseButton),
+    /// The pointer was first moved while the button was held.
+    DragStart(MouseButton),
+    Drag(MouseButton),
+    /// The button was released after dragging.
+    DragEnd(MouseButton),
     Scroll(Direction),
     Hover,
 }
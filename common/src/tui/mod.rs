@@ -2,6 +2,13 @@ mod render;
 pub use render::*;
 mod layout;
 pub use layout::*;
+mod vterm;
+pub use vterm::*;
+mod state;
+pub use state::*;
+mod theme;
+pub use theme::*;
+pub mod testing;
 
 use std::{fmt, sync::Arc};
 
@@ -15,6 +22,10 @@ enum ElemKind {
     Block(BlockBuilder),
     MinSize { size: Vec2<u16>, elem: Elem },
     Interact(InteractElem),
+    Slider(Slider),
+    Stateful(Callback<WidgetStates, Elem>),
+    Scroll(Scroll),
+    Themed(Callback<Theme, Elem>),
 }
 #[derive(Debug, Clone)]
 struct InteractElem {
@@ -73,6 +84,9 @@ impl Elem {
             borders: Default::default(),
             border_style: Default::default(),
             border_set: LineSet::normal(),
+            fill: None,
+            padding: Default::default(),
+            caps: Default::default(),
             inner: None,
         };
         init(&mut builder);
@@ -83,6 +97,24 @@ impl Elem {
         init(&mut builder);
         builder.build()
     }
+    /// Stacks `items` along `axis` like [`StackBuilder::fit`], but at most `max_len`
+    /// cells long. If they do not fit, a scrollbar is shown at the end across `axis`,
+    /// and the scroll wheel scrolls by whole items. The offset is kept as the state of
+    /// the widget `key`.
+    pub fn scroll(
+        key: impl Into<WidgetKey>,
+        axis: Axis,
+        max_len: u16,
+        items: impl IntoIterator<Item = Elem>,
+    ) -> Self {
+        ElemKind::Scroll(Scroll {
+            key: key.into(),
+            axis,
+            max_len,
+            items: items.into_iter().collect(),
+        })
+        .into()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -211,6 +243,85 @@ impl<D> RawPrint<D> {
     }
 }
 
+/// A line of text made of differently styled parts. Like [`RawPrint`], its width is
+/// that of the text, not counting the escapes of the styles.
+#[derive(Debug, Clone, Default)]
+pub struct Spans {
+    raw: String,
+    width: u16,
+}
+impl Spans {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    #[track_caller]
+    pub fn push(&mut self, text: impl AsRef<str>, style: Style) -> &mut Self {
+        self.push_print(RawPrint::plain(text.as_ref()).styled(style))
+    }
+    /// Appends text with a known width, e.g. from [`RawPrint::center_symbol`]. It
+    /// must be a single line.
+    pub fn push_print(&mut self, print: RawPrint<impl fmt::Display>) -> &mut Self {
+        if print.size.y > 1 {
+            log::warn!(
+                "Spans should be a single line, but got {} lines",
+                print.size.y
+            );
+        }
+        fmt::Write::write_fmt(&mut self.raw, format_args!("{}", print.raw))
+            .expect("writing to a String does not fail");
+        self.width = self.width.saturating_add(print.size.x);
+        self
+    }
+}
+impl From<Spans> for Elem {
+    fn from(value: Spans) -> Self {
+        let Spans { raw, width } = value;
+        ElemKind::Print {
+            raw,
+            size: Vec2 { x: width, y: 1 },
+        }
+        .into()
+    }
+}
+
+/// A bar that is filled up to a value between 0 and 1, drawn with partial block
+/// characters. Vertical sliders fill from the bottom. It fills the available space
+/// across its axis.
+///
+/// Use [`InteractArgs::slider_value`] to get the value under the pointer.
+#[derive(Debug, Clone, Copy)]
+pub struct Slider {
+    axis: Axis,
+    len: u16,
+    value: f64,
+    style: Style,
+}
+impl Slider {
+    /// A slider of `len` cells along `axis`. The value is clamped to `0..=1`.
+    pub fn new(axis: Axis, len: u16, value: f64) -> Self {
+        Self {
+            axis,
+            len,
+            value: if value.is_nan() {
+                0.0
+            } else {
+                value.clamp(0.0, 1.0)
+            },
+            style: Default::default(),
+        }
+    }
+    /// The style of the whole slider. The background is visible where it is not
+    /// filled.
+    pub fn styled(self, style: Style) -> Self {
+        Self { style, ..self }
+    }
+}
+impl From<Slider> for Elem {
+    fn from(value: Slider) -> Self {
+        ElemKind::Slider(value).into()
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct PlainLines<S> {
     text: S,
@@ -233,20 +344,65 @@ impl<S> PlainLines<S> {
 pub struct OpenMenu {
     pub tui: Elem,
     pub menu_kind: MenuKind,
+    pub anchor: MenuAnchor,
 }
 impl OpenMenu {
     pub fn context(tui: Elem) -> Self {
         Self {
             tui,
             menu_kind: MenuKind::Context,
+            anchor: Default::default(),
         }
     }
     pub fn tooltip(tui: Elem) -> Self {
         Self {
             tui,
             menu_kind: MenuKind::Tooltip,
+            anchor: Default::default(),
         }
     }
+    pub fn anchored(mut self, anchor: MenuAnchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+}
+
+/// Where a menu is placed. Menus are placed below the bar, or below the element of
+/// the menu that they were opened from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAnchor {
+    /// Aligns the menu horizontally with the element that it was opened from. Menus
+    /// that would not fit are flipped to the other side of the element.
+    Element(MenuAlign),
+    /// In a corner of the space below the bar.
+    Corner(Corner),
+    /// At an offset in pixels from the top left corner of the space below the bar.
+    Fixed(Vec2<u32>),
+    /// With the top left corner at the pointer, or below it for menus of the bar.
+    /// Menus that would not fit are flipped to the other side of the pointer.
+    Pointer,
+}
+impl Default for MenuAnchor {
+    fn default() -> Self {
+        Self::Element(MenuAlign::Center)
+    }
+}
+
+/// Which edge of a menu is aligned with the same edge of the element that it was
+/// opened from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAlign {
+    Start,
+    Center,
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MenuKind {
@@ -260,6 +416,36 @@ pub type InteractCallback = Callback<InteractArgs, Option<OpenMenu>>;
 #[non_exhaustive]
 pub struct InteractArgs {
     pub kind: InteractKind,
+    pub modifiers: Modifiers,
+    /// For [`InteractKind::Click`], how many clicks in quick succession this is, e.g.
+    /// 2 for a double click. Zero for other kinds.
+    pub clicks: u8,
+    /// The pointer relative to the top left corner of the element in pixels, clamped
+    /// to the element.
+    pub pos: Vec2<u32>,
+    /// The size of the element in pixels.
+    pub size: Vec2<u32>,
+    /// The state of the widgets of the panel. Updating it re-renders the panel.
+    pub states: WidgetStates,
+    bubble: Arc<std::sync::atomic::AtomicBool>,
+}
+impl InteractArgs {
+    /// Passes the event on to the enclosing interactive element, if any, once the
+    /// callback returns. The menu that the callback returns is ignored then.
+    pub fn bubble(&self) {
+        self.bubble
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// The value of a [`Slider`] along `axis` that fills the element up to the
+    /// pointer.
+    pub fn slider_value(&self, axis: Axis) -> f64 {
+        let fraction = f64::from(self.pos[axis]) / f64::from(self.size[axis].max(1));
+        match axis {
+            Axis::X => fraction,
+            Axis::Y => 1.0 - fraction,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -285,23 +471,89 @@ pub struct BlockBuilder {
     borders: Borders,
     border_style: Style,
     border_set: LineSet,
+    fill: Option<Color>,
+    padding: Padding,
+    caps: Caps,
     inner: Option<Elem>,
 }
 impl BlockBuilder {
     pub fn set_borders_at(&mut self, borders: Borders) {
         self.borders = borders;
     }
+    /// The style of the borders. Its background also fills the block, unless
+    /// [`Self::set_fill`] is used.
     pub fn set_style(&mut self, style: Style) {
         self.border_style = style;
     }
     pub fn set_lines(&mut self, lines: LineSet) {
         self.border_set = lines;
     }
+    /// Paints the whole block with this background color, including the parts of the
+    /// content that do not set their own.
+    pub fn set_fill(&mut self, color: Color) {
+        self.fill = Some(color);
+    }
+    pub fn set_padding(&mut self, padding: Padding) {
+        self.padding = padding;
+    }
+    /// Caps on the left and right of the block in its fill color, e.g. to make a pill.
+    pub fn set_caps(&mut self, caps: Caps) {
+        self.caps = caps;
+    }
     pub fn set_inner(&mut self, inner: Elem) {
         self.inner = Some(inner);
     }
 }
 
+/// Space between the borders of a block and its content, in cells.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Padding {
+    pub top: u16,
+    pub bottom: u16,
+    pub left: u16,
+    pub right: u16,
+}
+impl Padding {
+    pub fn all(n: u16) -> Self {
+        Self {
+            top: n,
+            bottom: n,
+            left: n,
+            right: n,
+        }
+    }
+    pub fn horizontal(n: u16) -> Self {
+        Self {
+            left: n,
+            right: n,
+            ..Default::default()
+        }
+    }
+}
+
+/// The ends of a filled block (see [`BlockBuilder::set_caps`]), drawn with Nerd Font
+/// glyphs. Blocks that are taller than a line use a single glyph scaled to their height
+/// if [`TermFeatures::text_sizing`] is available, or one glyph per line otherwise.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Caps {
+    #[default]
+    None,
+    /// Half circles
+    Round,
+    /// Powerline arrows pointing outwards
+    Powerline,
+}
+impl Caps {
+    /// The glyphs of the left and right cap.
+    fn glyphs(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Self::None => None,
+            Self::Round => Some(("\u{e0b6}", "\u{e0b4}")),
+            Self::Powerline => Some(("\u{e0b2}", "\u{e0b0}")),
+        }
+    }
+}
+
 #[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Borders {
     pub top: bool,
@@ -326,13 +578,24 @@ struct Stack {
     parts: Arc<[StackItem]>,
 }
 
+#[derive(Debug, Clone)]
+struct Scroll {
+    key: WidgetKey,
+    axis: Axis,
+    max_len: u16,
+    items: Arc<[Elem]>,
+}
+/// The state of a [`Scroll`], which is the index of the first item shown.
+#[derive(Debug, Clone, Copy, Default)]
+struct ScrollOffset(usize);
+
 #[derive(Debug, Clone)]
 pub struct StackItem {
     fill_weight: u16,
     elem: Elem,
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub struct Style {
     pub fg: Option<Color>,
     pub bg: Option<Color>,
@@ -352,10 +615,25 @@ pub struct Modifier {
     pub underline: bool,
     pub hidden: bool,
     pub strike: bool,
+    /// Swaps the foreground and background color.
+    pub reverse: bool,
+    pub blink: bool,
+    pub overline: bool,
+    /// How [`Self::underline`] is drawn.
+    pub underline_style: UnderlineStyle,
 
     #[doc(hidden)]
     pub __non_exhaustive: (),
 }
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnderlineStyle {
+    #[default]
+    Straight,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
 
 // TODO: enums
 #[derive(Default, Debug, Clone, Copy)]
@@ -16,6 +16,44 @@ pub(super) struct RenderCtx<'a, W> {
 #[derive(Debug, Clone)]
 pub struct SizingArgs {
     pub font_size: Vec2<u16>,
+    pub features: TermFeatures,
+    /// The state that [`Elem::stateful`] elements are built from.
+    pub states: WidgetStates,
+    /// The theme that [`Elem::themed`] elements are built from.
+    pub theme: Theme,
+}
+
+/// Optional terminal protocols that rendering may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TermFeatures {
+    /// The kitty graphics protocol. Images are replaced by placeholders without it.
+    pub graphics: bool,
+    /// The kitty text sizing protocol. Sized text is printed normally without it.
+    pub text_sizing: bool,
+    /// SGR-Pixels mouse reporting (mode 1016), see [`EnablePixelMouse`]. Mouse events
+    /// are only precise to the cell without it.
+    pub pixel_mouse: bool,
+    /// 24-bit colors. RGB colors are replaced by the closest of the 256 indexed colors
+    /// without it.
+    pub true_color: bool,
+}
+impl TermFeatures {
+    pub fn all() -> Self {
+        Self {
+            graphics: true,
+            text_sizing: true,
+            pixel_mouse: true,
+            true_color: true,
+        }
+    }
+    pub fn none() -> Self {
+        Self {
+            graphics: false,
+            text_sizing: false,
+            pixel_mouse: false,
+            true_color: false,
+        }
+    }
 }
 
 pub fn calc_min_size(elem: &Elem, args: &SizingArgs) -> Vec2<u16> {
@@ -28,6 +66,7 @@ pub fn render(
     sizing: &SizingArgs,
     old_layout: Option<&RenderedLayout>,
 ) -> std::io::Result<RenderedLayout> {
+    let _span = crate::timing::span("tui::render");
     crossterm::queue!(
         writer,
         crossterm::terminal::BeginSynchronizedUpdate,
@@ -38,15 +77,42 @@ pub fn render(
         widgets: Default::default(),
         last_mouse_pos,
         last_hover_elem: None,
+        states: sizing.states.clone(),
+        scroll_areas: Vec::new(),
+        scrolled: old_layout.is_some_and(|it| it.scrolled),
+        pressed: old_layout.and_then(|it| it.pressed.clone()),
+        last_click: old_layout.and_then(|it| it.last_click.clone()),
     };
-    elem.render(
-        &mut RenderCtx {
-            sizing,
-            writer: &mut *writer,
-            layout: &mut layout,
-        },
-        area,
-    )?;
+    if sizing.features.true_color {
+        elem.render(
+            &mut RenderCtx {
+                sizing,
+                writer: &mut *writer,
+                layout: &mut layout,
+            },
+            area,
+        )?;
+    } else {
+        let mut buf = Vec::new();
+        elem.render(
+            &mut RenderCtx {
+                sizing,
+                writer: &mut buf,
+                layout: &mut layout,
+            },
+            area,
+        )?;
+        writer.write_all(&downgrade_true_color(&buf))?;
+    }
+    // The pressed elements were likely replaced, e.g. because they show a new value
+    if let Some(press) = &layout.pressed {
+        let elems = layout.elems_at(press.start);
+        if !elems.is_empty()
+            && let Some(press) = &mut layout.pressed
+        {
+            press.elems = elems;
+        }
+    }
     crossterm::execute!(writer, crossterm::terminal::EndSynchronizedUpdate)?;
     Ok(layout)
 }
@@ -70,7 +136,12 @@ impl Render for ElemKind {
             Self::Image(image) => image.render(ctx, area),
             Self::Block(block) => block.render(ctx, area),
             Self::Print { raw, .. } => {
-                crossterm::queue!(ctx.writer, crossterm::style::Print(raw as &str))
+                let raw = if ctx.sizing.features.text_sizing {
+                    std::borrow::Cow::Borrowed(raw as &str)
+                } else {
+                    strip_text_sizing(raw)
+                };
+                crossterm::queue!(ctx.writer, crossterm::style::Print(raw))
             }
             Self::MinSize { elem, .. } => elem.render(ctx, area),
             Self::Interact(elem) => {
@@ -81,16 +152,12 @@ impl Render for ElemKind {
                     .last_mouse_pos
                     .is_some_and(|it| area.contains(it))
                 {
-                    if ctx.layout.last_hover_elem.is_some() {
-                        log::warn!("Nested interactivity is unsupported");
-                        &elem.inner
+                    // Nested elements are rendered later and replace this one
+                    ctx.layout.last_hover_elem = Some(elem.clone());
+                    if let Some(hover) = &elem.hovered {
+                        hover
                     } else {
-                        ctx.layout.last_hover_elem = Some(elem.clone());
-                        if let Some(hover) = &elem.hovered {
-                            hover
-                        } else {
-                            &elem.inner
-                        }
+                        &elem.inner
                     }
                 } else {
                     &elem.inner
@@ -98,6 +165,10 @@ impl Render for ElemKind {
 
                 inner.render(ctx, area)
             }
+            Self::Slider(slider) => slider.render(ctx, area),
+            Self::Stateful(build) => build.call(ctx.sizing.states.clone()).render(ctx, area),
+            Self::Scroll(scroll) => scroll.render(ctx, area),
+            Self::Themed(build) => build.call(ctx.sizing.theme).render(ctx, area),
         }
     }
     fn calc_min_size(&self, args: &SizingArgs) -> Vec2<u16> {
@@ -108,8 +179,251 @@ impl Render for ElemKind {
             Self::Print { size, .. } => *size,
             Self::MinSize { size, elem } => elem.calc_min_size(args).combine(*size, std::cmp::max),
             Self::Interact(elem) => elem.inner.calc_min_size(args),
+            Self::Slider(slider) => slider.calc_min_size(args),
+            Self::Stateful(build) => build.call(args.states.clone()).calc_min_size(args),
+            Self::Scroll(scroll) => scroll.calc_min_size(args),
+            Self::Themed(build) => build.call(args.theme).calc_min_size(args),
+        }
+    }
+}
+
+impl Render for Scroll {
+    fn render(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()> {
+        let axis = self.axis;
+        let view = area.size[axis];
+        // Items longer than the view are cut off
+        let lens: Vec<u16> = self
+            .items
+            .iter()
+            .map(|it| it.calc_min_size(ctx.sizing)[axis].min(view))
+            .collect();
+        let total: u32 = lens.iter().copied().map(u32::from).sum();
+
+        // The smallest offset from which the remaining items fit. Clamping to this
+        // keeps the view filled, e.g. if items were removed.
+        let max_offset = {
+            let mut rem = u32::from(view);
+            let fitting = lens
+                .iter()
+                .rev()
+                .take_while(|&&len| rem.checked_sub(len.into()).map(|it| rem = it).is_some())
+                .count();
+            (lens.len() - fitting).min(lens.len().saturating_sub(1))
+        };
+        let offset = ctx
+            .sizing
+            .states
+            .get::<ScrollOffset>(&self.key)
+            .0
+            .min(max_offset);
+
+        let scrolls = total > u32::from(view);
+        let mut items_area = area;
+        if scrolls {
+            items_area.size[axis.other()] = items_area.size[axis.other()].saturating_sub(1);
+        }
+
+        let mut pos = 0u16;
+        for (item, &len) in self.items[offset..].iter().zip(&lens[offset..]) {
+            if pos.saturating_add(len) > view {
+                break;
+            }
+            let mut subarea = items_area;
+            subarea.pos[axis] += pos;
+            subarea.size[axis] = len;
+            item.render(ctx, subarea)?;
+            pos += len;
+        }
+
+        if scrolls {
+            let before: u32 = lens[..offset].iter().copied().map(u32::from).sum();
+            let view32 = u32::from(view);
+            let thumb_start = before * view32 / total;
+            let thumb_len = (view32 * view32).div_ceil(total);
+            let (track, thumb) = match axis {
+                Axis::X => ("─", "━"),
+                Axis::Y => ("│", "┃"),
+            };
+            for i in 0..view {
+                let mut cell = area.pos;
+                cell[axis] += i;
+                cell[axis.other()] += items_area.size[axis.other()];
+                let (symbol, style) =
+                    if (thumb_start..thumb_start + thumb_len).contains(&u32::from(i)) {
+                        (thumb, Style::default())
+                    } else {
+                        (track, ctx.sizing.theme.style(ThemeRole::Muted))
+                    };
+                crossterm::queue!(
+                    ctx.writer,
+                    crossterm::cursor::MoveTo(cell.x, cell.y),
+                    crossterm::style::Print(style.apply(symbol)),
+                )?;
+            }
+
+            ctx.layout.scroll_areas.push(ScrollArea {
+                area,
+                key: self.key.clone(),
+                axis,
+                offset,
+                max_offset,
+            });
+        }
+        Ok(())
+    }
+
+    fn calc_min_size(&self, args: &SizingArgs) -> Vec2<u16> {
+        let mut size = Vec2::<u16>::default();
+        for item in self.items.iter() {
+            let item = item.calc_min_size(args);
+            size[self.axis] = size[self.axis].saturating_add(item[self.axis]);
+            size[self.axis.other()] = size[self.axis.other()].max(item[self.axis.other()]);
+        }
+        if size[self.axis] > self.max_len {
+            size[self.axis] = self.max_len;
+            // For the scrollbar
+            size[self.axis.other()] = size[self.axis.other()].saturating_add(1);
+        }
+        size
+    }
+}
+
+/// Blocks filled by 1/8 to 8/8 from the left, for horizontal sliders.
+const LEFT_BLOCKS: [char; 8] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+/// Blocks filled by 1/8 to 8/8 from the bottom, for vertical sliders.
+const LOWER_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+impl Render for Slider {
+    fn render(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()> {
+        let len = area.size[self.axis];
+        // The filled length in eighths of a cell
+        let eighths = (self.value * f64::from(len) * 8.0).round() as u32;
+        let cell = |i: u16| {
+            let filled = eighths.saturating_sub(u32::from(i) * 8).min(8);
+            let blocks = match self.axis {
+                Axis::X => &LEFT_BLOCKS,
+                Axis::Y => &LOWER_BLOCKS,
+            };
+            match filled {
+                0 => ' ',
+                n => blocks[n as usize - 1],
+            }
+        };
+        match self.axis {
+            Axis::X => {
+                let line = self.style.apply((0..len).map(cell).collect::<String>());
+                for y in area.pos.y..area.pos.y.saturating_add(area.size.y) {
+                    crossterm::queue!(
+                        ctx.writer,
+                        crossterm::cursor::MoveTo(area.pos.x, y),
+                        crossterm::style::Print(&line),
+                    )?;
+                }
+            }
+            Axis::Y => {
+                for i in 0..len {
+                    let line = self
+                        .style
+                        .apply(String::from(cell(i)).repeat(area.size.x.into()));
+                    crossterm::queue!(
+                        ctx.writer,
+                        crossterm::cursor::MoveTo(area.pos.x, area.y_bottom() - i),
+                        crossterm::style::Print(&line),
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn calc_min_size(&self, _: &SizingArgs) -> Vec2<u16> {
+        let mut size = Vec2 { x: 1, y: 1 };
+        size[self.axis] = self.len;
+        size
+    }
+}
+
+/// Replaces the RGB colors in the SGR sequences of `out` by indexed colors.
+fn downgrade_true_color(out: &[u8]) -> Vec<u8> {
+    map_sgr(out, |params, res| {
+        let mut params = params.split(';');
+        let mut downgraded = Vec::new();
+        while let Some(param) = params.next() {
+            downgraded.push(param.to_owned());
+            if !matches!(param, "38" | "48" | "58") {
+                continue;
+            }
+            match params.next() {
+                Some("2") => {
+                    let mut channel = || params.next().and_then(|it| it.parse::<u8>().ok());
+                    match (channel(), channel(), channel()) {
+                        (Some(r), Some(g), Some(b)) => {
+                            downgraded.push("5".into());
+                            downgraded.push(rgb_to_ansi256(r, g, b).to_string());
+                        }
+                        _ => log::debug!("Dropping malformed RGB color"),
+                    }
+                }
+                Some(other) => downgraded.push(other.to_owned()),
+                None => {}
+            }
         }
+        res.extend_from_slice(format!("\x1b[{}m", downgraded.join(";")).as_bytes());
+    })
+}
+
+/// The closest color of the 6x6x6 color cube and the grayscale ramp of the 256
+/// indexed colors.
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let dist = |[r2, g2, b2]: [u8; 3]| {
+        [(r, r2), (g, g2), (b, b2)]
+            .map(|(a, b)| u32::from(a.abs_diff(b)).pow(2))
+            .iter()
+            .sum::<u32>()
+    };
+
+    let cube_step = |c: u8| match c {
+        0..48 => 0,
+        48..115 => 1,
+        _ => (c - 35) / 40,
+    };
+    let cube_level = |i: u8| if i == 0 { 0 } else { 55 + i * 40 };
+    let (cr, cg, cb) = (cube_step(r), cube_step(g), cube_step(b));
+    let cube = [cube_level(cr), cube_level(cg), cube_level(cb)];
+
+    let avg = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let gray_step = (avg.saturating_sub(3) / 10).min(23) as u8;
+    let gray = [8 + gray_step * 10; 3];
+
+    if dist(gray) < dist(cube) {
+        232 + gray_step
+    } else {
+        16 + 36 * cr + 6 * cg + cb
+    }
+}
+
+/// Drawn in place of images if the graphics protocol is unavailable.
+const IMAGE_PLACEHOLDER: &str = "▒";
+
+/// Removes kitty text sizing escapes (OSC 66), keeping the text they wrap.
+fn strip_text_sizing(raw: &str) -> std::borrow::Cow<'_, str> {
+    const START: &str = "\x1b]66;";
+    if !raw.contains(START) {
+        return std::borrow::Cow::Borrowed(raw);
     }
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(start) = rest.find(START) {
+        out.push_str(&rest[..start]);
+        let esc = &rest[start + START.len()..];
+        // The escape is terminated by BEL (see KittyTextSize::apply)
+        let (body, after) = esc.split_once('\x07').unwrap_or((esc, ""));
+        // The metadata is separated from the text by the first `;`
+        out.push_str(body.split_once(';').map_or("", |(_, text)| text));
+        rest = after;
+    }
+    out.push_str(rest);
+    std::borrow::Cow::Owned(out)
 }
 
 impl Image {
@@ -160,6 +474,25 @@ impl Image {
 }
 impl Render for Image {
     fn render(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()> {
+        if !ctx.sizing.features.graphics {
+            let size = self
+                .calc_min_size(ctx.sizing)
+                .combine(area.size, std::cmp::min);
+            let line = ctx
+                .sizing
+                .theme
+                .style(ThemeRole::Muted)
+                .apply(IMAGE_PLACEHOLDER.repeat(size.x.into()));
+            for y in area.pos.y..area.pos.y.saturating_add(size.y) {
+                crossterm::queue!(
+                    ctx.writer,
+                    crossterm::cursor::MoveTo(area.pos.x, y),
+                    crossterm::style::Print(&line),
+                )?;
+            }
+            return Ok(());
+        }
+
         let img_cell_ratio = self.img_cell_ratio(ctx.sizing);
         let (fill_axis, fill_axis_len) = Self::max_fit_to_fill_axis(area.size, img_cell_ratio);
 
@@ -228,35 +561,33 @@ impl Render for Stack {
             0
         });
 
-        if total_weight > 0 {
-            let mut rem_fill_len = tot_fill_len;
-
-            for (part, len) in self.parts.iter().zip(&mut lens) {
-                let extra_len = u16::try_from(
-                    u64::from(tot_fill_len) * u64::from(part.fill_weight) / total_weight,
-                )
-                .expect("bounded by render area");
-                *len = len.checked_add(extra_len).unwrap_or_else(|| {
-                    log::error!("Element is way too large");
-                    *len
-                });
-                rem_fill_len = rem_fill_len
-                    .checked_sub(extra_len)
-                    .expect("bounded by partition via floor div");
-            }
-            if rem_fill_len > 0 {
-                let mut fills: Vec<_> = self
-                    .parts
-                    .iter()
-                    .zip(&mut lens)
-                    .filter_map(|(part, len)| {
-                        (part.fill_weight > 0).then_some((part.fill_weight, len))
-                    })
-                    .collect();
-                fills.sort();
-                for (_, len) in fills.into_iter().take(rem_fill_len.into()) {
-                    *len += 1;
-                }
+        let mut rem_fill_len = tot_fill_len;
+        for (part, len) in self.parts.iter().zip(&mut lens) {
+            let Some(extra_len) =
+                (u64::from(tot_fill_len) * u64::from(part.fill_weight)).checked_div(total_weight)
+            else {
+                // No part fills the remaining space
+                break;
+            };
+            let extra_len = u16::try_from(extra_len).expect("bounded by render area");
+            *len = len.checked_add(extra_len).unwrap_or_else(|| {
+                log::error!("Element is way too large");
+                *len
+            });
+            rem_fill_len = rem_fill_len
+                .checked_sub(extra_len)
+                .expect("bounded by partition via floor div");
+        }
+        if rem_fill_len > 0 {
+            let mut fills: Vec<_> = self
+                .parts
+                .iter()
+                .zip(&mut lens)
+                .filter_map(|(part, len)| (part.fill_weight > 0).then_some((part.fill_weight, len)))
+                .collect();
+            fills.sort();
+            for (_, len) in fills.into_iter().take(rem_fill_len.into()) {
+                *len += 1;
             }
         }
 
@@ -288,6 +619,145 @@ impl Render for Stack {
 }
 impl Render for BlockBuilder {
     fn render(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()> {
+        let Some(fill) = self.fill.or(self.border_style.bg) else {
+            return self.render_body(ctx, area);
+        };
+        // Caps as tall as the block, or per-line glyphs if those do not fit next to
+        // the content
+        let body_w = self
+            .inner
+            .as_ref()
+            .map_or(0, |it| it.calc_min_size(ctx.sizing).x);
+        let body_w = body_w.saturating_add(self.extra_dim().x);
+        let cap_w = match self.cap_width(area.size.y, ctx.sizing) {
+            w if w.saturating_mul(2).saturating_add(body_w) <= area.size.x => w,
+            w => w.min(1).min(area.size.x / 2),
+        };
+        let body = Area {
+            pos: Vec2 {
+                x: area.pos.x + cap_w,
+                ..area.pos
+            },
+            size: Vec2 {
+                x: area.size.x - 2 * cap_w,
+                ..area.size
+            },
+        };
+
+        // Render the content first, so that its background can be fixed up
+        let mut buf = Vec::new();
+        self.render_body(
+            &mut RenderCtx {
+                sizing: ctx.sizing,
+                writer: &mut buf,
+                layout: ctx.layout,
+            },
+            body,
+        )?;
+
+        let blank = " ".repeat(body.size.x.into());
+        crossterm::queue!(ctx.writer, crossterm::style::SetBackgroundColor(fill))?;
+        for y in body.pos.y..body.pos.y.saturating_add(body.size.y) {
+            crossterm::queue!(
+                ctx.writer,
+                crossterm::cursor::MoveTo(body.pos.x, y),
+                crossterm::style::Print(&blank),
+            )?;
+        }
+        ctx.writer.write_all(&keep_background(&buf, fill))?;
+        crossterm::queue!(
+            ctx.writer,
+            crossterm::style::SetBackgroundColor(Color::Reset)
+        )?;
+
+        if let Some((left, right)) = self.caps.glyphs()
+            && cap_w > 0
+        {
+            let style = Style {
+                fg: Some(fill),
+                ..Default::default()
+            };
+            let mut cap = |glyph: &str, x: u16| -> std::io::Result<()> {
+                if cap_w > 1 {
+                    // A single glyph that covers cap_w lines
+                    let size = KittyTextSize {
+                        s: Some(cap_w),
+                        ..Default::default()
+                    };
+                    return crossterm::queue!(
+                        ctx.writer,
+                        crossterm::cursor::MoveTo(x, area.pos.y),
+                        crossterm::style::Print(style.apply(size.apply(glyph))),
+                    );
+                }
+                for y in area.pos.y..area.pos.y.saturating_add(area.size.y) {
+                    crossterm::queue!(
+                        ctx.writer,
+                        crossterm::cursor::MoveTo(x, y),
+                        crossterm::style::Print(style.apply(glyph)),
+                    )?;
+                }
+                Ok(())
+            };
+            cap(left, area.pos.x)?;
+            cap(right, body.pos.x + body.size.x)?;
+        }
+
+        Ok(())
+    }
+
+    fn calc_min_size(&self, args: &SizingArgs) -> Vec2<u16> {
+        let mut size = self
+            .inner
+            .as_ref()
+            .map(|it| it.calc_min_size(args))
+            .unwrap_or_default();
+        let Vec2 { x: w, y: h } = self.extra_dim();
+        size.x = size.x.saturating_add(w);
+        size.y = size.y.saturating_add(h);
+        if self.fill.or(self.border_style.bg).is_some() {
+            size.x = size.x.saturating_add(2 * self.cap_width(size.y, args));
+        }
+        size
+    }
+}
+impl BlockBuilder {
+    /// The space taken by the borders and padding.
+    fn extra_dim(&self) -> Vec2<u16> {
+        let Borders {
+            top,
+            bottom,
+            left,
+            right,
+        } = self.borders;
+        let Padding {
+            top: pad_top,
+            bottom: pad_bottom,
+            left: pad_left,
+            right: pad_right,
+        } = self.padding;
+        Vec2 {
+            x: (u16::from(left) + u16::from(right))
+                .saturating_add(pad_left)
+                .saturating_add(pad_right),
+            y: (u16::from(top) + u16::from(bottom))
+                .saturating_add(pad_top)
+                .saturating_add(pad_bottom),
+        }
+    }
+
+    /// The width of each cap of a block that is `height` lines tall.
+    fn cap_width(&self, height: u16, args: &SizingArgs) -> u16 {
+        match self.caps.glyphs() {
+            None => 0,
+            // The text sizing protocol scales by at most 7
+            Some(_) if args.features.text_sizing && height <= 7 => height.max(1),
+            Some(_) => 1,
+        }
+    }
+
+    /// Renders the borders and content, without the fill and caps.
+    fn render_body(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()> {
         let Borders {
             top,
             bottom,
@@ -296,17 +766,27 @@ impl Render for BlockBuilder {
         } = self.borders;
 
         if let Some(inner) = &self.inner {
+            let Padding {
+                top: pad_top,
+                left: pad_left,
+                ..
+            } = self.padding;
             inner.render(
                 ctx,
                 Area {
                     pos: Vec2 {
-                        x: area.pos.x.saturating_add(left.into()),
-                        y: area.pos.y.saturating_add(top.into()),
-                    },
-                    size: Vec2 {
-                        x: area.size.x.saturating_sub(right.into()),
-                        y: area.size.y.saturating_sub(bottom.into()),
+                        x: area
+                            .pos
+                            .x
+                            .saturating_add(left.into())
+                            .saturating_add(pad_left),
+                        y: area
+                            .pos
+                            .y
+                            .saturating_add(top.into())
+                            .saturating_add(pad_top),
                     },
+                    size: area.size.combine(self.extra_dim(), u16::saturating_sub),
                 },
             )?;
         }
@@ -371,37 +851,70 @@ impl Render for BlockBuilder {
 
         Ok(())
     }
+}
 
-    fn calc_min_size(&self, args: &SizingArgs) -> Vec2<u16> {
-        let mut size = self
-            .inner
-            .as_ref()
-            .map(|it| it.calc_min_size(args))
-            .unwrap_or_default();
-        let Vec2 { x: w, y: h } = self.extra_dim();
-        size.x = size.x.saturating_add(w);
-        size.y = size.y.saturating_add(h);
-        size
+/// Calls `f` with the parameters of every SGR sequence in `out`, which writes what
+/// replaces the sequence. Everything else is kept as is.
+fn map_sgr(out: &[u8], mut f: impl FnMut(&str, &mut Vec<u8>)) -> Vec<u8> {
+    let mut res = Vec::with_capacity(out.len());
+    let mut rest = out;
+    while let Some(start) = rest.windows(2).position(|it| it == b"\x1b[") {
+        let params = &rest[start + 2..];
+        let Some(len) = params.iter().position(|it| (0x40..=0x7e).contains(it)) else {
+            break;
+        };
+        let end = start + 2 + len + 1;
+        match std::str::from_utf8(&params[..len]) {
+            Ok(sgr) if params[len] == b'm' => {
+                res.extend_from_slice(&rest[..start]);
+                f(sgr, &mut res);
+            }
+            _ => res.extend_from_slice(&rest[..end]),
+        }
+        rest = &rest[end..];
     }
+    res.extend_from_slice(rest);
+    res
 }
-impl BlockBuilder {
-    fn extra_dim(&self) -> Vec2<u16> {
-        let Borders {
-            top,
-            bottom,
-            left,
-            right,
-        } = self.borders;
-        Vec2 {
-            x: u16::from(left) + u16::from(right),
-            y: u16::from(top) + u16::from(bottom),
+
+/// Sets the background to `bg` again after every SGR sequence in `out` that resets it,
+/// so that it stays the default for the content of a filled block.
+fn keep_background(out: &[u8], bg: Color) -> Vec<u8> {
+    let mut set_bg = Vec::new();
+    crossterm::queue!(set_bg, crossterm::style::SetBackgroundColor(bg))
+        .expect("writing to a Vec does not fail");
+
+    map_sgr(out, |params, res| {
+        res.extend_from_slice(format!("\x1b[{params}m").as_bytes());
+        if resets_background(params) {
+            res.extend_from_slice(&set_bg);
+        }
+    })
+}
+fn resets_background(params: &str) -> bool {
+    let mut params = params.split(';');
+    while let Some(param) = params.next() {
+        match param {
+            "" | "0" | "49" => return true,
+            // Extended colors, whose arguments are not SGR codes
+            "38" | "48" | "58" => match params.next() {
+                Some("5") => {
+                    params.next();
+                }
+                Some("2") => {
+                    params.nth(2);
+                }
+                _ => {}
+            },
+            _ => {}
         }
     }
+    false
 }
 
 impl Style {
     pub fn apply(self, d: impl std::fmt::Display) -> impl std::fmt::Display {
-        use crossterm::style::{StyledContent, Stylize};
+        use crossterm::style::{Attribute, StyledContent, Stylize};
 
         let Self {
             fg,
@@ -414,6 +927,10 @@ impl Style {
                     underline,
                     hidden,
                     strike,
+                    reverse,
+                    blink,
+                    overline,
+                    underline_style,
                     __non_exhaustive: (),
                 },
             underline_color,
@@ -431,7 +948,13 @@ impl Style {
             styled = styled.italic();
         }
         if underline {
-            styled = styled.underlined();
+            styled = styled.attribute(match underline_style {
+                UnderlineStyle::Straight => Attribute::Underlined,
+                UnderlineStyle::Double => Attribute::DoubleUnderlined,
+                UnderlineStyle::Curly => Attribute::Undercurled,
+                UnderlineStyle::Dotted => Attribute::Underdotted,
+                UnderlineStyle::Dashed => Attribute::Underdashed,
+            });
         }
         if hidden {
             styled = styled.hidden();
@@ -439,6 +962,15 @@ impl Style {
         if strike {
             styled = styled.crossed_out();
         }
+        if reverse {
+            styled = styled.reverse();
+        }
+        if blink {
+            styled = styled.slow_blink();
+        }
+        if overline {
+            styled = styled.attribute(Attribute::OverLined);
+        }
         if let Some(fg) = fg {
             styled = styled.with(fg);
         }
@@ -475,3 +1007,242 @@ impl KittyTextSize {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizing(states: &WidgetStates) -> SizingArgs {
+        SizingArgs {
+            font_size: Vec2 { x: 10, y: 20 },
+            features: TermFeatures::all(),
+            states: states.clone(),
+            theme: Theme::default(),
+        }
+    }
+
+    fn render_term(
+        elem: &Elem,
+        size: Vec2<u16>,
+        sizing: &SizingArgs,
+    ) -> (VirtualTerm, RenderedLayout) {
+        let mut buf = Vec::new();
+        let area = Area {
+            pos: Vec2::default(),
+            size,
+        };
+        let layout = render(elem, area, &mut buf, sizing, None).unwrap();
+        let mut term = VirtualTerm::new(size);
+        term.feed(&buf);
+        (term, layout)
+    }
+
+    fn render_text(
+        elem: &Elem,
+        size: Vec2<u16>,
+        states: &WidgetStates,
+    ) -> (String, RenderedLayout) {
+        let (term, layout) = render_term(elem, size, &sizing(states));
+        (term.text(), layout)
+    }
+
+    fn lines(text: &str) -> Elem {
+        PlainLines::new(text).into()
+    }
+
+    #[test]
+    fn rgb_to_ansi256_prefers_grays_for_grays() {
+        assert_eq!(rgb_to_ansi256(128, 128, 128), 244);
+        assert_eq!(rgb_to_ansi256(18, 18, 18), 233);
+        assert_eq!(rgb_to_ansi256(0, 0, 0), 16);
+        assert_eq!(rgb_to_ansi256(255, 255, 255), 231);
+    }
+
+    #[test]
+    fn rgb_to_ansi256_rounds_to_the_nearest_cube_level() {
+        assert_eq!(rgb_to_ansi256(47, 0, 255), 21);
+        assert_eq!(rgb_to_ansi256(48, 0, 255), 57);
+        assert_eq!(rgb_to_ansi256(114, 0, 255), 57);
+        assert_eq!(rgb_to_ansi256(115, 0, 255), 93);
+        assert_eq!(rgb_to_ansi256(255, 0, 0), 196);
+    }
+
+    #[test]
+    fn downgrade_true_color_replaces_rgb_colors() {
+        let out = downgrade_true_color(b"\x1b[1;38;2;0;0;0;48;2;255;255;255mx");
+        assert_eq!(out, b"\x1b[1;38;5;16;48;5;231mx");
+        // Underline colors
+        let out = downgrade_true_color(b"\x1b[4;58;2;255;0;0mx");
+        assert_eq!(out, b"\x1b[4;58;5;196mx");
+    }
+
+    #[test]
+    fn downgrade_true_color_keeps_indexed_colors() {
+        let out = downgrade_true_color(b"\x1b[38;5;196;48;5;2mx\x1b[0m");
+        assert_eq!(out, b"\x1b[38;5;196;48;5;2mx\x1b[0m");
+    }
+
+    #[test]
+    fn map_sgr_only_maps_sgr_sequences() {
+        let out = map_sgr(b"a\x1b[1mb\x1b[2;3Hc\x1b[m", |params, res| {
+            res.extend_from_slice(format!("<{params}>").as_bytes());
+        });
+        assert_eq!(out, b"a<1>b\x1b[2;3Hc<>");
+        // Unterminated sequences are kept as is
+        assert_eq!(map_sgr(b"a\x1b[1", |_, _| {}), b"a\x1b[1");
+    }
+
+    #[test]
+    fn scroll_cuts_off_items_longer_than_the_view() {
+        let scroll = Elem::scroll("list", Axis::Y, 2, [lines("a\nb\nc"), lines("d")]);
+        let states = WidgetStates::default();
+        let (text, layout) = render_text(&scroll, Vec2 { x: 2, y: 2 }, &states);
+        assert_eq!(text, "a┃\nb┃");
+        assert_eq!(layout.scroll_areas[0].max_offset, 1);
+
+        states.update("list".into(), |it: &mut ScrollOffset| it.0 = 5);
+        let (text, _) = render_text(&scroll, Vec2 { x: 2, y: 2 }, &states);
+        assert_eq!(text, "d│\n ┃");
+    }
+
+    fn pill() -> Elem {
+        Elem::build_block(|block| {
+            block.set_fill(Color::Blue);
+            block.set_caps(Caps::Round);
+            block.set_inner(lines("ab"));
+        })
+    }
+
+    fn render_raw(elem: &Elem, size: Vec2<u16>) -> String {
+        let mut buf = Vec::new();
+        let area = Area {
+            pos: Vec2::default(),
+            size,
+        };
+        render(elem, area, &mut buf, &sizing(&Default::default()), None).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn caps_span_the_height_of_the_area() {
+        assert_eq!(pill().calc_min_size(&sizing(&Default::default())).x, 4);
+        let raw = render_raw(&pill(), Vec2 { x: 6, y: 2 });
+        assert_eq!(raw.matches("\x1b]66;s=2;").count(), 2, "{raw:?}");
+    }
+
+    #[test]
+    fn caps_fall_back_to_a_glyph_per_line() {
+        // Too narrow for caps that are two cells wide
+        let raw = render_raw(&pill(), Vec2 { x: 4, y: 2 });
+        assert!(!raw.contains("\x1b]66;"), "{raw:?}");
+        let (text, _) = render_text(&pill(), Vec2 { x: 4, y: 2 }, &Default::default());
+        assert_eq!(text, "\u{e0b6}ab\u{e0b4}\n\u{e0b6}  \u{e0b4}");
+
+        // Text sizing cannot scale by more than 7
+        let raw = render_raw(&pill(), Vec2 { x: 20, y: 8 });
+        assert!(!raw.contains("\x1b]66;"), "{raw:?}");
+    }
+
+    #[test]
+    fn pills_fill_their_whole_area() {
+        let content = Elem::build_stack(Axis::X, |stack| {
+            stack.fit(
+                RawPrint::plain("A")
+                    .styled(Style {
+                        fg: Some(Color::Red),
+                        ..Default::default()
+                    })
+                    .into(),
+            );
+            stack.fit(RawPrint::plain(" B").into());
+        });
+        let pill = Elem::build_block(|block| {
+            block.set_fill(Color::Blue);
+            block.set_padding(Padding::horizontal(1));
+            block.set_caps(Caps::Round);
+            block.set_inner(content);
+        });
+        let elem = Elem::build_stack(Axis::X, |stack| stack.fit(pill));
+        let (term, _) = render_term(&elem, Vec2 { x: 10, y: 1 }, &sizing(&Default::default()));
+
+        assert_eq!(term.text(), "\u{e0b6} A B \u{e0b4}");
+        let cell = |x| term.cell(Vec2 { x, y: 0 }).unwrap().style;
+        // The caps are drawn in the fill color, outside of it
+        for x in [0, 6] {
+            assert_eq!((cell(x).fg, cell(x).bg), (Some(Color::Blue), None));
+        }
+        // Including the padding and the text after a styled part
+        for x in 1..=5 {
+            assert_eq!(cell(x).bg, Some(Color::Blue), "{x}");
+        }
+        assert_eq!(cell(2).fg, Some(Color::Red));
+        assert_eq!(cell(7).bg, None);
+    }
+
+    #[test]
+    fn spans_mix_styles_within_a_line() {
+        let mut spans = Spans::new();
+        spans
+            .push(
+                "ab",
+                Style {
+                    fg: Some(Color::Rgb { r: 255, g: 0, b: 0 }),
+                    modifier: Modifier {
+                        underline: true,
+                        underline_style: UnderlineStyle::Curly,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .push(
+                "ç",
+                Style {
+                    modifier: Modifier {
+                        reverse: true,
+                        overline: true,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            );
+        let elem = Elem::build_stack(Axis::X, |stack| {
+            stack.fit(spans.into());
+            stack.fit(RawPrint::plain("|").into());
+        });
+
+        for (features, fg) in [
+            (TermFeatures::all(), Color::Rgb { r: 255, g: 0, b: 0 }),
+            // Downgraded to the closest indexed color
+            (TermFeatures::none(), Color::AnsiValue(196)),
+        ] {
+            let sizing = SizingArgs {
+                features,
+                ..sizing(&Default::default())
+            };
+            let (term, _) = render_term(&elem, Vec2 { x: 10, y: 1 }, &sizing);
+            assert_eq!(term.text(), "abç|");
+
+            let cell = |x| term.cell(Vec2 { x, y: 0 }).unwrap().style;
+            let first = cell(1);
+            assert_eq!(first.fg, Some(fg));
+            assert!(first.modifier.underline);
+            assert_eq!(first.modifier.underline_style, UnderlineStyle::Curly);
+            let second = cell(2);
+            assert_eq!(second.fg, None);
+            assert!(second.modifier.reverse && second.modifier.overline);
+            assert!(!second.modifier.underline);
+            assert_eq!(cell(3), Style::default());
+        }
+    }
+
+    #[test]
+    fn scroll_clamps_to_the_last_items_that_fit() {
+        let scroll = Elem::scroll("list", Axis::Y, 2, ["a", "b", "c"].map(lines));
+        let states = WidgetStates::default();
+        states.update("list".into(), |it: &mut ScrollOffset| it.0 = 5);
+        let (text, layout) = render_text(&scroll, Vec2 { x: 2, y: 2 }, &states);
+        assert_eq!(text, "b┃\nc┃");
+        assert_eq!(layout.scroll_areas[0].offset, 1);
+    }
+}
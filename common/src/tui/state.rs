@@ -0,0 +1,84 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::tui::*;
+
+/// Identifies the state of a widget (see [`Elem::stateful`]) across re-renders.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WidgetKey(Arc<str>);
+impl From<&str> for WidgetKey {
+    fn from(value: &str) -> Self {
+        Self(value.into())
+    }
+}
+impl From<String> for WidgetKey {
+    fn from(value: String) -> Self {
+        Self(value.into())
+    }
+}
+
+/// The state of the widgets of a panel, by key. Clones share the same state.
+#[derive(Clone, Default)]
+pub struct WidgetStates(Arc<StatesInner>);
+#[derive(Default)]
+struct StatesInner {
+    states: Mutex<HashMap<WidgetKey, Box<dyn Any + Send>>>,
+    changed: AtomicBool,
+}
+impl fmt::Debug for WidgetStates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let states = self.0.states.lock().unwrap_or_else(|err| err.into_inner());
+        f.debug_set().entries(states.keys()).finish()
+    }
+}
+impl WidgetStates {
+    /// The state of the widget, or the default if it has none (yet).
+    pub fn get<S: Default + Clone + Send + 'static>(&self, key: &WidgetKey) -> S {
+        let states = self.0.states.lock().unwrap_or_else(|err| err.into_inner());
+        states
+            .get(key)
+            .and_then(|it| it.downcast_ref::<S>())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Changes the state of the widget, such that the panel is re-rendered.
+    pub fn update<S: Default + Send + 'static>(&self, key: WidgetKey, f: impl FnOnce(&mut S)) {
+        let mut states = self.0.states.lock().unwrap_or_else(|err| err.into_inner());
+        let state = states.entry(key).or_insert_with(|| Box::new(S::default()));
+        if !state.is::<S>() {
+            log::warn!("Replacing widget state of a different type");
+            *state = Box::new(S::default());
+        }
+        f(state.downcast_mut().expect("type was checked"));
+        self.0.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the state was updated since the last call.
+    pub fn take_changed(&self) -> bool {
+        self.0.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Elem {
+    /// An element that is built from the state of the widget with `key` whenever it is
+    /// rendered. Interaction callbacks can change the state with
+    /// [`InteractArgs::states`], which re-renders only the panel.
+    pub fn stateful<S: Default + Clone + Send + 'static>(
+        key: impl Into<WidgetKey>,
+        build: impl Fn(&S) -> Elem + 'static + Send + Sync,
+    ) -> Self {
+        let key = key.into();
+        ElemKind::Stateful(Callback::from_fn(move |states: WidgetStates| {
+            build(&states.get(&key))
+        }))
+        .into()
+    }
+}
@@ -0,0 +1,164 @@
+//! Utilities for checking what an [`Elem`] renders to, e.g. in snapshot tests.
+
+use std::{fmt::Write as _, path::Path};
+
+use crate::tui::*;
+
+/// Env var that makes [`assert_snapshot!`](crate::assert_snapshot) overwrite
+/// mismatching snapshots instead of failing.
+pub const UPDATE_SNAPSHOTS_VAR: &str = "BAR_UPDATE_SNAPSHOTS";
+
+/// Sizing with a typical font size where images are drawn as placeholders, so that
+/// they show up in the text.
+pub fn sizing() -> SizingArgs {
+    SizingArgs {
+        font_size: Vec2 { x: 10, y: 20 },
+        features: TermFeatures {
+            graphics: false,
+            text_sizing: true,
+            pixel_mouse: false,
+            true_color: true,
+        },
+        states: Default::default(),
+        theme: Theme::dark(),
+    }
+}
+
+/// The result of rendering an [`Elem`] onto a [`VirtualTerm`].
+#[derive(Debug)]
+pub struct Rendered {
+    pub screen: VirtualTerm,
+    pub layout: RenderedLayout,
+}
+
+/// Renders `elem` into `area` of a screen that is just large enough to contain it.
+pub fn render_elem(elem: &Elem, area: Area, sizing: &SizingArgs) -> std::io::Result<Rendered> {
+    let mut buf = Vec::new();
+    let layout = render(elem, area, &mut buf, sizing, None)?;
+    let mut screen = VirtualTerm::new(Vec2 {
+        x: area.pos.x.saturating_add(area.size.x),
+        y: area.pos.y.saturating_add(area.size.y),
+    });
+    screen.feed(&buf);
+    Ok(Rendered { screen, layout })
+}
+
+/// Renders `elem` at its minimum size.
+pub fn render_min(elem: &Elem, sizing: &SizingArgs) -> std::io::Result<Rendered> {
+    let size = calc_min_size(elem, sizing);
+    render_elem(
+        elem,
+        Area {
+            pos: Default::default(),
+            size,
+        },
+        sizing,
+    )
+}
+
+impl Rendered {
+    /// The text on the screen, without styles.
+    pub fn text(&self) -> String {
+        self.screen.text()
+    }
+
+    /// The text on the screen, with styles as SGR escapes.
+    pub fn ansi(&self) -> String {
+        let mut out = String::new();
+        for y in 0..self.screen.size().y {
+            if y > 0 {
+                out.push('\n');
+            }
+            let mut run = String::new();
+            let mut run_style = Style::default();
+            for x in 0..self.screen.size().x {
+                let cell = self.screen.cell(Vec2 { x, y }).expect("in bounds");
+                if cell.style != run_style {
+                    write!(out, "{}", run_style.apply(&run)).expect("writing to a String");
+                    run.clear();
+                    run_style = cell.style;
+                }
+                run.push_str(&cell.symbol);
+            }
+            let run = run.trim_end();
+            write!(out, "{}", run_style.apply(run)).expect("writing to a String");
+        }
+        out
+    }
+
+    /// The areas of interactive elements, in the order they were rendered.
+    pub fn regions(&self) -> Vec<Area> {
+        self.layout.widgets.iter().map(|&(area, _)| area).collect()
+    }
+
+    /// The text followed by a list of the interactive regions. This is what
+    /// should usually be snapshotted.
+    pub fn snapshot(&self) -> String {
+        let mut out = self.text();
+        out.push_str("\n---\n");
+        for Area { pos, size } in self.regions() {
+            writeln!(out, "({}, {}) {}x{}", pos.x, pos.y, size.x, size.y)
+                .expect("writing to a String");
+        }
+        out
+    }
+}
+
+/// Compares `actual` with the contents of `dir/name.snap`.
+///
+/// Missing snapshots are created, unless running in CI. Mismatching snapshots fail,
+/// and the new value is written to `dir/name.snap.new` for review, unless
+/// [`UPDATE_SNAPSHOTS_VAR`] is set, in which case the snapshot is overwritten.
+#[track_caller]
+pub fn check_snapshot(dir: impl AsRef<Path>, name: &str, actual: &str) {
+    let dir = dir.as_ref();
+    let path = dir.join(format!("{name}.snap"));
+    let new_path = dir.join(format!("{name}.snap.new"));
+    let write = |path: &Path| {
+        std::fs::create_dir_all(dir)
+            .and_then(|()| std::fs::write(path, actual))
+            .unwrap_or_else(|err| panic!("Failed to write snapshot {path:?}: {err}"));
+    };
+
+    let expected = match std::fs::read_to_string(&path) {
+        Ok(expected) => expected,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            if std::env::var_os("CI").is_some() {
+                write(&new_path);
+                panic!("Missing snapshot {path:?}. The new value was written to {new_path:?}");
+            }
+            write(&path);
+            eprintln!("Created snapshot {path:?}");
+            return;
+        }
+        Err(err) => panic!("Failed to read snapshot {path:?}: {err}"),
+    };
+    if expected == actual {
+        _ = std::fs::remove_file(&new_path);
+        return;
+    }
+    if std::env::var_os(UPDATE_SNAPSHOTS_VAR).is_some() {
+        write(&path);
+        eprintln!("Updated snapshot {path:?}");
+        return;
+    }
+    write(&new_path);
+    panic!(
+        "Snapshot {path:?} does not match. Set {UPDATE_SNAPSHOTS_VAR} to accept the new value.\n\
+        --- expected\n{expected}\n--- actual\n{actual}\n---"
+    );
+}
+
+/// Asserts that a value matches the snapshot `name` in the `snapshots` directory of
+/// the calling crate, see [`check_snapshot`](crate::tui::testing::check_snapshot).
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($name:expr, $actual:expr $(,)?) => {
+        $crate::tui::testing::check_snapshot(
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots"),
+            $name,
+            &$actual,
+        )
+    };
+}
+pub use crate::assert_snapshot;
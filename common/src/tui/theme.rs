@@ -0,0 +1,298 @@
+use crate::tui::*;
+
+/// Env var that selects the palette: `dark`, `light` or `system` (the default), which
+/// follows the system's preference if the bar knows it and uses `dark` otherwise.
+pub const THEME_VAR: &str = "BAR_THEME";
+/// Prefix of the env vars that override a color of both palettes, e.g.
+/// `BAR_THEME_ACCENT=#5e81ac`. See [`parse_color`] for the accepted values.
+pub const THEME_COLOR_VAR_PREFIX: &str = "BAR_THEME_";
+
+/// The colors that the bar is drawn with, by role.
+///
+/// Modules should build their elements with [`Elem::themed`] and [`Theme::style`]
+/// instead of using literal colors, so that they follow changes of the theme.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// The default color of text.
+    pub foreground: Color,
+    /// The background of the panels.
+    pub background: Color,
+    /// Highlights, e.g. the active workspace.
+    pub accent: Color,
+    /// Less important content, e.g. a slider's track.
+    pub muted: Color,
+    pub warning: Color,
+    pub critical: Color,
+    /// The borders of menus and separators.
+    pub border: Color,
+    /// The opacity of the background of menus, between 0 and 1.
+    pub menu_opacity: f32,
+}
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+/// A color of a [`Theme`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThemeRole {
+    Foreground,
+    Background,
+    Accent,
+    Muted,
+    Warning,
+    Critical,
+    Border,
+}
+impl ThemeRole {
+    pub const ALL: [Self; 7] = [
+        Self::Foreground,
+        Self::Background,
+        Self::Accent,
+        Self::Muted,
+        Self::Warning,
+        Self::Critical,
+        Self::Border,
+    ];
+
+    /// The name of the role in config, e.g. `accent`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Foreground => "foreground",
+            Self::Background => "background",
+            Self::Accent => "accent",
+            Self::Muted => "muted",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+            Self::Border => "border",
+        }
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            foreground: Color::White,
+            background: Color::Black,
+            accent: Color::Green,
+            muted: Color::DarkGrey,
+            warning: Color::Yellow,
+            critical: Color::Red,
+            border: Color::DarkGrey,
+            menu_opacity: 0.85,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            foreground: Color::Black,
+            background: Color::White,
+            accent: Color::DarkGreen,
+            muted: Color::DarkGrey,
+            warning: Color::DarkYellow,
+            critical: Color::DarkRed,
+            border: Color::DarkGrey,
+            menu_opacity: 0.9,
+        }
+    }
+
+    pub fn color(&self, role: ThemeRole) -> Color {
+        match role {
+            ThemeRole::Foreground => self.foreground,
+            ThemeRole::Background => self.background,
+            ThemeRole::Accent => self.accent,
+            ThemeRole::Muted => self.muted,
+            ThemeRole::Warning => self.warning,
+            ThemeRole::Critical => self.critical,
+            ThemeRole::Border => self.border,
+        }
+    }
+    fn color_mut(&mut self, role: ThemeRole) -> &mut Color {
+        match role {
+            ThemeRole::Foreground => &mut self.foreground,
+            ThemeRole::Background => &mut self.background,
+            ThemeRole::Accent => &mut self.accent,
+            ThemeRole::Muted => &mut self.muted,
+            ThemeRole::Warning => &mut self.warning,
+            ThemeRole::Critical => &mut self.critical,
+            ThemeRole::Border => &mut self.border,
+        }
+    }
+
+    /// A style with the color of the role, as the background for
+    /// [`ThemeRole::Background`] and as the foreground otherwise.
+    pub fn style(&self, role: ThemeRole) -> Style {
+        let color = Some(self.color(role));
+        match role {
+            ThemeRole::Background => Style {
+                bg: color,
+                ..Default::default()
+            },
+            _ => Style {
+                fg: color,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// A light or dark palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    Dark,
+    Light,
+}
+
+/// The palettes to choose from and how to choose.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThemeConfig {
+    /// The palette to use, or `None` to follow the system's preference.
+    pub scheme: Option<ColorScheme>,
+    pub dark: Theme,
+    pub light: Theme,
+}
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            scheme: None,
+            dark: Theme::dark(),
+            light: Theme::light(),
+        }
+    }
+}
+impl ThemeConfig {
+    /// Reads the config from [`THEME_VAR`] and the env vars starting with
+    /// [`THEME_COLOR_VAR_PREFIX`]. Invalid values are logged and ignored.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        match std::env::var(THEME_VAR).as_deref() {
+            Err(_) | Ok("" | "system" | "auto") => {}
+            Ok("dark") => config.scheme = Some(ColorScheme::Dark),
+            Ok("light") => config.scheme = Some(ColorScheme::Light),
+            Ok(other) => log::error!("Unknown {THEME_VAR} {other:?}, using system"),
+        }
+        for role in ThemeRole::ALL {
+            let var = format!("{THEME_COLOR_VAR_PREFIX}{}", role.name().to_uppercase());
+            let Ok(val) = std::env::var(&var) else {
+                continue;
+            };
+            match parse_color(&val) {
+                Some(color) => {
+                    *config.dark.color_mut(role) = color;
+                    *config.light.color_mut(role) = color;
+                }
+                None => log::error!("Invalid color {val:?} in {var}"),
+            }
+        }
+        config
+    }
+
+    /// The theme to use, given the system's preference if it is known.
+    pub fn theme(&self, system: Option<ColorScheme>) -> Theme {
+        match self.scheme.or(system).unwrap_or(ColorScheme::Dark) {
+            ColorScheme::Dark => self.dark,
+            ColorScheme::Light => self.light,
+        }
+    }
+}
+
+/// Parses a color name like `dark_grey` or `green`, an ANSI color index like `208`
+/// or a hex color like `#5e81ac`.
+pub fn parse_color(s: &str) -> Option<Color> {
+    let s = s.trim();
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return Some(Color::Rgb {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        });
+    }
+    if let Ok(n) = s.parse::<u8>() {
+        return Some(Color::AnsiValue(n));
+    }
+    Color::try_from(s).ok()
+}
+
+/// The RGB value of a color, assuming kitty's default palette for the ANSI colors.
+/// `None` for [`Color::Reset`].
+pub fn color_to_rgb(color: Color) -> Option<[u8; 3]> {
+    const ANSI: [[u8; 3]; 16] = [
+        [0x00, 0x00, 0x00],
+        [0xcc, 0x04, 0x03],
+        [0x19, 0xcb, 0x00],
+        [0xce, 0xcb, 0x00],
+        [0x0d, 0x73, 0xcc],
+        [0xcb, 0x1e, 0xd1],
+        [0x0d, 0xcd, 0xcd],
+        [0xdd, 0xdd, 0xdd],
+        [0x76, 0x76, 0x76],
+        [0xf2, 0x20, 0x1f],
+        [0x23, 0xfd, 0x00],
+        [0xff, 0xfd, 0x00],
+        [0x1a, 0x8f, 0xff],
+        [0xfd, 0x28, 0xff],
+        [0x14, 0xff, 0xff],
+        [0xff, 0xff, 0xff],
+    ];
+    let index = match color {
+        Color::Reset => return None,
+        Color::Rgb { r, g, b } => return Some([r, g, b]),
+        Color::Black => 0,
+        Color::DarkRed => 1,
+        Color::DarkGreen => 2,
+        Color::DarkYellow => 3,
+        Color::DarkBlue => 4,
+        Color::DarkMagenta => 5,
+        Color::DarkCyan => 6,
+        Color::Grey => 7,
+        Color::DarkGrey => 8,
+        Color::Red => 9,
+        Color::Green => 10,
+        Color::Yellow => 11,
+        Color::Blue => 12,
+        Color::Magenta => 13,
+        Color::Cyan => 14,
+        Color::White => 15,
+        Color::AnsiValue(n) => n,
+    };
+    Some(match index {
+        0..16 => ANSI[usize::from(index)],
+        // The 6x6x6 color cube
+        16..232 => {
+            let level = |it: u8| if it == 0 { 0 } else { 55 + it * 40 };
+            let n = index - 16;
+            [level(n / 36), level(n / 6 % 6), level(n % 6)]
+        }
+        // The grayscale ramp
+        232.. => [8 + (index - 232) * 10; 3],
+    })
+}
+
+/// Sets the default foreground and background color of the terminal to those of the
+/// theme, using OSC 10 and 11.
+#[derive(Debug, Clone, Copy)]
+pub struct SetThemeColors(pub Theme);
+impl crossterm::Command for SetThemeColors {
+    fn write_ansi(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        for (osc, color) in [(10, self.0.foreground), (11, self.0.background)] {
+            match color_to_rgb(color) {
+                Some([r, g, b]) => write!(f, "\x1b]{osc};#{r:02x}{g:02x}{b:02x}\x1b\\")?,
+                // Resets to the color from the config
+                None => write!(f, "\x1b]1{osc}\x1b\\")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Elem {
+    /// An element that is built from the current theme whenever it is rendered.
+    pub fn themed(build: impl Fn(&Theme) -> Elem + 'static + Send + Sync) -> Self {
+        ElemKind::Themed(Callback::from_fn(move |theme: Theme| build(&theme))).into()
+    }
+}
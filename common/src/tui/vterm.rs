@@ -0,0 +1,549 @@
+use crate::tui::*;
+
+/// A minimal terminal emulator that understands the output of [`render`], e.g. for tests
+/// that cannot run a real terminal.
+///
+/// Text past the right edge is clipped instead of wrapped, and sized text (OSC 66) is
+/// only as tall as a single line.
+#[derive(Debug, Clone)]
+pub struct VirtualTerm {
+    size: Vec2<u16>,
+    cells: Vec<Cell>,
+    cursor: Vec2<u16>,
+    style: Style,
+    images: Vec<ImagePlacement>,
+    /// The start of an escape sequence that was cut off at the end of the last [`Self::feed`].
+    pending: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cell {
+    /// Empty if the cell is covered by a wide character to its left.
+    pub symbol: String,
+    pub style: Style,
+}
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: " ".into(),
+            style: Default::default(),
+        }
+    }
+}
+
+/// An image that was displayed using the kitty graphics protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImagePlacement {
+    pub pos: Vec2<u16>,
+    /// The size of the image data in pixels.
+    pub pix_size: Vec2<u32>,
+    /// The number of columns the image was scaled to, if specified.
+    pub columns: Option<u16>,
+    /// The number of rows the image was scaled to, if specified.
+    pub rows: Option<u16>,
+}
+
+impl VirtualTerm {
+    pub fn new(size: Vec2<u16>) -> Self {
+        Self {
+            size,
+            cells: vec![Cell::default(); usize::from(size.x) * usize::from(size.y)],
+            cursor: Default::default(),
+            style: Default::default(),
+            images: Default::default(),
+            pending: Default::default(),
+        }
+    }
+
+    pub fn size(&self) -> Vec2<u16> {
+        self.size
+    }
+
+    /// Changes the size, clearing the screen.
+    pub fn resize(&mut self, size: Vec2<u16>) {
+        *self = Self::new(size);
+    }
+
+    pub fn cell(&self, pos: Vec2<u16>) -> Option<&Cell> {
+        self.index(pos).map(|i| &self.cells[i])
+    }
+
+    pub fn images(&self) -> &[ImagePlacement] {
+        &self.images
+    }
+
+    /// The text of line `y` without trailing whitespace.
+    pub fn line(&self, y: u16) -> String {
+        let mut line = String::new();
+        for x in 0..self.size.x {
+            line.push_str(&self.cells[self.index(Vec2 { x, y }).unwrap()].symbol);
+        }
+        line.truncate(line.trim_end().len());
+        line
+    }
+
+    /// The text of all lines, without trailing whitespace.
+    pub fn text(&self) -> String {
+        (0..self.size.y)
+            .map(|y| self.line(y))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The position of the first cell where `needle` starts.
+    pub fn find(&self, needle: &str) -> Option<Vec2<u16>> {
+        (0..self.size.y).find_map(|y| {
+            let mut line = String::new();
+            let mut starts = Vec::new();
+            for x in 0..self.size.x {
+                let symbol = &self.cells[self.index(Vec2 { x, y }).unwrap()].symbol;
+                if !symbol.is_empty() {
+                    starts.push((line.len(), x));
+                }
+                line.push_str(symbol);
+            }
+            let offset = line.find(needle)?;
+            let &(_, x) = starts.iter().find(|&&(start, _)| start == offset)?;
+            Some(Vec2 { x, y })
+        })
+    }
+
+    fn index(&self, Vec2 { x, y }: Vec2<u16>) -> Option<usize> {
+        (x < self.size.x && y < self.size.y)
+            .then(|| usize::from(y) * usize::from(self.size.x) + usize::from(x))
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut buf = std::mem::take(&mut self.pending);
+        buf.extend_from_slice(bytes);
+
+        let mut rest = &buf[..];
+        while !rest.is_empty() {
+            match self.feed_one(rest) {
+                Some(len) => rest = &rest[len..],
+                None => {
+                    self.pending = rest.to_vec();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Handles a single character or escape sequence and returns its length,
+    /// or `None` if it is incomplete.
+    fn feed_one(&mut self, bytes: &[u8]) -> Option<usize> {
+        match bytes {
+            [0x1b, b'[', rest @ ..] => {
+                let end = rest.iter().position(|b| (0x40..=0x7e).contains(b))?;
+                let params = String::from_utf8_lossy(&rest[..end]);
+                self.csi(&params, rest[end]);
+                Some(2 + end + 1)
+            }
+            [0x1b, b']', rest @ ..] => {
+                let (end, term_len) = find_string_terminator(rest, true)?;
+                self.osc(&String::from_utf8_lossy(&rest[..end]));
+                Some(2 + end + term_len)
+            }
+            [0x1b, b'_', rest @ ..] => {
+                let (end, term_len) = find_string_terminator(rest, false)?;
+                self.apc(&String::from_utf8_lossy(&rest[..end]));
+                Some(2 + end + term_len)
+            }
+            [0x1b] => None,
+            [0x1b, _, ..] => Some(2),
+            [b'\r', ..] => {
+                self.cursor.x = 0;
+                Some(1)
+            }
+            [b'\n', ..] => {
+                self.cursor.y = self.cursor.y.saturating_add(1);
+                Some(1)
+            }
+            [b, ..] if b.is_ascii_control() => Some(1),
+            _ => {
+                let len = utf8_char_len(bytes[0]);
+                match std::str::from_utf8(bytes.get(..len)?) {
+                    Ok(ch) => self.print(ch, None),
+                    Err(_) => {
+                        self.print(char::REPLACEMENT_CHARACTER.encode_utf8(&mut [0; 4]), None)
+                    }
+                }
+                Some(len)
+            }
+        }
+    }
+
+    /// Prints `text` into a single (possibly wide) cell. The width is taken from
+    /// the text if not specified.
+    fn print(&mut self, text: &str, width: Option<u16>) {
+        let width = width.unwrap_or_else(|| {
+            unicode_width::UnicodeWidthStr::width(text)
+                .try_into()
+                .unwrap_or(u16::MAX)
+        });
+        if width == 0 {
+            // Combining characters are attached to the previous cell
+            if let Some(x) = self.cursor.x.checked_sub(1)
+                && let Some(i) = self.index(Vec2 { x, ..self.cursor })
+            {
+                self.cells[i].symbol.push_str(text);
+            }
+            return;
+        }
+
+        let Some(i) = self.index(self.cursor) else {
+            return;
+        };
+        self.cells[i] = Cell {
+            symbol: text.into(),
+            style: self.style,
+        };
+        for dx in 1..width {
+            let pos = Vec2 {
+                x: self.cursor.x.saturating_add(dx),
+                ..self.cursor
+            };
+            if let Some(i) = self.index(pos) {
+                self.cells[i] = Cell {
+                    symbol: String::new(),
+                    style: self.style,
+                };
+            }
+        }
+        self.cursor.x = self.cursor.x.saturating_add(width);
+    }
+
+    fn clear(&mut self, from: usize) {
+        let from = from.min(self.cells.len());
+        for cell in &mut self.cells[from..] {
+            *cell = Cell::default();
+        }
+    }
+
+    fn csi(&mut self, params: &str, fin: u8) {
+        // Private modes like synchronized updates do not affect the contents
+        if params.starts_with('?') {
+            return;
+        }
+        let mut nums = params
+            .split(';')
+            .map(|it| it.parse::<u16>().ok().filter(|&n| n > 0));
+        match fin {
+            b'H' | b'f' => {
+                let y = nums.next().flatten().unwrap_or(1);
+                let x = nums.next().flatten().unwrap_or(1);
+                self.cursor = Vec2 { x: x - 1, y: y - 1 };
+            }
+            b'J' => match params {
+                "2" | "3" => {
+                    self.clear(0);
+                    // Like kitty, clearing the screen also removes images on it
+                    self.images.clear();
+                }
+                _ => {
+                    let from = self.index(self.cursor).unwrap_or(self.cells.len());
+                    self.clear(from);
+                }
+            },
+            b'K' => {
+                if let Some(from) = self.index(self.cursor) {
+                    let to = from + usize::from(self.size.x - self.cursor.x);
+                    for cell in &mut self.cells[from..to] {
+                        *cell = Cell::default();
+                    }
+                }
+            }
+            b'm' => self.sgr(params),
+            _ => log::trace!("Ignoring CSI {params:?} {:?}", char::from(fin)),
+        }
+    }
+
+    fn sgr(&mut self, params: &str) {
+        let parse = |it: &str| it.parse::<u8>().unwrap_or(0);
+        // Parameters are separated by `;`, their sub-parameters by `:`
+        let mut groups = params.split(';');
+        while let Some(group) = groups.next() {
+            let mut subparams = group.split(':').map(parse);
+            let param = subparams.next().unwrap_or(0);
+            let has_subparams = group.contains(':');
+            // Extended colors take their arguments from the following parameters
+            // unless they use sub-parameters
+            let mut color_args = |subparams: &mut dyn Iterator<Item = u8>| {
+                if has_subparams {
+                    // The color space of `38:2:<space>:r:g:b` is usually left empty
+                    let args: Vec<u8> = subparams.collect();
+                    let args = match args.as_slice() {
+                        [2, _, r, g, b] => vec![2, *r, *g, *b],
+                        other => other.to_vec(),
+                    };
+                    extended_color(&mut args.into_iter())
+                } else {
+                    extended_color(&mut (&mut groups).map(parse))
+                }
+            };
+            let style = &mut self.style;
+            match param {
+                0 => *style = Default::default(),
+                1 => style.modifier.bold = true,
+                2 => style.modifier.dim = true,
+                3 => style.modifier.italic = true,
+                4 => {
+                    let underline_style = match subparams.next() {
+                        None | Some(1) => Some(UnderlineStyle::Straight),
+                        Some(2) => Some(UnderlineStyle::Double),
+                        Some(3) => Some(UnderlineStyle::Curly),
+                        Some(4) => Some(UnderlineStyle::Dotted),
+                        Some(5) => Some(UnderlineStyle::Dashed),
+                        Some(_) => None,
+                    };
+                    style.modifier.underline = underline_style.is_some();
+                    style.modifier.underline_style = underline_style.unwrap_or_default();
+                }
+                5 | 6 => style.modifier.blink = true,
+                7 => style.modifier.reverse = true,
+                8 => style.modifier.hidden = true,
+                9 => style.modifier.strike = true,
+                21 => {
+                    style.modifier.underline = true;
+                    style.modifier.underline_style = UnderlineStyle::Double;
+                }
+                22 => {
+                    style.modifier.bold = false;
+                    style.modifier.dim = false;
+                }
+                23 => style.modifier.italic = false,
+                24 => style.modifier.underline = false,
+                25 => style.modifier.blink = false,
+                27 => style.modifier.reverse = false,
+                28 => style.modifier.hidden = false,
+                29 => style.modifier.strike = false,
+                53 => style.modifier.overline = true,
+                55 => style.modifier.overline = false,
+                30..=37 => style.fg = Some(ansi_color(param - 30)),
+                38 => style.fg = color_args(&mut subparams),
+                39 => style.fg = None,
+                40..=47 => style.bg = Some(ansi_color(param - 40)),
+                48 => style.bg = color_args(&mut subparams),
+                49 => style.bg = None,
+                58 => style.underline_color = color_args(&mut subparams),
+                59 => style.underline_color = None,
+                90..=97 => style.fg = Some(ansi_color(param - 90 + 8)),
+                100..=107 => style.bg = Some(ansi_color(param - 100 + 8)),
+                _ => log::trace!("Ignoring SGR {param}"),
+            }
+        }
+    }
+
+    fn osc(&mut self, body: &str) {
+        // https://sw.kovidgoyal.net/kitty/text-sizing-protocol/
+        let Some(("66", rest)) = body.split_once(';') else {
+            log::trace!("Ignoring OSC {body:?}");
+            return;
+        };
+        let (meta, text) = rest.split_once(';').unwrap_or(("", rest));
+        let mut scale = 1;
+        let mut width = None;
+        for (key, value) in meta.split(':').filter_map(|it| it.split_once('=')) {
+            match (key, value.parse::<u16>()) {
+                ("s", Ok(s)) => scale = s.max(1),
+                ("w", Ok(w)) if w > 0 => width = Some(w),
+                _ => {}
+            }
+        }
+        let width = width.unwrap_or_else(|| {
+            let w: u16 = unicode_width::UnicodeWidthStr::width(text)
+                .try_into()
+                .unwrap_or(u16::MAX);
+            w.saturating_mul(scale)
+        });
+        self.print(text, Some(width));
+    }
+
+    fn apc(&mut self, body: &str) {
+        // https://sw.kovidgoyal.net/kitty/graphics-protocol/#control-data-reference
+        let Some(control) = body.strip_prefix('G') else {
+            log::trace!("Ignoring APC");
+            return;
+        };
+        let control = control.split_once(';').map_or(control, |(it, _)| it);
+
+        let mut placement = ImagePlacement {
+            pos: self.cursor,
+            pix_size: Default::default(),
+            columns: None,
+            rows: None,
+        };
+        let mut display = false;
+        for (key, value) in control.split(',').filter_map(|it| it.split_once('=')) {
+            match key {
+                "a" => display = matches!(value, "T" | "p"),
+                "s" => placement.pix_size.x = value.parse().unwrap_or(0),
+                "v" => placement.pix_size.y = value.parse().unwrap_or(0),
+                "c" => placement.columns = value.parse().ok(),
+                "r" => placement.rows = value.parse().ok(),
+                _ => {}
+            }
+        }
+        if display {
+            self.images.push(placement);
+        }
+    }
+}
+
+/// Finds BEL (if allowed) or ST, returning the position and length of the terminator.
+fn find_string_terminator(bytes: &[u8], allow_bel: bool) -> Option<(usize, usize)> {
+    bytes.iter().enumerate().find_map(|(i, &b)| match b {
+        0x07 if allow_bel => Some((i, 1)),
+        0x1b if bytes.get(i + 1) == Some(&b'\\') => Some((i, 2)),
+        _ => None,
+    })
+}
+
+fn utf8_char_len(first: u8) -> usize {
+    match first {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    }
+}
+
+/// The inverse of how crossterm writes [`Color`]s.
+fn ansi_color(n: u8) -> Color {
+    match n {
+        0 => Color::Black,
+        1 => Color::DarkRed,
+        2 => Color::DarkGreen,
+        3 => Color::DarkYellow,
+        4 => Color::DarkBlue,
+        5 => Color::DarkMagenta,
+        6 => Color::DarkCyan,
+        7 => Color::Grey,
+        8 => Color::DarkGrey,
+        9 => Color::Red,
+        10 => Color::Green,
+        11 => Color::Yellow,
+        12 => Color::Blue,
+        13 => Color::Magenta,
+        14 => Color::Cyan,
+        15 => Color::White,
+        n => Color::AnsiValue(n),
+    }
+}
+
+fn extended_color(params: &mut impl Iterator<Item = u8>) -> Option<Color> {
+    match params.next()? {
+        5 => Some(ansi_color(params.next()?)),
+        2 => Some(Color::Rgb {
+            r: params.next()?,
+            g: params.next()?,
+            b: params.next()?,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(x: u16, y: u16, bytes: &[u8]) -> VirtualTerm {
+        let mut term = VirtualTerm::new(Vec2 { x, y });
+        term.feed(bytes);
+        term
+    }
+
+    fn style_at(term: &VirtualTerm, x: u16) -> Style {
+        term.cell(Vec2 { x, y: 0 }).unwrap().style
+    }
+
+    #[test]
+    fn moves_the_cursor_and_clips_long_lines() {
+        let term = term(4, 3, b"abcdef\r\nx\x1b[3;2Hy");
+        assert_eq!(term.text(), "abcd\nx\n y");
+    }
+
+    #[test]
+    fn wide_and_combining_characters() {
+        let term = term(6, 1, "a界e\u{301}b".as_bytes());
+        assert_eq!(term.text(), "a界e\u{301}b");
+        assert_eq!(term.cell(Vec2 { x: 2, y: 0 }).unwrap().symbol, "");
+        assert_eq!(term.find("e\u{301}b"), Some(Vec2 { x: 3, y: 0 }));
+        assert_eq!(term.find("b"), Some(Vec2 { x: 4, y: 0 }));
+    }
+
+    #[test]
+    fn clears_lines_and_the_screen() {
+        let mut term = term(4, 2, b"abcd\r\nefgh\x1b[1;3H\x1b[K");
+        assert_eq!(term.text(), "ab\nefgh");
+        term.feed(b"\x1b[2;2H\x1b[J");
+        assert_eq!(term.text(), "ab\ne");
+
+        term.feed(b"\x1b_Ga=T,s=1,v=1;AAAA\x1b\\");
+        assert_eq!(term.images().len(), 1);
+        term.feed(b"\x1b[2J");
+        assert_eq!(term.text(), "\n");
+        assert!(term.images().is_empty());
+    }
+
+    #[test]
+    fn escapes_may_be_split_across_feeds() {
+        let mut term = term(4, 1, b"a\x1b[3");
+        term.feed(b"1mb\xe7");
+        term.feed(b"\x95\x8c");
+        assert_eq!(term.text(), "ab界");
+        assert_eq!(style_at(&term, 1).fg, Some(Color::DarkRed));
+    }
+
+    #[test]
+    fn sgr_sets_and_resets_styles() {
+        let term = term(
+            5,
+            1,
+            b"\x1b[1;4:3;38:2::1:2:3;58;5;196ma\x1b[22;24;48;5;2mb\x1b[0;21;7mc\x1b[mx",
+        );
+        let a = style_at(&term, 0);
+        assert!(a.modifier.bold && a.modifier.underline);
+        assert_eq!(a.modifier.underline_style, UnderlineStyle::Curly);
+        assert_eq!(a.fg, Some(Color::Rgb { r: 1, g: 2, b: 3 }));
+        assert_eq!(a.underline_color, Some(Color::AnsiValue(196)));
+
+        let b = style_at(&term, 1);
+        assert!(!b.modifier.bold && !b.modifier.underline);
+        assert_eq!(b.fg, Some(Color::Rgb { r: 1, g: 2, b: 3 }));
+        assert_eq!(b.bg, Some(Color::DarkGreen));
+
+        let c = style_at(&term, 2);
+        assert_eq!((c.fg, c.bg), (None, None));
+        assert!(c.modifier.reverse && c.modifier.underline);
+        assert_eq!(c.modifier.underline_style, UnderlineStyle::Double);
+        assert_eq!(style_at(&term, 3), Style::default());
+    }
+
+    #[test]
+    fn sized_text_takes_its_width() {
+        let term = term(10, 1, b"\x1b]66;s=2;ab\x07|\x1b]66;w=3;c\x1b\\|");
+        assert_eq!(term.text(), "ab|c|");
+        assert_eq!(term.find("|c"), Some(Vec2 { x: 4, y: 0 }));
+        assert_eq!(term.find("|"), Some(Vec2 { x: 4, y: 0 }));
+        assert_eq!(term.find("c|"), Some(Vec2 { x: 5, y: 0 }));
+    }
+
+    #[test]
+    fn records_displayed_images() {
+        let term = term(
+            8,
+            2,
+            b"\x1b[2;3H\x1b_Ga=T,f=32,s=20,v=10,c=2,r=1;AAAA\x1b\\\x1b_Ga=t,s=1,v=1;AAAA\x1b\\",
+        );
+        assert_eq!(
+            term.images(),
+            [ImagePlacement {
+                pos: Vec2 { x: 2, y: 1 },
+                pix_size: Vec2 { x: 20, y: 10 },
+                columns: Some(2),
+                rows: Some(1),
+            }]
+        );
+    }
+}
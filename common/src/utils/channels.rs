@@ -37,3 +37,229 @@ pub fn unb_chan<T>() -> (UnbTx<T>, UnbRx<T>) {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
     (tx, rx.into())
 }
+
+/// Error returned by [`FrameTx`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSendError {
+    /// There are too many queued items that are not frames.
+    Full,
+    Closed,
+}
+impl std::fmt::Display for FrameSendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Full => f.write_str("Channel is full"),
+            Self::Closed => f.write_str("Channel is closed"),
+        }
+    }
+}
+impl std::error::Error for FrameSendError {}
+
+struct FrameQueue<T> {
+    /// Items in order, tagged by whether they are frames.
+    items: std::collections::VecDeque<(bool, T)>,
+    senders: usize,
+    closed: bool,
+}
+struct FrameShared<T> {
+    queue: std::sync::Mutex<FrameQueue<T>>,
+    notify: tokio::sync::Notify,
+    capacity: usize,
+    dropped_frames: std::sync::atomic::AtomicU64,
+}
+impl<T> FrameShared<T> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, FrameQueue<T>> {
+        self.queue.lock().unwrap_or_else(|it| it.into_inner())
+    }
+}
+
+/// Sending half of [`frame_chan`].
+pub struct FrameTx<T> {
+    shared: std::sync::Arc<FrameShared<T>>,
+}
+/// Receiving half of [`frame_chan`].
+pub struct FrameRx<T> {
+    shared: std::sync::Arc<FrameShared<T>>,
+}
+
+/// A bounded channel where a newly sent frame replaces all frames that have not been
+/// received yet. Other items keep their order relative to the frames.
+pub fn frame_chan<T>(capacity: usize) -> (FrameTx<T>, FrameRx<T>) {
+    let shared = std::sync::Arc::new(FrameShared {
+        queue: std::sync::Mutex::new(FrameQueue {
+            items: Default::default(),
+            senders: 1,
+            closed: false,
+        }),
+        notify: Default::default(),
+        capacity,
+        dropped_frames: Default::default(),
+    });
+    (
+        FrameTx {
+            shared: shared.clone(),
+        },
+        FrameRx { shared },
+    )
+}
+
+impl<T> FrameTx<T> {
+    fn push(&self, is_frame: bool, item: T) -> Result<(), FrameSendError> {
+        let mut queue = self.shared.lock();
+        if queue.closed {
+            return Err(FrameSendError::Closed);
+        }
+        if is_frame {
+            let len = queue.items.len();
+            queue.items.retain(|&(is_frame, _)| !is_frame);
+            let dropped = len - queue.items.len();
+            self.shared
+                .dropped_frames
+                .fetch_add(dropped as u64, std::sync::atomic::Ordering::Relaxed);
+        }
+        if queue.items.len() >= self.shared.capacity {
+            return Err(FrameSendError::Full);
+        }
+        queue.items.push_back((is_frame, item));
+        drop(queue);
+        self.shared.notify.notify_one();
+        Ok(())
+    }
+
+    pub fn send(&self, item: T) -> Result<(), FrameSendError> {
+        self.push(false, item)
+    }
+
+    /// Sends a frame, dropping any frames that are still queued.
+    pub fn send_frame(&self, frame: T) -> Result<(), FrameSendError> {
+        self.push(true, frame)
+    }
+
+    /// The number of frames that were replaced before being received.
+    pub fn dropped_frames(&self) -> u64 {
+        self.shared
+            .dropped_frames
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+impl<T> Clone for FrameTx<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+impl<T> Drop for FrameTx<T> {
+    fn drop(&mut self) {
+        let mut queue = self.shared.lock();
+        queue.senders -= 1;
+        if queue.senders == 0 {
+            drop(queue);
+            self.shared.notify.notify_one();
+        }
+    }
+}
+
+impl<T> FrameRx<T> {
+    /// Returns `None` once all senders are gone and the queue is empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut queue = self.shared.lock();
+                if let Some((_, item)) = queue.items.pop_front() {
+                    return Some(item);
+                }
+                if queue.senders == 0 {
+                    return None;
+                }
+            }
+            self.shared.notify.notified().await;
+        }
+    }
+
+    pub fn into_stream(self) -> impl futures::Stream<Item = T> {
+        futures::stream::unfold(self, async |mut rx| rx.recv().await.map(|it| (it, rx)))
+    }
+
+    /// The number of frames that were replaced before being received.
+    pub fn dropped_frames(&self) -> u64 {
+        self.shared
+            .dropped_frames
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+impl<T> Drop for FrameRx<T> {
+    fn drop(&mut self) {
+        let mut queue = self.shared.lock();
+        queue.closed = true;
+        queue.items.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn frames_replace_queued_frames() {
+        let (tx, mut rx) = frame_chan(8);
+        tx.send_frame("frame 1").unwrap();
+        tx.send("print 1").unwrap();
+        tx.send_frame("frame 2").unwrap();
+        tx.send("print 2").unwrap();
+        tx.send_frame("frame 3").unwrap();
+        drop(tx);
+
+        let mut received = Vec::new();
+        while let Some(item) = rx.recv().await {
+            received.push(item);
+        }
+        assert_eq!(received, ["print 1", "print 2", "frame 3"]);
+        assert_eq!(rx.dropped_frames(), 2);
+    }
+
+    #[tokio::test]
+    async fn received_frames_are_not_dropped() {
+        let (tx, mut rx) = frame_chan(8);
+        tx.send_frame(1).unwrap();
+        assert_eq!(rx.recv().await, Some(1));
+        tx.send_frame(2).unwrap();
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(tx.dropped_frames(), 0);
+    }
+
+    #[test]
+    fn capacity_bounds_the_queue() {
+        let (tx, _rx) = frame_chan(2);
+        tx.send(1).unwrap();
+        tx.send_frame(2).unwrap();
+        assert_eq!(tx.send(3), Err(FrameSendError::Full));
+        // Replaces the queued frame, so it fits
+        tx.send_frame(4).unwrap();
+        assert_eq!(tx.dropped_frames(), 1);
+
+        let (tx, _rx) = frame_chan(2);
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        assert_eq!(tx.send_frame(3), Err(FrameSendError::Full));
+    }
+
+    #[tokio::test]
+    async fn closes_with_either_side() {
+        let (tx, rx) = frame_chan(2);
+        drop(rx);
+        assert_eq!(tx.send(1), Err(FrameSendError::Closed));
+        assert_eq!(tx.send_frame(2), Err(FrameSendError::Closed));
+
+        let (tx, mut rx) = frame_chan(2);
+        let tx2 = tx.clone();
+        tx.send(1).unwrap();
+        drop(tx);
+        let recv = tokio::spawn(async move { [rx.recv().await, rx.recv().await, rx.recv().await] });
+        tx2.send(2).unwrap();
+        drop(tx2);
+        // Queued items are still received after the senders are gone
+        assert_eq!(recv.await.unwrap(), [Some(1), Some(2), None]);
+    }
+}
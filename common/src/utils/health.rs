@@ -0,0 +1,136 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::utils::{Backoff, ReloadRx, RetryPolicy, WatchRx, WatchTx};
+
+/// The status of a module or client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    Starting,
+    Running,
+    /// Failed with the given error and is being restarted.
+    Degraded(Arc<str>),
+    /// Failed with the given error and will not be restarted until the next reload.
+    Failed(Arc<str>),
+}
+impl Health {
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            Self::Starting | Self::Running => None,
+            Self::Degraded(err) | Self::Failed(err) => Some(err),
+        }
+    }
+}
+
+pub type HealthMap = BTreeMap<Arc<str>, Health>;
+
+/// Collects the [`Health`] of all modules and clients on a watch channel.
+#[derive(Debug, Clone)]
+pub struct HealthTx {
+    tx: WatchTx<HealthMap>,
+}
+impl HealthTx {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            tx: WatchTx::new(HealthMap::new()),
+        }
+    }
+    pub fn subscribe(&self) -> WatchRx<HealthMap> {
+        self.tx.subscribe()
+    }
+
+    /// Registers a component under `name`, which is shown to the user, as starting.
+    pub fn reporter(&self, name: impl Into<Arc<str>>) -> HealthReporter {
+        let reporter = HealthReporter {
+            name: name.into(),
+            tx: self.tx.clone(),
+        };
+        reporter.report(Health::Starting);
+        reporter
+    }
+}
+
+/// Reports the [`Health`] of a single component to a [`HealthTx`].
+#[derive(Debug, Clone)]
+pub struct HealthReporter {
+    name: Arc<str>,
+    tx: WatchTx<HealthMap>,
+}
+impl HealthReporter {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn report(&self, health: Health) {
+        self.tx.send_if_modified(|map| {
+            if map.get(&self.name) == Some(&health) {
+                return false;
+            }
+            map.insert(self.name.clone(), health);
+            true
+        });
+    }
+    pub fn running(&self) {
+        self.report(Health::Running);
+    }
+    pub fn degraded(&self, err: &anyhow::Error) {
+        self.report(Health::Degraded(format!("{err:#}").into()));
+    }
+    pub fn failed(&self, err: &anyhow::Error) {
+        self.report(Health::Failed(format!("{err:#}").into()));
+    }
+}
+
+/// Runs `f` until it returns `Ok`, restarting it according to `policy` and reporting
+/// its status to `health`. `f` should report [`Health::Running`] once it is up.
+///
+/// A reload skips the current delay. If the policy gives up, the next reload starts
+/// over, or this returns if there is no `reload_rx`.
+pub async fn supervise<A>(
+    health: &HealthReporter,
+    policy: RetryPolicy,
+    mut reload_rx: Option<&mut ReloadRx>,
+    mut args: A,
+    mut f: impl AsyncFnMut(&mut A, &HealthReporter) -> anyhow::Result<()>,
+) {
+    let mut backoff = Backoff::new(policy);
+    loop {
+        health.report(Health::Starting);
+        backoff.start_attempt();
+        let Err(err) = f(&mut args, health).await else {
+            return;
+        };
+
+        let Some(delay) = backoff.next_delay() else {
+            log::error!(
+                "{} has failed. Giving up after {} attempts: {err:?}",
+                health.name(),
+                backoff.failures(),
+            );
+            health.failed(&err);
+
+            let Some(reload_rx) = reload_rx.as_deref_mut() else {
+                return;
+            };
+            if reload_rx.wait().await.is_none() {
+                return;
+            }
+            backoff = Backoff::new(policy);
+            continue;
+        };
+        log::error!(
+            "{} has failed. Restarting in {:.1}s: {err:?}",
+            health.name(),
+            delay.as_secs_f64(),
+        );
+        health.degraded(&err);
+
+        tokio::select! {
+            () = tokio::time::sleep(delay) => {}
+            Some(()) = async {
+                let reload_rx = reload_rx.as_deref_mut()?;
+                reload_rx.wait().await
+            } => {}
+        }
+    }
+}
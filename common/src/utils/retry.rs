@@ -0,0 +1,293 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+
+use crate::utils::{ReloadRx, ResultExt as _};
+
+/// How often and how quickly a failing task is retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// The delay before the first retry (or the second, if `immediate_first_retry` is set).
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Factor by which the delay grows after each failure.
+    pub multiplier: f64,
+    /// Each delay is randomly stretched or shrunk by up to this fraction of itself, so
+    /// that tasks failing for the same reason do not retry in lockstep.
+    pub jitter: f64,
+    /// Give up after this many failed attempts.
+    pub max_attempts: Option<u32>,
+    /// If an attempt ran for at least this long before failing, it is considered a
+    /// fresh failure and the delay starts over.
+    pub reset_after: Option<Duration>,
+    /// Retry right away after the first failure.
+    pub immediate_first_retry: bool,
+}
+impl RetryPolicy {
+    /// Retries forever with the same delay.
+    pub const fn fixed(delay: Duration) -> Self {
+        Self {
+            initial_delay: delay,
+            max_delay: delay,
+            multiplier: 1.0,
+            jitter: 0.0,
+            max_attempts: None,
+            reset_after: None,
+            immediate_first_retry: false,
+        }
+    }
+
+    /// Retries forever, immediately at first and then with doubling delays.
+    /// The delay starts over once the task has run for `max_delay`.
+    pub const fn exponential(initial_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            initial_delay,
+            max_delay,
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+            reset_after: Some(max_delay),
+            immediate_first_retry: true,
+        }
+    }
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::exponential(Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+/// Tracks the failures of a task that is retried according to a [`RetryPolicy`].
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: RetryPolicy,
+    failures: u32,
+    attempt_start: Instant,
+}
+impl Backoff {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            failures: 0,
+            attempt_start: Instant::now(),
+        }
+    }
+
+    /// The number of failures since the last reset.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Should be called when an attempt starts, so that its uptime can be measured.
+    pub fn start_attempt(&mut self) {
+        self.attempt_start = Instant::now();
+    }
+
+    /// Records a failed attempt and returns how long to wait before the next one,
+    /// or `None` if the task should be given up on.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        let RetryPolicy {
+            initial_delay,
+            max_delay,
+            multiplier,
+            jitter,
+            max_attempts,
+            reset_after,
+            immediate_first_retry,
+        } = self.policy;
+
+        if reset_after.is_some_and(|it| self.attempt_start.elapsed() >= it) {
+            self.failures = 0;
+        }
+        self.failures = self.failures.saturating_add(1);
+
+        if max_attempts.is_some_and(|max| self.failures >= max) {
+            return None;
+        }
+
+        let Some(exp) = self
+            .failures
+            .checked_sub(1 + u32::from(immediate_first_retry))
+        else {
+            return Some(Duration::ZERO);
+        };
+        let delay = (initial_delay.as_secs_f64()
+            * multiplier.powi(exp.try_into().unwrap_or(i32::MAX)))
+        .min(max_delay.as_secs_f64());
+        let jitter = jitter * (random_unit() * 2.0 - 1.0);
+        Some(Duration::from_secs_f64((delay * (1.0 + jitter)).max(0.0)))
+    }
+}
+
+/// A random number in `0.0..1.0`. Not suitable for anything but jitter.
+fn random_unit() -> f64 {
+    use std::hash::{BuildHasher as _, Hasher as _};
+    // Every RandomState is seeded differently
+    let bits = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Runs `f` until it succeeds, retrying according to `policy`. A reload skips the
+/// current delay. Returns `None` if the policy gives up.
+pub async fn run_or_retry<T, E, A>(
+    mut f: impl AsyncFnMut(&mut A) -> Result<T, E>,
+    mut args: A,
+    mut ctx: impl FnMut(Result<T, E>) -> anyhow::Result<T>,
+    policy: RetryPolicy,
+    mut reload_rx: Option<&mut ReloadRx>,
+) -> Option<T> {
+    let mut backoff = Backoff::new(policy);
+    loop {
+        backoff.start_attempt();
+        let err = match ctx(f(&mut args).await) {
+            Ok(init) => return Some(init),
+            Err(err) => err,
+        };
+
+        let Some(delay) = backoff.next_delay() else {
+            Err::<(), _>(err)
+                .with_context(|| {
+                    format!(
+                        "Failed to run task. Giving up after {} attempts",
+                        backoff.failures()
+                    )
+                })
+                .ok_or_log();
+            return None;
+        };
+        Err::<(), _>(err)
+            .with_context(|| {
+                format!(
+                    "Failed to run task. Retrying in {:.1}s",
+                    delay.as_secs_f64()
+                )
+            })
+            .ok_or_log();
+
+        tokio::select! {
+            () = tokio::time::sleep(delay) => {}
+            Some(()) = async {
+                let reload_rx = reload_rx.as_deref_mut()?;
+                reload_rx.wait().await
+            } => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ReloadTx;
+
+    const SEC: Duration = Duration::from_secs(1);
+
+    fn delays(policy: RetryPolicy, n: usize) -> Vec<Option<Duration>> {
+        let mut backoff = Backoff::new(policy);
+        (0..n).map(|_| backoff.next_delay()).collect()
+    }
+
+    #[test]
+    fn fixed_delay() {
+        assert_eq!(delays(RetryPolicy::fixed(SEC), 3), [Some(SEC); 3]);
+    }
+
+    #[test]
+    fn exponential_delay_is_capped() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            reset_after: None,
+            ..RetryPolicy::exponential(SEC, 5 * SEC)
+        };
+        assert_eq!(
+            delays(policy, 6),
+            [0, 1, 2, 4, 5, 5].map(|secs| Some(secs * SEC)),
+        );
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let policy = RetryPolicy {
+            jitter: 0.5,
+            ..RetryPolicy::fixed(SEC)
+        };
+        for delay in delays(policy, 100) {
+            let delay = delay.unwrap();
+            assert!((SEC / 2..=SEC * 3 / 2).contains(&delay), "{delay:?}");
+        }
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let policy = RetryPolicy {
+            max_attempts: Some(3),
+            ..RetryPolicy::fixed(SEC)
+        };
+        assert_eq!(delays(policy, 4), [Some(SEC), Some(SEC), None, None]);
+    }
+
+    #[test]
+    fn long_attempts_reset_the_delay() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            reset_after: Some(Duration::ZERO),
+            ..RetryPolicy::exponential(SEC, 60 * SEC)
+        };
+        let mut backoff = Backoff::new(policy);
+        for _ in 0..3 {
+            backoff.start_attempt();
+            assert_eq!(backoff.next_delay(), Some(Duration::ZERO));
+            assert_eq!(backoff.failures(), 1);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_until_success() {
+        let start = tokio::time::Instant::now();
+        let res = run_or_retry(
+            async |attempts: &mut u32| {
+                *attempts += 1;
+                if *attempts < 3 {
+                    Err(*attempts)
+                } else {
+                    Ok(*attempts)
+                }
+            },
+            0,
+            |res| res.map_err(|it| anyhow::anyhow!("attempt {it} failed")),
+            RetryPolicy::fixed(SEC),
+            None,
+        )
+        .await;
+        assert_eq!(res, Some(3));
+        assert_eq!(start.elapsed(), 2 * SEC);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reload_skips_the_delay() {
+        let mut reload_tx = ReloadTx::new();
+        let mut reload_rx = reload_tx.subscribe();
+        let start = tokio::time::Instant::now();
+        let retry = run_or_retry(
+            async |attempts: &mut u32| {
+                *attempts += 1;
+                if *attempts < 2 { Err(()) } else { Ok(()) }
+            },
+            0,
+            |res| res.map_err(|()| anyhow::anyhow!("failed")),
+            RetryPolicy::fixed(60 * SEC),
+            Some(&mut reload_rx),
+        );
+        let reload = async {
+            tokio::time::sleep(SEC).await;
+            reload_tx.reload();
+            std::future::pending::<()>().await
+        };
+        tokio::select! {
+            res = retry => assert_eq!(res, Some(())),
+            () = reload => unreachable!(),
+        }
+        assert_eq!(start.elapsed(), SEC);
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::{Context, bail};
+use futures::StreamExt as _;
+use tokio_util::task::AbortOnDropHandle;
+
+use bar_common::{
+    tui::ColorScheme,
+    utils::{
+        HealthReporter, ReloadRx, ResultExt, RetryPolicy, WatchRx, WatchTx, supervise, watch_chan,
+    },
+};
+
+mod dbus {
+    use zbus::{proxy, zvariant::OwnedValue};
+
+    #[proxy(
+        interface = "org.freedesktop.portal.Settings",
+        default_service = "org.freedesktop.portal.Desktop",
+        default_path = "/org/freedesktop/portal/desktop"
+    )]
+    pub trait Settings {
+        fn read_one(&self, namespace: &str, key: &str) -> zbus::Result<OwnedValue>;
+
+        #[zbus(signal)]
+        fn setting_changed(
+            &self,
+            namespace: &str,
+            key: &str,
+            value: OwnedValue,
+        ) -> zbus::Result<()>;
+    }
+}
+
+const NAMESPACE: &str = "org.freedesktop.appearance";
+const KEY: &str = "color-scheme";
+
+/// The system's light/dark preference, as reported by the settings portal.
+pub struct AppearanceClient {
+    /// `None` if there is no preference or it is unknown.
+    pub scheme_rx: WatchRx<Option<ColorScheme>>,
+    _background: AbortOnDropHandle<()>,
+}
+
+/// Whether the error means that there is no settings portal or that it does not
+/// know the setting, which is the same as having no preference.
+fn is_unavailable(err: &zbus::Error) -> bool {
+    match err {
+        zbus::Error::MethodError(name, ..) => matches!(
+            name.as_str(),
+            "org.freedesktop.DBus.Error.ServiceUnknown"
+                | "org.freedesktop.DBus.Error.NameHasNoOwner"
+                | "org.freedesktop.DBus.Error.UnknownMethod"
+                | "org.freedesktop.DBus.Error.UnknownInterface"
+                | "org.freedesktop.DBus.Error.UnknownObject"
+                | "org.freedesktop.portal.Error.NotFound"
+        ),
+        zbus::Error::FDO(err) => matches!(
+            **err,
+            zbus::fdo::Error::ServiceUnknown(_)
+                | zbus::fdo::Error::NameHasNoOwner(_)
+                | zbus::fdo::Error::UnknownMethod(_)
+                | zbus::fdo::Error::UnknownInterface(_)
+                | zbus::fdo::Error::UnknownObject(_)
+        ),
+        _ => false,
+    }
+}
+
+fn scheme_from_value(value: zbus::zvariant::OwnedValue) -> Option<ColorScheme> {
+    match u32::try_from(value).ok_or_log()? {
+        1 => Some(ColorScheme::Dark),
+        2 => Some(ColorScheme::Light),
+        _ => None,
+    }
+}
+
+async fn run_bg(
+    scheme_tx: WatchTx<Option<ColorScheme>>,
+    reload_rx: ReloadRx,
+    health: HealthReporter,
+) {
+    supervise(
+        &health,
+        RetryPolicy::default(),
+        Some(&mut reload_rx.clone()),
+        (scheme_tx, reload_rx),
+        async |(scheme_tx, reload_rx), health| try_run_bg(scheme_tx, reload_rx, health).await,
+    )
+    .await;
+}
+
+async fn try_run_bg(
+    scheme_tx: &WatchTx<Option<ColorScheme>>,
+    reload_rx: &mut ReloadRx,
+    health: &HealthReporter,
+) -> anyhow::Result<()> {
+    let connection = zbus::Connection::session()
+        .await
+        .context("Failed to connect to the session bus")?;
+    let proxy = dbus::SettingsProxy::new(&connection)
+        .await
+        .context("Failed to connect to the settings portal")?;
+
+    let changed_rx = proxy
+        .receive_setting_changed_with_args(&[(0, NAMESPACE), (1, KEY)])
+        .await
+        .context("Failed to subscribe to setting changes")?;
+    tokio::pin!(changed_rx);
+    health.running();
+
+    loop {
+        let scheme = match proxy.read_one(NAMESPACE, KEY).await {
+            Ok(value) => scheme_from_value(value),
+            Err(err) if is_unavailable(&err) => {
+                log::debug!("The color scheme is not available: {err}");
+                None
+            }
+            Err(err) => {
+                log::error!("Failed to read the color scheme: {err}");
+                None
+            }
+        };
+        scheme_tx.send_if_modified(|cur| {
+            let changed = *cur != scheme;
+            *cur = scheme;
+            changed
+        });
+
+        tokio::select! {
+            Some(_) = changed_rx.next() => (),
+            Some(()) = reload_rx.wait() => (),
+            else => bail!("Settings portal signal stream was closed"),
+        }
+    }
+}
+
+pub fn connect(reload_rx: ReloadRx, health: HealthReporter) -> AppearanceClient {
+    let (scheme_tx, scheme_rx) = watch_chan(None);
+    AppearanceClient {
+        _background: AbortOnDropHandle::new(tokio::spawn(run_bg(scheme_tx, reload_rx, health))),
+        scheme_rx,
+    }
+}
@@ -1,6 +1,6 @@
 use crate::desktop::{BasicDesktopState, BasicWorkspace, WorkspaceId};
 use anyhow::Context;
-use bar_common::utils::{ReloadRx, WatchRx, watch_chan};
+use bar_common::utils::{HealthReporter, ReloadRx, RetryPolicy, WatchRx, supervise, watch_chan};
 use bar_common::utils::{ResultExt, WatchTx};
 use futures::StreamExt;
 use hyprland::data::*;
@@ -29,10 +29,26 @@ impl HyprClient {
     }
 }
 
-async fn run_bg(basic_tx: WatchTx<BasicDesktopState>, mut reload_rx: ReloadRx) {
+async fn run_bg(basic_tx: WatchTx<BasicDesktopState>, reload_rx: ReloadRx, health: HealthReporter) {
+    supervise(
+        &health,
+        RetryPolicy::default(),
+        Some(&mut reload_rx.clone()),
+        (basic_tx, reload_rx),
+        async |(basic_tx, reload_rx), health| try_run_bg(basic_tx, reload_rx, health).await,
+    )
+    .await;
+}
+
+async fn try_run_bg(
+    basic_tx: &WatchTx<BasicDesktopState>,
+    reload_rx: &mut ReloadRx,
+    health: &HealthReporter,
+) -> anyhow::Result<()> {
     let ev_rx = hyprland::event_listener::EventStream::new()
         .filter_map(async |res| res.context("Hyprland error").ok_or_log());
     tokio::pin!(ev_rx);
+    health.running();
 
     let mut workspaces = Default::default();
     let mut monitors = HashMap::new();
@@ -41,7 +57,7 @@ async fn run_bg(basic_tx: WatchTx<BasicDesktopState>, mut reload_rx: ReloadRx) {
         type HyprEvent = hyprland::event_listener::Event;
         let (upd_wss, upd_mons) = tokio::select! {
             Some(()) = reload_rx.wait() => (true, true),
-            Some(ev) = ev_rx.next() => match ev {
+            ev = ev_rx.next() => match ev.context("Hyprland event stream was closed")? {
                 HyprEvent::MonitorAdded(_) => (false, true),
                 HyprEvent::ActiveMonitorChanged(_) => (false, true),
                 HyprEvent::MonitorRemoved(_) => (false, true),
@@ -125,10 +141,10 @@ async fn run_bg(basic_tx: WatchTx<BasicDesktopState>, mut reload_rx: ReloadRx) {
     }
 }
 
-pub fn connect(reload_rx: ReloadRx) -> HyprClient {
+pub fn connect(reload_rx: ReloadRx, health: HealthReporter) -> HyprClient {
     let (basic_tx, basic_rx) = watch_chan(BasicDesktopState::default());
     HyprClient {
-        _background: AbortOnDropHandle::new(tokio::spawn(run_bg(basic_tx, reload_rx))),
+        _background: AbortOnDropHandle::new(tokio::spawn(run_bg(basic_tx, reload_rx, health))),
         basic_rx,
     }
 }
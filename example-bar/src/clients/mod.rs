@@ -1,3 +1,4 @@
+pub mod appearance;
 pub mod hypr;
 pub mod ppd;
 pub mod pulse;
@@ -1,11 +1,13 @@
 use std::sync::Arc;
 
-use anyhow::Context;
+use anyhow::{Context, bail};
 use futures::StreamExt as _;
 use tokio::sync::Semaphore;
 use tokio_util::task::AbortOnDropHandle;
 
-use bar_common::utils::{ReloadRx, ResultExt, WatchRx, WatchTx, watch_chan};
+use bar_common::utils::{
+    HealthReporter, ReloadRx, ResultExt, RetryPolicy, WatchRx, WatchTx, supervise, watch_chan,
+};
 
 mod dbus {
     use serde::{Deserialize, Serialize};
@@ -55,23 +57,44 @@ impl PpdClient {
 async fn run_bg(
     cycle_rx: Arc<Semaphore>,
     profile_tx: WatchTx<Option<Arc<str>>>,
-    mut reload_rx: ReloadRx,
+    reload_rx: ReloadRx,
+    health: HealthReporter,
 ) {
-    let Some(connection) = zbus::Connection::system().await.ok_or_log() else {
-        return;
-    };
-    let Some(proxy) = dbus::PpdProxy::new(&connection).await.ok_or_log() else {
-        return;
-    };
+    supervise(
+        &health,
+        RetryPolicy::default(),
+        Some(&mut reload_rx.clone()),
+        (cycle_rx, profile_tx, reload_rx),
+        async |(cycle_rx, profile_tx, reload_rx), health| {
+            try_run_bg(cycle_rx, profile_tx, reload_rx, health).await
+        },
+    )
+    .await;
+}
+
+async fn try_run_bg(
+    cycle_rx: &Semaphore,
+    profile_tx: &WatchTx<Option<Arc<str>>>,
+    reload_rx: &mut ReloadRx,
+    health: &HealthReporter,
+) -> anyhow::Result<()> {
+    let connection = zbus::Connection::system()
+        .await
+        .context("Failed to connect to the system bus")?;
+    let proxy = dbus::PpdProxy::new(&connection)
+        .await
+        .context("Failed to connect to power-profiles-daemon")?;
 
     let profiles_fut = async {
         let profile_rx = proxy.receive_active_profile_changed().await;
         tokio::pin!(profile_rx);
+        health.running();
 
         loop {
             tokio::select! {
                 Some(_) = profile_rx.next() => (),
                 Some(()) = reload_rx.wait() => (),
+                else => break,
             };
 
             let profile = proxy.active_profile().await.ok_or_log();
@@ -119,12 +142,12 @@ async fn run_bg(
     };
 
     tokio::select! {
-        () = profiles_fut => (),
-        () = cycle_fut => (),
+        () = profiles_fut => bail!("power-profiles-daemon profile stream was closed"),
+        () = cycle_fut => Ok(()),
     }
 }
 
-pub fn connect(reload_rx: ReloadRx) -> PpdClient {
+pub fn connect(reload_rx: ReloadRx, health: HealthReporter) -> PpdClient {
     let cycle = Arc::new(Semaphore::new(0));
     let (profile_tx, profile_rx) = watch_chan(Default::default());
     PpdClient {
@@ -132,6 +155,7 @@ pub fn connect(reload_rx: ReloadRx) -> PpdClient {
             cycle.clone(),
             profile_tx,
             reload_rx,
+            health,
         ))),
         cycle,
         profile_rx,
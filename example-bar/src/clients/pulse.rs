@@ -25,7 +25,8 @@ use std::{
 };
 
 use bar_common::utils::{
-    CancelDropGuard, ReloadRx, ResultExt, UnbTx, WatchRx, WatchTx, unb_chan, watch_chan,
+    CancelDropGuard, HealthReporter, ReloadRx, ResultExt, RetryPolicy, UnbTx, WatchRx, WatchTx,
+    supervise, unb_chan, watch_chan,
 };
 
 #[derive(Debug, Clone, Default)]
@@ -56,6 +57,8 @@ pub struct PulseUpdate {
 #[derive(Clone, Debug)]
 pub enum PulseUpdateKind {
     VolumeDelta(i32),
+    /// Sets the volume, where 1 is 100%.
+    SetVolume(f64),
     ToggleMute,
     ResetVolume,
 }
@@ -72,6 +75,7 @@ fn run_blocking(
     tx: WatchTx<PulseState>,
     cancel: CancellationToken,
     awaiting_reload: Arc<AtomicBool>,
+    health: &HealthReporter,
 ) -> anyhow::Result<()> {
     let tx = Rc::new(tx);
     log::info!("Connecting to PulseAudio");
@@ -104,6 +108,7 @@ fn run_blocking(
             _ => handle_iterate_result(mainloop.iterate(true))?,
         }
     }
+    health.running();
 
     let state = Rc::new(RefCell::new(PulseState::default()));
 
@@ -263,6 +268,16 @@ async fn run_updater(update_rx: impl Stream<Item = PulseUpdate>) {
                     .output()
                     .await
             }
+            PulseUpdateKind::SetVolume(volume) => {
+                pactl()
+                    .args([
+                        set_vol_cmd,
+                        device_name,
+                        &format!("{}%", (volume * 100.0).round() as u32),
+                    ])
+                    .output()
+                    .await
+            }
             PulseUpdateKind::ToggleMute => {
                 pactl()
                     .args([set_mute_cmd, device_name, "toggle"])
@@ -292,11 +307,13 @@ pub struct PulseClient {
     pub update_tx: UnbTx<PulseUpdate>,
     _background: AbortOnDropHandle<()>,
 }
-pub fn connect(reload_rx: ReloadRx) -> PulseClient {
+pub fn connect(reload_rx: ReloadRx, health: HealthReporter) -> PulseClient {
     let (state_tx, state_rx) = watch_chan(Default::default());
     let (update_tx, update_rx) = unb_chan();
     PulseClient {
-        _background: AbortOnDropHandle::new(tokio::spawn(run_bg(state_tx, update_rx, reload_rx))),
+        _background: AbortOnDropHandle::new(tokio::spawn(run_bg(
+            state_tx, update_rx, reload_rx, health,
+        ))),
         state_rx,
         update_tx,
     }
@@ -306,30 +323,46 @@ async fn run_bg(
     state_tx: WatchTx<PulseState>,
     update_rx: impl Stream<Item = PulseUpdate> + 'static + Send,
     mut reload_rx: ReloadRx,
+    health: HealthReporter,
 ) {
     let mut tasks = JoinSet::<()>::new();
     tasks.spawn(run_updater(update_rx));
 
     let awaiting_reload = Arc::new(AtomicBool::new(false));
     let auto_cancel = CancelDropGuard::new();
-    {
-        let awaiting_reload = awaiting_reload.clone();
-        let cancel = auto_cancel.inner.clone();
-        // FIXME: Rerun on failure
-        std::thread::spawn(|| {
-            run_blocking(state_tx, cancel, awaiting_reload)
-                .context("PulseAudio client has failed")
-                .ok_or_log();
-        });
-    }
 
-    tokio::spawn(async move {
-        while let Some(()) = reload_rx.wait().await {
-            awaiting_reload.store(true, std::sync::atomic::Ordering::Relaxed);
+    tokio::spawn({
+        let awaiting_reload = awaiting_reload.clone();
+        let mut reload_rx = reload_rx.clone();
+        async move {
+            while let Some(()) = reload_rx.wait().await {
+                awaiting_reload.store(true, std::sync::atomic::Ordering::Relaxed);
+            }
         }
     });
 
-    if let Some(res) = tasks.join_next().await {
-        res.context("PulseAudio module failed").ok_or_log();
+    let client = supervise(
+        &health,
+        RetryPolicy::default(),
+        Some(&mut reload_rx),
+        (state_tx, auto_cancel.inner.clone(), awaiting_reload),
+        async |(state_tx, cancel, awaiting_reload), health| {
+            let state_tx = state_tx.clone();
+            let cancel = cancel.clone();
+            let awaiting_reload = awaiting_reload.clone();
+            let health = health.clone();
+            tokio::task::spawn_blocking(move || {
+                run_blocking(state_tx, cancel, awaiting_reload, &health)
+            })
+            .await
+            .context("PulseAudio client panicked")?
+        },
+    );
+
+    tokio::select! {
+        () = client => {}
+        Some(res) = tasks.join_next() => {
+            res.context("PulseAudio module failed").ok_or_log();
+        }
     }
 }
@@ -1,15 +1,14 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use anyhow::Context;
-use futures::Stream;
+use anyhow::{Context, bail};
 use futures::StreamExt as _;
 use system_tray::item::StatusNotifierItem;
 use tokio::sync::broadcast;
-use tokio::task::JoinSet;
 use tokio_util::task::AbortOnDropHandle;
 
 use bar_common::utils::{
-    ReloadRx, ReloadTx, ResultExt, UnbTx, WatchRx, WatchTx, run_or_retry, unb_chan, watch_chan,
+    HealthReporter, ReloadRx, ReloadTx, ResultExt, RetryPolicy, UnbRx, UnbTx, WatchRx, WatchTx,
+    supervise, unb_chan, watch_chan,
 };
 
 #[derive(Debug, Default)]
@@ -46,7 +45,7 @@ impl TrayClient {
             .ok_or_debug();
     }
 }
-pub fn connect(reload_rx: ReloadRx) -> TrayClient {
+pub fn connect(reload_rx: ReloadRx, health: HealthReporter) -> TrayClient {
     let (state_tx, state_rx) = watch_chan(Default::default());
     let (client_sched_tx, client_sched_rx) = unb_chan();
     TrayClient {
@@ -54,6 +53,7 @@ pub fn connect(reload_rx: ReloadRx) -> TrayClient {
             state_tx,
             client_sched_rx,
             reload_rx,
+            health,
         ))),
         state_rx,
         client_sched_tx,
@@ -61,36 +61,52 @@ pub fn connect(reload_rx: ReloadRx) -> TrayClient {
 }
 async fn run_bg(
     state_tx: WatchTx<TrayState>,
-    client_sched_rx: impl Stream<Item = ClientCallback> + Send + 'static,
-    mut reload_rx: ReloadRx,
+    client_sched_rx: UnbRx<ClientCallback>,
+    reload_rx: ReloadRx,
+    health: HealthReporter,
 ) {
-    let client = run_or_retry(
-        |_: &mut ()| system_tray::client::Client::new(),
-        (),
-        |res| res.context("Failed to initialize tray client"),
-        Duration::from_secs(90),
-        Some(&mut reload_rx),
+    supervise(
+        &health,
+        RetryPolicy::default(),
+        Some(&mut reload_rx.clone()),
+        BgArgs {
+            state_tx,
+            client_sched_rx,
+            reload_rx,
+        },
+        try_run_bg,
     )
     .await;
-
-    let mut tasks = JoinSet::<()>::new();
-
-    let event_reload_tx = ReloadTx::new();
-    let event_reload_rx = event_reload_tx.subscribe();
-
-    tasks.spawn(events_to_reloads(event_reload_tx, client.subscribe()));
-
-    tasks.spawn(run_state_fetcher(
-        client.items(),
-        event_reload_rx,
+}
+struct BgArgs {
+    state_tx: WatchTx<TrayState>,
+    client_sched_rx: UnbRx<ClientCallback>,
+    reload_rx: ReloadRx,
+}
+async fn try_run_bg(
+    BgArgs {
         state_tx,
+        client_sched_rx,
         reload_rx,
-    ));
+    }: &mut BgArgs,
+    health: &HealthReporter,
+) -> anyhow::Result<()> {
+    let client = system_tray::client::Client::new()
+        .await
+        .context("Failed to initialize tray client")?;
+    health.running();
 
-    tasks.spawn(run_client_sched(client, client_sched_rx));
+    let event_reload_tx = ReloadTx::new();
+    let event_reload_rx = event_reload_tx.subscribe();
 
-    if let Some(res @ Err(_)) = tasks.join_next().await {
-        res.context("Systray module failed").ok_or_log();
+    tokio::select! {
+        () = events_to_reloads(event_reload_tx, client.subscribe()) => {
+            bail!("Tray event stream was closed")
+        }
+        () = run_state_fetcher(client.items(), event_reload_rx, state_tx, reload_rx) => {
+            bail!("Tray state fetcher has exited")
+        }
+        () = run_client_sched(Arc::new(client), client_sched_rx) => Ok(()),
     }
 }
 
@@ -120,8 +136,8 @@ async fn events_to_reloads(mut tx: ReloadTx, mut rx: broadcast::Receiver<impl Cl
 async fn run_state_fetcher(
     state_mutex: Arc<std::sync::Mutex<system_tray::data::BaseMap>>,
     mut event_reload_rx: ReloadRx,
-    state_tx: WatchTx<TrayState>,
-    mut reload_rx: ReloadRx,
+    state_tx: &WatchTx<TrayState>,
+    reload_rx: &mut ReloadRx,
 ) {
     let fetch_blocking = move || {
         let lock = state_mutex.lock().unwrap_or_else(|it| it.into_inner());
@@ -160,11 +176,9 @@ async fn run_state_fetcher(
     }
 }
 async fn run_client_sched(
-    client: system_tray::client::Client,
-    cb_rx: impl Stream<Item = ClientCallback>,
+    client: Arc<system_tray::client::Client>,
+    cb_rx: &mut UnbRx<ClientCallback>,
 ) {
-    let client = Arc::new(client);
-    tokio::pin!(cb_rx);
     while let Some(cb) = cb_rx.next().await {
         cb(client.clone());
     }
@@ -1,11 +1,13 @@
 use std::time::Duration;
 
-use anyhow::Context as _;
+use anyhow::{Context as _, bail};
 use futures::StreamExt as _;
 use tokio_util::task::AbortOnDropHandle;
 use zbus::proxy;
 
-use bar_common::utils::{ReloadRx, ResultExt, WatchRx, WatchTx, run_or_retry, watch_chan};
+use bar_common::utils::{
+    HealthReporter, ReloadRx, ResultExt, RetryPolicy, WatchRx, WatchTx, supervise, watch_chan,
+};
 
 macro_rules! declare_properties {
     (
@@ -160,20 +162,21 @@ pub struct EnergyClient {
     _background: AbortOnDropHandle<()>,
 }
 
-async fn run_bg(state_tx: WatchTx<UpowerState>, mut reload_rx: ReloadRx) {
-    run_or_retry(
-        async |(state_tx, reload_rx)| try_run_bg(state_tx, reload_rx).await,
-        (state_tx, reload_rx.clone()),
-        |it| it.context("Failed to run upower client"),
-        Duration::from_secs(60),
-        Some(&mut reload_rx),
+async fn run_bg(state_tx: WatchTx<UpowerState>, reload_rx: ReloadRx, health: HealthReporter) {
+    supervise(
+        &health,
+        RetryPolicy::default(),
+        Some(&mut reload_rx.clone()),
+        (state_tx, reload_rx),
+        async |(state_tx, reload_rx), health| try_run_bg(state_tx, reload_rx, health).await,
     )
-    .await
+    .await;
 }
 
 async fn try_run_bg(
     state_tx: &WatchTx<UpowerState>,
     reload_rx: &mut ReloadRx,
+    health: &HealthReporter,
 ) -> anyhow::Result<()> {
     let dbus = zbus::Connection::system().await?;
 
@@ -217,6 +220,7 @@ async fn try_run_bg(
     });
 
     let mut prop_change_rx = device_proxy.receive_all_signals().await?;
+    health.running();
     while let Some(msg) = prop_change_rx.next().await {
         let state_tx = state_tx.clone();
         let device_interface = device_interface.clone();
@@ -236,13 +240,13 @@ async fn try_run_bg(
         });
     }
 
-    Ok(())
+    bail!("UPower signal stream was closed")
 }
 
-pub fn connect(reload_rx: ReloadRx) -> EnergyClient {
+pub fn connect(reload_rx: ReloadRx, health: HealthReporter) -> EnergyClient {
     let (state_tx, state_rx) = watch_chan(Default::default());
     EnergyClient {
-        _background: AbortOnDropHandle::new(tokio::spawn(run_bg(state_tx, reload_rx))),
+        _background: AbortOnDropHandle::new(tokio::spawn(run_bg(state_tx, reload_rx, health))),
         state_rx,
     }
 }
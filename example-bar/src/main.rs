@@ -9,6 +9,7 @@ fn main() -> std::process::ExitCode {
     bar_common::logging::init_logger(
         bar_common::logging::ProcKind::Controller,
         "CONTROLLER".into(),
+        bar_common::logging::LogConfig::from_env(),
     );
 
     let runtime = tokio::runtime::Builder::new_multi_thread()
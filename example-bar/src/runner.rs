@@ -1,12 +1,13 @@
 use std::{collections::HashMap, sync::Arc};
 
-use anyhow::Context as _;
+use anyhow::{Context as _, anyhow};
 use bar_common::{
     tui,
-    utils::{ReloadRx, ReloadTx, ResultExt as _, WatchRx, WatchTx, watch_chan},
+    utils::{Health, HealthTx, ReloadRx, ReloadTx, ResultExt as _, WatchRx, WatchTx, watch_chan},
 };
-use bar_panel_controller::BarTuiState;
+use bar_panel_controller::{BarTuiState, KittyPanelBackend, PanelBackend, TerminalWindowBackend};
 use tokio::task::JoinSet;
+use tokio_util::task::AbortOnDropHandle;
 
 use crate::clients;
 
@@ -27,32 +28,50 @@ impl From<tui::Elem> for BarTuiElem {
 struct ModuleArgs {
     tui_tx: WatchTx<BarTuiElem>,
     reload_rx: ReloadRx,
+    /// For registering the clients that the module uses.
+    health: HealthTx,
     _unused: (),
 }
 
 struct BarModuleFactory {
     reload_tx: ReloadTx,
+    health: HealthTx,
     tasks: JoinSet<()>,
 }
 impl BarModuleFactory {
+    /// Spawns a module, whose health is reported under `name`.
+    /// Modules are not restarted, since they are not expected to exit.
     fn spawn<F: Future<Output = ()> + 'static + Send>(
         &mut self,
+        name: &str,
         task: impl FnOnce(ModuleArgs) -> F,
     ) -> WatchRx<BarTuiElem> {
         let (tui_tx, tui_rx) = watch_chan(BarTuiElem::Hide);
-        self.tasks.spawn(task(ModuleArgs {
+        let health = self.health.reporter(name);
+        let task = AbortOnDropHandle::new(tokio::spawn(task(ModuleArgs {
             reload_rx: self.reload_tx.subscribe(),
             tui_tx,
+            health: self.health.clone(),
             _unused: (),
-        }));
+        })));
+        self.tasks.spawn(async move {
+            health.running();
+            let err = match task.await {
+                Ok(()) => anyhow!("Module has exited"),
+                Err(err) => anyhow::Error::from(err).context("Module has failed"),
+            };
+            log::error!("{}: {err:?}", health.name());
+            health.failed(&err);
+        });
         tui_rx
     }
     fn spawn_with<F: Future<Output = ()> + 'static + Send, C>(
         &mut self,
+        name: &str,
         ctx: C,
         task: impl FnOnce(C, ModuleArgs) -> F,
     ) -> WatchRx<BarTuiElem> {
-        self.spawn(|args| task(ctx, args))
+        self.spawn(name, |args| task(ctx, args))
     }
     fn fixed(&mut self, elem: BarTuiElem) -> WatchRx<BarTuiElem> {
         let (_, rx) = watch_chan(elem);
@@ -92,38 +111,76 @@ fn gather_bar_tui(bar_tui: &[BarTuiElem], tx: &WatchTx<BarTuiState>) {
         };
     }
 
-    tx.send_replace(BarTuiState {
-        by_monitor: by_monitor
+    tx.send_modify(|state| {
+        state.by_monitor = by_monitor
             .into_iter()
             .map(|(k, stack)| (k, stack.build()))
-            .collect(),
-        fallback: fallback.build(),
+            .collect();
+        state.fallback = fallback.build();
     });
 }
 
+/// Env var with a terminal command (e.g. `kitty` or `foot`) to run the panels in
+/// ordinary windows instead of `kitten panel`. Useful for development.
+const DEV_TERMINAL_VAR: &str = "BAR_DEV_TERMINAL";
+
+fn panel_backend() -> Arc<dyn PanelBackend> {
+    match std::env::var(DEV_TERMINAL_VAR) {
+        Ok(command) => {
+            log::info!("Running panels in {command:?} ({DEV_TERMINAL_VAR} is set)");
+            // Only kitty is known to support both protocols.
+            let features = if command.starts_with("kitty") {
+                tui::TermFeatures::all()
+            } else {
+                tui::TermFeatures::none()
+            };
+            Arc::new(TerminalWindowBackend::from_command_line(&command, features))
+        }
+        Err(_) => Arc::new(KittyPanelBackend::default()),
+    }
+}
+
 pub async fn main() -> std::process::ExitCode {
     let mut required_tasks = JoinSet::new();
     let mut reload_tx = ReloadTx::new();
 
-    let bar_tui_tx = WatchTx::new(BarTuiState::default());
+    let theme_config = tui::ThemeConfig::from_env();
+    let bar_tui_tx = WatchTx::new(BarTuiState {
+        theme: theme_config.theme(None),
+        ..Default::default()
+    });
     required_tasks.spawn(bar_panel_controller::run_controller(
         bar_tui_tx.subscribe(),
         reload_tx.clone(),
+        panel_backend(),
     ));
 
+    let health = HealthTx::new();
+
     let mut fac = BarModuleFactory {
         reload_tx: reload_tx.clone(),
+        health: health.clone(),
         tasks: JoinSet::new(),
     };
 
-    let pulse = Arc::new(clients::pulse::connect(reload_tx.subscribe()));
+    // Follow the system's light/dark preference unless the palette is configured
+    let _theme = theme_config
+        .scheme
+        .is_none()
+        .then(|| fac.spawn_with("Theme", (theme_config, bar_tui_tx.clone()), theme_module));
+
+    let pulse = Arc::new(clients::pulse::connect(
+        reload_tx.subscribe(),
+        health.reporter("PulseAudio"),
+    ));
     let mut modules = [
         fac.fixed(BarTuiElem::Spacing(1)),
-        fac.spawn(hypr_module),
+        fac.spawn("Workspaces", hypr_module),
         fac.fixed(BarTuiElem::FillSpace(1)),
-        fac.spawn(tray_module),
+        fac.spawn("Tray", tray_module),
         fac.fixed(BarTuiElem::Spacing(3)),
         fac.spawn_with(
+            "Microphone",
             PulseModuleCtx {
                 pulse: pulse.clone(),
                 device_kind: clients::pulse::PulseDeviceKind::Source,
@@ -134,6 +191,7 @@ pub async fn main() -> std::process::ExitCode {
         ),
         fac.fixed(BarTuiElem::Spacing(3)),
         fac.spawn_with(
+            "Volume",
             PulseModuleCtx {
                 pulse,
                 device_kind: clients::pulse::PulseDeviceKind::Sink,
@@ -143,10 +201,11 @@ pub async fn main() -> std::process::ExitCode {
             pulse_module,
         ),
         fac.fixed(BarTuiElem::Spacing(3)),
-        fac.spawn(ppd_module),
-        fac.spawn(energy_module),
+        fac.spawn("Power profile", ppd_module),
+        fac.spawn("Battery", energy_module),
         fac.fixed(BarTuiElem::Spacing(3)),
-        fac.spawn(time_module),
+        fac.spawn("Health", health_module),
+        fac.spawn("Clock", time_module),
     ];
 
     let mut module_tasks = JoinSet::new();
@@ -184,12 +243,34 @@ pub async fn main() -> std::process::ExitCode {
     }
 }
 
+/// Sets the theme by the system's light/dark preference. Shows nothing.
+async fn theme_module(
+    (config, bar_tui_tx): (tui::ThemeConfig, WatchTx<BarTuiState>),
+    ModuleArgs {
+        reload_rx, health, ..
+    }: ModuleArgs,
+) {
+    let appearance = clients::appearance::connect(reload_rx, health.reporter("Appearance"));
+
+    let mut scheme_rx = appearance.scheme_rx.clone();
+    while let Some(()) = scheme_rx.changed().await.ok_or_debug() {
+        let theme = config.theme(*scheme_rx.borrow_and_update());
+        bar_tui_tx.send_if_modified(|state| std::mem::replace(&mut state.theme, theme) != theme);
+    }
+}
+
 async fn hypr_module(
     ModuleArgs {
-        tui_tx, reload_rx, ..
+        tui_tx,
+        reload_rx,
+        health,
+        ..
     }: ModuleArgs,
 ) {
-    let hypr = Arc::new(clients::hypr::connect(reload_rx));
+    let hypr = Arc::new(clients::hypr::connect(
+        reload_rx,
+        health.reporter("Hyprland"),
+    ));
 
     let mut basic_rx = hypr.basic_rx.clone();
     basic_rx.mark_changed();
@@ -219,11 +300,17 @@ async fn hypr_module(
                 },
             );
 
+            let (name, is_active) = (ws.name.clone(), ws.is_active);
             wss.fit(
-                tui::Elem::from(tui::RawPrint::plain(&ws.name).styled(tui::Style {
-                    fg: ws.is_active.then_some(tui::Color::Green),
-                    ..Default::default()
-                }))
+                tui::Elem::themed(move |theme| {
+                    tui::RawPrint::plain(&name)
+                        .styled(if is_active {
+                            theme.style(tui::ThemeRole::Accent)
+                        } else {
+                            Default::default()
+                        })
+                        .into()
+                })
                 .on_interact(on_interact, None),
             );
             wss.spacing(1);
@@ -236,6 +323,101 @@ async fn hypr_module(
         tui_tx.send_replace(BarTuiElem::ByMonitor(by_monitor));
     }
 }
+
+/// A calendar of the month of `today`, with `today` highlighted.
+fn calendar_tui(today: chrono::NaiveDate) -> Option<tui::Elem> {
+    use chrono::Datelike;
+
+    let title = today.format("%B %Y").to_string();
+
+    let first_day_offset = today
+        .with_day(1)
+        .map(|first| first.weekday() as u16)
+        .context("Failed to set day")
+        .ok_or_log()?;
+
+    let num_weeks = usize::div_ceil(
+        usize::from(today.num_days_in_month()) + usize::from(first_day_offset),
+        7,
+    );
+
+    // FIXME: Simplify
+    let mut lines: Vec<_> = std::iter::repeat_n(
+        std::array::repeat::<_, 7>(tui::Elem::empty().with_min_size(tui::Vec2 {
+            x: 2,
+            ..Default::default()
+        })),
+        num_weeks,
+    )
+    .collect();
+
+    for n0 in 0u16..today.num_days_in_month().into() {
+        let n1 = n0 + 1;
+        let day = today
+            .with_day(n1.into())
+            .with_context(|| format!("Failed to set day {n1}"))
+            .ok_or_log()?;
+
+        let week_in_month = (first_day_offset + n0) / 7;
+        let item = &mut lines[usize::from(week_in_month)][day.weekday() as usize];
+        *item = {
+            let it = tui::RawPrint::plain(format!("{n1:>2}"));
+            if today == day {
+                tui::Elem::themed(move |theme| {
+                    it.clone()
+                        .styled(theme.style(tui::ThemeRole::Accent))
+                        .map_display(|styled| styled.to_string())
+                        .into()
+                })
+            } else {
+                it.into()
+            }
+        };
+    }
+    let weekday_line =
+        ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"].map(|d| tui::RawPrint::plain(d).into());
+
+    let elem = tui::Elem::build_stack(tui::Axis::Y, |vstack| {
+        vstack.fit(
+            tui::RawPrint::plain(title)
+                .styled(tui::Style {
+                    modifier: tui::Modifier {
+                        bold: true,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .into(),
+        );
+        for line in std::iter::once(weekday_line).chain(lines) {
+            vstack.fit(tui::Elem::build_stack(tui::Axis::X, |hstack| {
+                let mut first = true;
+                for day in line {
+                    if !first {
+                        hstack.spacing(1);
+                    }
+                    first = false;
+
+                    hstack.fit(day);
+                }
+            }));
+        }
+    });
+    Some(elem)
+}
+
+/// Groups the element into a rounded, filled container.
+fn pill(elem: tui::Elem) -> tui::Elem {
+    tui::Elem::themed(move |theme| {
+        tui::Elem::build_block(|block| {
+            block.set_fill(theme.muted);
+            block.set_padding(tui::Padding::horizontal(1));
+            block.set_caps(tui::Caps::Round);
+            block.set_inner(elem.clone());
+        })
+    })
+}
+
 async fn time_module(
     ModuleArgs {
         tui_tx,
@@ -243,91 +425,14 @@ async fn time_module(
         ..
     }: ModuleArgs,
 ) {
-    use chrono::{Datelike, Timelike};
+    use chrono::Timelike;
     use std::time::Duration;
 
     let on_interact = tui::InteractCallback::from_fn(move |interact| {
         if interact.kind != tui::InteractKind::Hover {
             return None;
         }
-
-        let now = chrono::Local::now().date_naive();
-        let title = now.format("%B %Y").to_string();
-
-        let first_day_offset = now
-            .with_day(1)
-            .map(|first| first.weekday() as u16)
-            .context("Failed to set day")
-            .ok_or_log()?;
-
-        let num_weeks = usize::div_ceil(
-            usize::from(now.num_days_in_month()) + usize::from(first_day_offset),
-            7,
-        );
-
-        // FIXME: Simplify
-        let mut lines: Vec<_> = std::iter::repeat_n(
-            std::array::repeat::<_, 7>(tui::Elem::empty().with_min_size(tui::Vec2 {
-                x: 2,
-                ..Default::default()
-            })),
-            num_weeks,
-        )
-        .collect();
-
-        for n0 in 0u16..now.num_days_in_month().into() {
-            let n1 = n0 + 1;
-            let day = now
-                .with_day(n1.into())
-                .with_context(|| format!("Failed to set day {n1}"))
-                .ok_or_log()?;
-
-            let week_in_month = (first_day_offset + n0) / 7;
-            let item = &mut lines[usize::from(week_in_month)][day.weekday() as usize];
-            *item = {
-                let it = tui::RawPrint::plain(format!("{n1:>2}"));
-                if now == day {
-                    it.styled(tui::Style {
-                        fg: Some(tui::Color::Green),
-                        ..Default::default()
-                    })
-                    .map_display(|styled| styled.to_string())
-                    .into()
-                } else {
-                    it.into()
-                }
-            };
-        }
-        let weekday_line =
-            ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"].map(|d| tui::RawPrint::plain(d).into());
-
-        let elem = tui::Elem::build_stack(tui::Axis::Y, |vstack| {
-            vstack.fit(
-                tui::RawPrint::plain(title)
-                    .styled(tui::Style {
-                        modifier: tui::Modifier {
-                            bold: true,
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    })
-                    .into(),
-            );
-            for line in std::iter::once(weekday_line).chain(lines) {
-                vstack.fit(tui::Elem::build_stack(tui::Axis::X, |hstack| {
-                    let mut first = true;
-                    for day in line {
-                        if !first {
-                            hstack.spacing(1);
-                        }
-                        first = false;
-
-                        hstack.fit(day);
-                    }
-                }));
-            }
-        });
-        Some(tui::OpenMenu::tooltip(elem))
+        calendar_tui(chrono::Local::now().date_naive()).map(tui::OpenMenu::tooltip)
     });
 
     let mut prev_minutes = 61;
@@ -337,7 +442,7 @@ async fn time_module(
         if prev_minutes != minute {
             let tui = tui::RawPrint::plain(now.format("%H:%M %d/%m").to_string());
             tui_tx.send_replace(BarTuiElem::Shared(
-                tui::Elem::from(tui).on_interact(&on_interact, None),
+                pill(tui.into()).on_interact(&on_interact, None),
             ));
 
             prev_minutes = minute;
@@ -353,6 +458,55 @@ async fn time_module(
     }
 }
 
+/// Shows a warning glyph if any module or client has failed, with the errors in a tooltip.
+async fn health_module(ModuleArgs { tui_tx, health, .. }: ModuleArgs) {
+    let mut health_rx = health.subscribe();
+    health_rx.mark_changed();
+    while let Some(()) = health_rx.changed().await.ok_or_debug() {
+        let mut any_failed = false;
+        let mut text = String::new();
+        for (name, health) in health_rx.borrow_and_update().iter() {
+            let status = match health {
+                Health::Starting | Health::Running => continue,
+                Health::Degraded(err) => format!("restarting after error: {err}"),
+                Health::Failed(err) => {
+                    any_failed = true;
+                    format!("failed: {err}")
+                }
+            };
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&format!("{name}: {status}"));
+        }
+        if text.is_empty() {
+            tui_tx.send_replace(BarTuiElem::Hide);
+            continue;
+        }
+
+        let tooltip = tui::InteractCallback::from_fn_ctx(text, |text, interact| {
+            (interact.kind == tui::InteractKind::Hover)
+                .then(|| tui::OpenMenu::tooltip(tui::PlainLines::new(text.clone()).into()))
+        });
+        let glyph = tui::Elem::themed(move |theme| {
+            tui::RawPrint::plain(" ")
+                .styled(theme.style(if any_failed {
+                    tui::ThemeRole::Critical
+                } else {
+                    tui::ThemeRole::Warning
+                }))
+                .into()
+        });
+        tui_tx.send_replace(BarTuiElem::Shared(tui::Elem::build_stack(
+            tui::Axis::X,
+            |stack| {
+                stack.fit(glyph.on_interact(tooltip, None));
+                stack.spacing(3);
+            },
+        )));
+    }
+}
+
 struct PulseModuleCtx {
     pulse: Arc<clients::pulse::PulseClient>,
     device_kind: clients::pulse::PulseDeviceKind,
@@ -387,12 +541,14 @@ async fn pulse_module(
                             PulseUpdateKind::ResetVolume
                         }
                         tui::InteractKind::Scroll(direction) => PulseUpdateKind::VolumeDelta(
-                            2 * match direction {
-                                tui::Direction::Up => 1,
-                                tui::Direction::Down => -1,
-                                tui::Direction::Left => -1,
-                                tui::Direction::Right => 1,
-                            },
+                            // Fine-grained with shift
+                            if interact.modifiers.shift { 1 } else { 2 }
+                                * match direction {
+                                    tui::Direction::Up => 1,
+                                    tui::Direction::Down => -1,
+                                    tui::Direction::Left => -1,
+                                    tui::Direction::Right => 1,
+                                },
                         ),
                         _ => return None,
                     },
@@ -402,6 +558,27 @@ async fn pulse_module(
         }
     });
 
+    let on_slider = tui::InteractCallback::from_fn({
+        let pulse = pulse.clone();
+        move |interact| {
+            match interact.kind {
+                tui::InteractKind::Click(tui::MouseButton::Left)
+                | tui::InteractKind::DragStart(tui::MouseButton::Left)
+                | tui::InteractKind::Drag(tui::MouseButton::Left) => {
+                    pulse
+                        .update_tx
+                        .send(PulseUpdate {
+                            target: device_kind,
+                            kind: PulseUpdateKind::SetVolume(interact.slider_value(tui::Axis::X)),
+                        })
+                        .ok_or_log();
+                }
+                _ => {}
+            }
+            None
+        }
+    });
+
     while let Some(()) = state_rx.changed().await.ok_or_debug() {
         let state = state_rx.borrow_and_update();
         let &PulseDeviceState { volume, muted, .. } = match device_kind {
@@ -410,28 +587,55 @@ async fn pulse_module(
         };
         drop(state);
 
-        tui_tx.send_replace(BarTuiElem::Shared(
-            tui::Elem::build_stack(tui::Axis::X, |stack| {
-                stack.fit(if muted {
-                    muted_sym.clone()
-                } else {
-                    unmuted_sym.clone()
-                });
+        tui_tx.send_replace(BarTuiElem::Shared(tui::Elem::build_stack(
+            tui::Axis::X,
+            |stack| {
+                stack.fit(
+                    tui::Elem::build_stack(tui::Axis::X, |stack| {
+                        stack.fit(if muted {
+                            muted_sym.clone()
+                        } else {
+                            unmuted_sym.clone()
+                        });
+                        stack.fit(
+                            tui::RawPrint::plain(format!(
+                                "{:>3}%",
+                                (volume * 100.0).round() as u32
+                            ))
+                            .into(),
+                        );
+                    })
+                    .on_interact(&on_interact, None),
+                );
+                stack.spacing(1);
                 stack.fit(
-                    tui::RawPrint::plain(format!("{:>3}%", (volume * 100.0).round() as u32)).into(),
+                    tui::Elem::themed(move |theme| {
+                        tui::Slider::new(tui::Axis::X, 5, volume)
+                            .styled(tui::Style {
+                                bg: Some(theme.muted),
+                                ..Default::default()
+                            })
+                            .into()
+                    })
+                    .on_interact(&on_slider, None),
                 );
-            })
-            .on_interact(&on_interact, None),
-        ));
+            },
+        )));
     }
 }
 async fn energy_module(
     ModuleArgs {
-        tui_tx, reload_rx, ..
+        tui_tx,
+        reload_rx,
+        health,
+        ..
     }: ModuleArgs,
 ) {
     use crate::clients::upower::*;
-    let energy = Arc::new(clients::upower::connect(reload_rx));
+    let energy = Arc::new(clients::upower::connect(
+        reload_rx,
+        health.reporter("UPower"),
+    ));
 
     let tooltip = tui::InteractCallback::from_fn_ctx(energy.clone(), |energy, interact| {
         if interact.kind != tui::InteractKind::Hover {
@@ -485,10 +689,16 @@ async fn energy_module(
 }
 async fn ppd_module(
     ModuleArgs {
-        tui_tx, reload_rx, ..
+        tui_tx,
+        reload_rx,
+        health,
+        ..
     }: ModuleArgs,
 ) {
-    let ppd = Arc::new(clients::ppd::connect(reload_rx));
+    let ppd = Arc::new(clients::ppd::connect(
+        reload_rx,
+        health.reporter("power-profiles-daemon"),
+    ));
 
     let on_interact =
         tui::InteractCallback::from_fn_ctx(ppd.clone(), |ppd, interact| match interact.kind {
@@ -532,11 +742,17 @@ async fn ppd_module(
 
 async fn tray_module(
     ModuleArgs {
-        tui_tx, reload_rx, ..
+        tui_tx,
+        reload_rx,
+        health,
+        ..
     }: ModuleArgs,
 ) {
     use crate::clients::tray::*;
-    let tray = Arc::new(clients::tray::connect(reload_rx));
+    let tray = Arc::new(clients::tray::connect(
+        reload_rx,
+        health.reporter("System tray"),
+    ));
 
     fn interact_cb(
         (addr, tray): &(Arc<str>, Arc<TrayClient>),
@@ -622,15 +838,18 @@ async fn tray_module(
                         .map(|menu_path| |id| icb(menu_path, id))
                         .as_ref(),
                 );
-                Some(tui::OpenMenu::context(tui::Elem::build_block(|block| {
-                    block.set_borders_at(tui::Borders::all());
-                    block.set_style(tui::Style {
-                        fg: Some(tui::Color::DarkGrey),
-                        ..Default::default()
-                    });
-                    block.set_lines(tui::LineSet::thick());
-                    block.set_inner(menu_tui);
-                })))
+                Some(
+                    tui::OpenMenu::context(tui::Elem::themed(move |theme| {
+                        tui::Elem::build_block(|block| {
+                            block.set_borders_at(tui::Borders::all());
+                            block.set_style(theme.style(tui::ThemeRole::Border));
+                            block.set_lines(tui::LineSet::thick());
+                            block.set_inner(menu_tui.clone());
+                        })
+                    }))
+                    // The tray is at the right edge of the bar
+                    .anchored(tui::MenuAnchor::Element(tui::MenuAlign::End)),
+                )
             }
             _ => None,
         }
@@ -686,93 +905,104 @@ async fn tray_module(
         });
         tui_tx.send_replace(BarTuiElem::Shared(tui));
     }
-    fn tray_menu_item_to_tui(
-        depth: u16,
-        item: &system_tray::menu::MenuItem,
-        on_interact: Option<&impl Fn(i32) -> tui::InteractCallback>,
-    ) -> Option<tui::Elem> {
-        use system_tray::menu::*;
-        let main_elem = match item {
-            MenuItem { visible: false, .. } => return None,
-            MenuItem {
-                visible: true,
-                menu_type: MenuType::Separator,
-                ..
-            } => tui::Elem::build_block(|block| {
+}
+
+fn tray_menu_item_to_tui(
+    depth: u16,
+    item: &system_tray::menu::MenuItem,
+    on_interact: Option<&impl Fn(i32) -> tui::InteractCallback>,
+) -> Option<tui::Elem> {
+    use system_tray::menu::*;
+    let main_elem = match item {
+        MenuItem { visible: false, .. } => return None,
+        MenuItem {
+            visible: true,
+            menu_type: MenuType::Separator,
+            ..
+        } => tui::Elem::themed(|theme| {
+            tui::Elem::build_block(|block| {
                 block.set_borders_at(tui::Borders {
                     top: true,
                     ..Default::default()
                 });
-                block.set_style(tui::Style {
-                    fg: Some(tui::Color::DarkGrey),
-                    ..Default::default()
-                });
-            }),
-            MenuItem {
-                id,
-                menu_type: MenuType::Standard,
-                label: Some(label),
-                enabled: _,
-                visible: true,
-                icon_name: _,
-                icon_data,
-                shortcut: _,
-                toggle_type: _, // TODO: implement toggle
-                toggle_state: _,
-                children_display: _,
-                disposition: _, // TODO: what to do with this?
-                submenu: _,
-            } => {
-                let elem = tui::Elem::build_stack(tui::Axis::X, |stack| {
-                    stack.spacing(depth + 1);
-                    if let Some(icon) = icon_data
-                        && let Some(img) =
-                            image::load_from_memory_with_format(icon, image::ImageFormat::Png)
-                                .context("Systray icon has invalid png data")
-                                .ok_or_log()
-                    {
-                        stack.fit(tui::Elem::image(
-                            img.into_rgba8(),
-                            tui::ImageSizeMode::FillAxis(tui::Axis::Y, 1),
-                        ));
-                        stack.spacing(1);
-                    }
-                    stack.fit(tui::PlainLines::new(label).into())
-                });
-
-                match on_interact {
-                    Some(mk_interact) => elem.on_interact(mk_interact(*id), None),
-                    None => elem,
+                block.set_style(theme.style(tui::ThemeRole::Border));
+            })
+        }),
+        MenuItem {
+            id,
+            menu_type: MenuType::Standard,
+            label: Some(label),
+            enabled: _,
+            visible: true,
+            icon_name: _,
+            icon_data,
+            shortcut: _,
+            toggle_type: _, // TODO: implement toggle
+            toggle_state: _,
+            children_display: _,
+            disposition: _, // TODO: what to do with this?
+            submenu: _,
+        } => {
+            let elem = tui::Elem::build_stack(tui::Axis::X, |stack| {
+                stack.spacing(depth + 1);
+                if let Some(icon) = icon_data
+                    && let Some(img) =
+                        image::load_from_memory_with_format(icon, image::ImageFormat::Png)
+                            .context("Systray icon has invalid png data")
+                            .ok_or_log()
+                {
+                    stack.fit(tui::Elem::image(
+                        img.into_rgba8(),
+                        tui::ImageSizeMode::FillAxis(tui::Axis::Y, 1),
+                    ));
+                    stack.spacing(1);
                 }
-            }
+                stack.fit(tui::PlainLines::new(label).into())
+            });
 
-            _ => {
-                log::error!("Unhandled menu item: {item:#?}");
-                return None;
+            match on_interact {
+                Some(mk_interact) => elem.on_interact(mk_interact(*id), None),
+                None => elem,
             }
-        };
+        }
 
-        Some(if item.submenu.is_empty() {
-            main_elem
-        } else {
-            tui::Elem::build_stack(tui::Axis::Y, |stack| {
-                stack.fit(main_elem);
-                stack.fit(tray_menu_to_tui(depth + 1, &item.submenu, on_interact));
-            })
+        _ => {
+            log::error!("Unhandled menu item: {item:#?}");
+            return None;
+        }
+    };
+
+    Some(if item.submenu.is_empty() {
+        main_elem
+    } else {
+        tui::Elem::build_stack(tui::Axis::Y, |stack| {
+            stack.fit(main_elem);
+            stack.fit(tray_menu_to_tui(depth + 1, &item.submenu, on_interact));
         })
-    }
+    })
+}
 
-    fn tray_menu_to_tui(
-        depth: u16,
-        items: &[system_tray::menu::MenuItem],
-        on_interact: Option<&impl Fn(i32) -> tui::InteractCallback>,
-    ) -> tui::Elem {
+const TRAY_MENU_MAX_LINES: u16 = 30;
+
+fn tray_menu_to_tui(
+    depth: u16,
+    items: &[system_tray::menu::MenuItem],
+    on_interact: Option<&impl Fn(i32) -> tui::InteractCallback>,
+) -> tui::Elem {
+    let items = items
+        .iter()
+        .filter_map(|item| tray_menu_item_to_tui(depth, item, on_interact));
+    if depth == 0 {
+        // Some applications have very long menus
+        tui::Elem::scroll("tray-menu", tui::Axis::Y, TRAY_MENU_MAX_LINES, items)
+    } else {
         tui::Elem::build_stack(tui::Axis::Y, |stack| {
             for item in items {
-                if let Some(item) = tray_menu_item_to_tui(depth, item, on_interact) {
-                    stack.fit(item)
-                }
+                stack.fit(item)
             }
         })
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,69 @@
+use bar_common::tui::{self, testing};
+
+fn menu_item(id: i32, label: &str) -> system_tray::menu::MenuItem {
+    system_tray::menu::MenuItem {
+        id,
+        label: Some(label.into()),
+        enabled: true,
+        visible: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn calendar() {
+    let today = chrono::NaiveDate::from_ymd_opt(2025, 2, 12).unwrap();
+    let rendered =
+        testing::render_min(&super::calendar_tui(today).unwrap(), &testing::sizing()).unwrap();
+
+    testing::assert_snapshot!("calendar", rendered.snapshot());
+    testing::assert_snapshot!("calendar_ansi", rendered.ansi());
+}
+
+#[test]
+fn tray_menu() {
+    use system_tray::menu::{MenuItem, MenuType};
+
+    let mut icon = Vec::new();
+    image::RgbaImage::new(16, 16)
+        .write_to(
+            &mut std::io::Cursor::new(&mut icon),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+
+    let items = [
+        MenuItem {
+            icon_data: Some(icon),
+            ..menu_item(1, "Open")
+        },
+        MenuItem {
+            menu_type: MenuType::Separator,
+            ..menu_item(2, "")
+        },
+        MenuItem {
+            submenu: vec![
+                menu_item(4, "Network"),
+                MenuItem {
+                    visible: false,
+                    ..menu_item(5, "Hidden")
+                },
+            ],
+            ..menu_item(3, "Settings")
+        },
+        menu_item(6, "Quit"),
+    ];
+    let on_interact = |_| tui::InteractCallback::from_fn(|_| None);
+
+    let with_interact = super::tray_menu_to_tui(0, &items, Some(&on_interact));
+    testing::assert_snapshot!(
+        "tray_menu",
+        testing::render_min(&with_interact, &testing::sizing())
+            .unwrap()
+            .snapshot(),
+    );
+
+    let without_interact = super::tray_menu_to_tui(0, &items, None::<&fn(i32) -> _>);
+    let rendered = testing::render_min(&without_interact, &testing::sizing()).unwrap();
+    assert!(rendered.regions().is_empty());
+}
@@ -0,0 +1,380 @@
+use std::{ffi::OsString, path::Path};
+
+use anyhow::Context as _;
+use bar_proc_mgr::{
+    TermEvent, TermRequest, TermUpdTx,
+    kitty::{Edge, KittyCommand, PanelSetting, ResizeAction, Spacing, SpacingKind},
+};
+use futures::future::BoxFuture;
+use tokio_util::sync::CancellationToken;
+
+use crate::{monitors::MonitorInfo, tui, utils::UnbTx};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanelKind {
+    Bar,
+    Menu,
+}
+impl PanelKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Bar => "bar",
+            Self::Menu => "menu",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackendCapabilities {
+    /// Terminal features that rendering may rely on.
+    pub features: tui::TermFeatures,
+    /// Whether `bar-proc-mgr` must support [`bar_proc_mgr::CAP_REMOTE_CONTROL`]
+    /// for the requests returned by the backend.
+    pub remote_control: bool,
+    /// Whether the menu reports focus changes through the socket in
+    /// [`SpawnArgs::watcher_sock`], by sending a single byte (0 = lost, 1 = gained).
+    pub focus_watcher: bool,
+}
+
+pub struct SpawnArgs<'a> {
+    pub kind: PanelKind,
+    /// The index of the menu among the menus of the monitor. Always 0 for the bar.
+    pub index: usize,
+    pub monitor: &'a MonitorInfo,
+    /// A directory that the backend can use for files that the terminal needs.
+    /// It is removed once the panel has connected.
+    pub tmpdir: &'a Path,
+    /// Only set for menus if [`BackendCapabilities::focus_watcher`] is set.
+    pub watcher_sock: Option<&'a Path>,
+    /// The theme at the time the panel is started. Later changes are applied with
+    /// [`tui::SetThemeColors`] and [`PanelBackend::set_menu_opacity`].
+    pub theme: &'a tui::Theme,
+    /// Cancelled when the panel should exit. Also cancelled by the backend if it exits.
+    pub cancel: &'a CancellationToken,
+}
+impl SpawnArgs<'_> {
+    /// `bar`, `menu`, `menu1`, ... Unique among the panels of the monitor.
+    pub fn panel_name(&self) -> String {
+        match self.index {
+            0 => self.kind.name().to_owned(),
+            index => format!("{}{index}", self.kind.name()),
+        }
+    }
+    pub fn log_name(&self) -> String {
+        format!("{}@{}", self.panel_name().to_uppercase(), self.monitor.name)
+    }
+}
+
+/// Starts `terminal`, which runs `bar-proc-mgr` (see [`PanelBackend::start_panel`]).
+pub async fn start_panel_process(
+    args: SpawnArgs<'_>,
+    terminal: tokio::process::Command,
+    term_ev_tx: UnbTx<TermEvent>,
+) -> anyhow::Result<TermUpdTx> {
+    bar_proc_mgr::start_generic_panel(
+        &args
+            .tmpdir
+            .join(format!("{}-term-socket.sock", args.panel_name())),
+        &args.log_name(),
+        terminal,
+        term_ev_tx,
+        args.cancel.clone(),
+    )
+    .await
+}
+
+/// Where and how large the menu should be, in scaled pixels and cells.
+#[derive(Debug, Clone, Copy)]
+pub struct MenuPlacement {
+    pub margin_left: u32,
+    pub margin_right: u32,
+    /// The distance from the top of the space below the bar, for popups opened
+    /// from other popups.
+    pub margin_top: u32,
+    pub lines: u16,
+}
+
+/// Decides which terminal the panels run in and how they are controlled.
+pub trait PanelBackend: Send + Sync + 'static {
+    fn capabilities(&self) -> BackendCapabilities;
+
+    /// Starts the terminal for a panel. Its first event must be [`TermEvent::Sizes`].
+    ///
+    /// Backends that run `bar-proc-mgr` ([`bar_proc_mgr::proc_mgr_program`]) in a terminal
+    /// can use [`start_panel_process`].
+    fn start_panel<'a>(
+        &'a self,
+        args: SpawnArgs<'a>,
+        term_ev_tx: UnbTx<TermEvent>,
+    ) -> BoxFuture<'a, anyhow::Result<TermUpdTx>>;
+
+    /// Requests to send after the panel has connected.
+    fn init_requests(&self, kind: PanelKind) -> Vec<TermRequest>;
+
+    /// The request that moves the menu into place, if the backend can do that.
+    fn place_menu(&self, placement: MenuPlacement) -> Option<TermRequest>;
+
+    /// The request that shows or hides the menu, if the backend can do that.
+    /// Otherwise, a hidden menu is drawn empty.
+    fn set_menu_visible(&self, visible: bool) -> Option<TermRequest>;
+
+    /// The request that changes the background opacity of the menu, if the backend
+    /// can do that.
+    fn set_menu_opacity(&self, opacity: f32) -> Option<TermRequest>;
+}
+
+/// Runs the panels as layer shell surfaces using `kitten panel`.
+#[derive(Debug, Clone)]
+pub struct KittyPanelBackend {
+    /// The edge that the bar is attached to, e.g. `top`.
+    pub edge: String,
+}
+impl Default for KittyPanelBackend {
+    fn default() -> Self {
+        Self { edge: "top".into() }
+    }
+}
+
+/// Adds an extra line and centers the content of the menu with padding of half a cell.
+const VERTICAL_PADDING: bool = true;
+
+impl KittyPanelBackend {
+    fn command(&self, args: &SpawnArgs<'_>) -> anyhow::Result<tokio::process::Command> {
+        let &SpawnArgs {
+            kind,
+            index: _,
+            monitor,
+            tmpdir,
+            watcher_sock,
+            theme,
+            cancel: _,
+        } = args;
+
+        let mut cmd = tokio::process::Command::new("kitten");
+        cmd.arg("panel").args([
+            format!("--output-name={}", monitor.name),
+            // Allow logging to $KITTY_STDIO_FORWARDED
+            "-o=forward_stdio=yes".into(),
+            // Do not use the system's kitty.conf
+            "--config=NONE".into(),
+            // disable hiding the mouse
+            "-o=mouse_hide_wait=0".into(),
+        ]);
+        // Basic look of the panels
+        for (name, color) in [
+            ("foreground", theme.foreground),
+            ("background", theme.background),
+        ] {
+            if let Some([r, g, b]) = tui::color_to_rgb(color) {
+                cmd.arg(format!("-o={name}=#{r:02x}{g:02x}{b:02x}"));
+            }
+        }
+
+        match kind {
+            PanelKind::Bar => {
+                cmd.args([
+                    // location of the bar
+                    format!("--edge={}", self.edge),
+                ]);
+            }
+            PanelKind::Menu => {
+                let watcher_py = tmpdir.join(format!("{}_watcher.py", args.panel_name()));
+                std::fs::write(&watcher_py, include_bytes!("menu_watcher.py"))
+                    .context("Failed to write menu watcher")?;
+                let mut watcher_arg = OsString::from("-o=watcher=");
+                watcher_arg.push(watcher_py);
+
+                // The background of menus is translucent and follows the theme
+                cmd.arg(format!("-o=background_opacity={}", theme.menu_opacity));
+                cmd.arg("-o=dynamic_background_opacity=yes");
+                cmd.arg(watcher_arg).args([
+                    // Configure remote control via socket
+                    "-o=allow_remote_control=socket-only",
+                    "--listen-on=unix:/tmp/kitty-bar-menu-panel-{kitty_pid}.sock",
+                    // Center within leftover pixels if cell size does not divide window size.
+                    "-o=placement_strategy=center",
+                    // location of the menu
+                    "--edge=top",
+                    // Window behavior of the menu panel. Makes panel
+                    // act as an overlay on top of other windows.
+                    // We do not want tilers to dedicate space to it.
+                    // Taken from the args that quick-access-terminal uses.
+                    "--exclusive-zone=0",
+                    "--override-exclusive-zone",
+                    "--layer=overlay",
+                    // Focus behavior of the panel. Since we cannot tell from
+                    // mouse events alone when the cursor leaves the panel
+                    // (since terminal mouse capture only gives us mouse
+                    // events inside the panel), we need external support for
+                    // hiding it automatically. We use a watcher to report
+                    // focus loss and hide the menu from the controller, which
+                    // knows whether the focus moved to a popup of this menu.
+                    "--focus-policy=on-demand",
+                    // Since we control resizes from the program and not from
+                    // a somewhat continuous drag-resize, debouncing between
+                    // resize and reloads is completely inappropriate and
+                    // just results in a larger delay between resize and
+                    // the old menu content being replaced with the new one.
+                    "-o=resize_debounce_time=0 0",
+                    // TODO: Mess with repaint_delay, input_delay
+                ]);
+                if let Some(sock) = watcher_sock {
+                    cmd.env("BAR_MENU_WATCHER_SOCK", sock);
+                }
+            }
+        }
+
+        cmd.arg(bar_proc_mgr::proc_mgr_program());
+        Ok(cmd)
+    }
+}
+impl PanelBackend for KittyPanelBackend {
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            features: tui::TermFeatures::all(),
+            remote_control: true,
+            focus_watcher: true,
+        }
+    }
+
+    fn start_panel<'a>(
+        &'a self,
+        args: SpawnArgs<'a>,
+        term_ev_tx: UnbTx<TermEvent>,
+    ) -> BoxFuture<'a, anyhow::Result<TermUpdTx>> {
+        Box::pin(async move {
+            let terminal = self.command(&args)?;
+            start_panel_process(args, terminal, term_ev_tx).await
+        })
+    }
+
+    fn init_requests(&self, kind: PanelKind) -> Vec<TermRequest> {
+        let mut reqs = Vec::new();
+        if kind == PanelKind::Menu {
+            reqs.extend(self.set_menu_visible(false));
+            if VERTICAL_PADDING {
+                // HACK: For some reason, using half font height padding at top and bottom
+                // shrinks the height by 2 cells. This way of doing it only works assuming
+                // that we do not have more than 1 pixel to spare for the padding and it
+                // can only be used for vertical padding of 1 cell in total.
+                reqs.push(TermRequest::RemoteControl(KittyCommand::SetSpacing(
+                    [Edge::Top, Edge::Bottom]
+                        .map(|edge| {
+                            let kind = SpacingKind::Padding;
+                            (Spacing { kind, edge }, Some(1.0))
+                        })
+                        .into(),
+                )));
+            }
+        }
+        reqs
+    }
+
+    fn place_menu(&self, placement: MenuPlacement) -> Option<TermRequest> {
+        let lines = placement.lines.saturating_add(VERTICAL_PADDING.into());
+        Some(kitty_place_menu(MenuPlacement { lines, ..placement }))
+    }
+
+    fn set_menu_visible(&self, visible: bool) -> Option<TermRequest> {
+        Some(kitty_set_menu_visible(visible))
+    }
+
+    fn set_menu_opacity(&self, opacity: f32) -> Option<TermRequest> {
+        Some(TermRequest::RemoteControl(
+            KittyCommand::SetBackgroundOpacity(opacity),
+        ))
+    }
+}
+
+/// Moves and resizes a kitty panel to the given placement.
+pub(crate) fn kitty_place_menu(placement: MenuPlacement) -> TermRequest {
+    let MenuPlacement {
+        margin_left,
+        margin_right,
+        margin_top,
+        lines,
+    } = placement;
+    TermRequest::RemoteControl(KittyCommand::ResizeOsWindow {
+        action: ResizeAction::OsPanel(vec![
+            PanelSetting::MarginLeft(margin_left),
+            PanelSetting::MarginRight(margin_right),
+            PanelSetting::MarginTop(margin_top),
+            PanelSetting::Lines(lines.into()),
+        ]),
+        incremental: true,
+    })
+}
+
+/// Shows or hides a kitty panel.
+pub(crate) fn kitty_set_menu_visible(visible: bool) -> TermRequest {
+    let action = if visible {
+        ResizeAction::Show
+    } else {
+        ResizeAction::Hide
+    };
+    TermRequest::RemoteControl(KittyCommand::ResizeOsWindow {
+        action,
+        incremental: false,
+    })
+}
+
+/// Runs each panel in an ordinary terminal window, e.g. for development.
+/// The menu is a regular window that is drawn empty instead of being hidden.
+#[derive(Debug, Clone)]
+pub struct TerminalWindowBackend {
+    /// The terminal and its arguments, e.g. `["kitty", "--hold"]`.
+    /// The program to run inside is appended as the last argument.
+    pub command: Vec<OsString>,
+    pub features: tui::TermFeatures,
+}
+impl TerminalWindowBackend {
+    /// Parses a whitespace-separated command, e.g. from an env var.
+    pub fn from_command_line(command: &str, features: tui::TermFeatures) -> Self {
+        Self {
+            command: command.split_whitespace().map(Into::into).collect(),
+            features,
+        }
+    }
+}
+
+impl PanelBackend for TerminalWindowBackend {
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            features: self.features,
+            remote_control: false,
+            focus_watcher: false,
+        }
+    }
+
+    fn start_panel<'a>(
+        &'a self,
+        args: SpawnArgs<'a>,
+        term_ev_tx: UnbTx<TermEvent>,
+    ) -> BoxFuture<'a, anyhow::Result<TermUpdTx>> {
+        Box::pin(async move {
+            let (program, rest) = self
+                .command
+                .split_first()
+                .context("Empty terminal command")?;
+            let mut terminal = tokio::process::Command::new(program);
+            terminal.args(rest).arg(bar_proc_mgr::proc_mgr_program());
+            start_panel_process(args, terminal, term_ev_tx).await
+        })
+    }
+
+    fn init_requests(&self, _: PanelKind) -> Vec<TermRequest> {
+        Vec::new()
+    }
+
+    fn place_menu(&self, _: MenuPlacement) -> Option<TermRequest> {
+        None
+    }
+
+    fn set_menu_visible(&self, _: bool) -> Option<TermRequest> {
+        None
+    }
+
+    fn set_menu_opacity(&self, _: f32) -> Option<TermRequest> {
+        None
+    }
+}
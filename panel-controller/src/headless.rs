@@ -0,0 +1,307 @@
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bar_proc_mgr::{
+    TermEvent, TermReply, TermRequest, TermUpdTx, TermUpdate, VirtualPanel,
+    kitty::{KittyCommand, PanelSetting, ResizeAction},
+};
+use futures::future::BoxFuture;
+use tokio_util::{sync::CancellationToken, time::FutureExt as _};
+
+use crate::{
+    backend::{self, BackendCapabilities, MenuPlacement, PanelBackend, PanelKind, SpawnArgs},
+    monitors::MonitorInfo,
+    tui,
+    utils::{UnbRx, UnbTx, unb_chan},
+};
+
+/// How long [`HeadlessPanel::settle`] waits for further updates.
+const SETTLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Runs the panels in-process on [`tui::VirtualTerm`]s instead of in a terminal,
+/// e.g. for integration tests of the controller.
+///
+/// Menus are controlled with the same remote control requests as with
+/// [`crate::KittyPanelBackend`], which the panels answer like `kitten panel` would.
+pub struct HeadlessBackend {
+    features: tui::TermFeatures,
+    font_size: tui::Vec2<u16>,
+    panels_tx: UnbTx<HeadlessPanel>,
+}
+impl HeadlessBackend {
+    /// Returns the backend and a receiver for the panels that it starts.
+    pub fn new(
+        features: tui::TermFeatures,
+        font_size: tui::Vec2<u16>,
+    ) -> (Self, UnbRx<HeadlessPanel>) {
+        let (panels_tx, panels_rx) = unb_chan();
+        (
+            Self {
+                features,
+                font_size,
+                panels_tx,
+            },
+            panels_rx,
+        )
+    }
+}
+
+impl PanelBackend for HeadlessBackend {
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            features: self.features,
+            remote_control: true,
+            focus_watcher: false,
+        }
+    }
+
+    fn start_panel<'a>(
+        &'a self,
+        args: SpawnArgs<'a>,
+        term_ev_tx: UnbTx<TermEvent>,
+    ) -> BoxFuture<'a, anyhow::Result<TermUpdTx>> {
+        Box::pin(async move {
+            let (upd_tx, conn) = bar_proc_mgr::start_virtual_panel(
+                vec![bar_proc_mgr::CAP_REMOTE_CONTROL.to_owned()],
+                term_ev_tx,
+            );
+            let cell_size = tui::Vec2 {
+                x: u16::try_from(args.monitor.width / u32::from(self.font_size.x))
+                    .unwrap_or(u16::MAX),
+                y: 1,
+            };
+            let panel = HeadlessPanel {
+                kind: args.kind,
+                index: args.index,
+                monitor: args.monitor.clone(),
+                font_size: self.font_size,
+                features: self.features,
+                conn,
+                screen: tui::VirtualTerm::new(cell_size),
+                position: tui::Vec2 { x: 0, y: 0 },
+                visible: true,
+                background_opacity: match args.kind {
+                    PanelKind::Bar => 1.0,
+                    PanelKind::Menu => args.theme.menu_opacity,
+                },
+                cancel: args.cancel.clone(),
+            };
+            panel.conn.send(TermEvent::Sizes(panel.sizes()))?;
+            self.panels_tx
+                .send(panel)
+                .context("Headless panel receiver was dropped")?;
+            Ok(upd_tx)
+        })
+    }
+
+    fn init_requests(&self, kind: PanelKind) -> Vec<TermRequest> {
+        match kind {
+            PanelKind::Bar => Vec::new(),
+            PanelKind::Menu => self.set_menu_visible(false).into_iter().collect(),
+        }
+    }
+
+    fn place_menu(&self, placement: MenuPlacement) -> Option<TermRequest> {
+        Some(backend::kitty_place_menu(placement))
+    }
+
+    fn set_menu_visible(&self, visible: bool) -> Option<TermRequest> {
+        Some(backend::kitty_set_menu_visible(visible))
+    }
+
+    fn set_menu_opacity(&self, opacity: f32) -> Option<TermRequest> {
+        Some(TermRequest::RemoteControl(
+            KittyCommand::SetBackgroundOpacity(opacity),
+        ))
+    }
+}
+
+/// A panel started by [`HeadlessBackend`]. Updates are only applied to the screen while
+/// one of the async methods runs. Dropping it acts like the terminal exiting.
+pub struct HeadlessPanel {
+    kind: PanelKind,
+    index: usize,
+    monitor: MonitorInfo,
+    font_size: tui::Vec2<u16>,
+    features: tui::TermFeatures,
+    conn: VirtualPanel,
+    screen: tui::VirtualTerm,
+    /// See [`Self::position`].
+    position: tui::Vec2<u32>,
+    visible: bool,
+    background_opacity: f32,
+    cancel: CancellationToken,
+}
+impl HeadlessPanel {
+    pub fn kind(&self) -> PanelKind {
+        self.kind
+    }
+    /// See [`SpawnArgs::index`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    pub fn monitor(&self) -> &MonitorInfo {
+        &self.monitor
+    }
+    pub fn screen(&self) -> &tui::VirtualTerm {
+        &self.screen
+    }
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+    pub fn background_opacity(&self) -> f32 {
+        self.background_opacity
+    }
+    /// The top left corner of the panel in physical pixels, as set by its margins.
+    /// For menus, this is relative to the space below the bar.
+    pub fn position(&self) -> tui::Vec2<u32> {
+        self.position
+    }
+
+    fn sizes(&self) -> tui::Sizes {
+        let cell_size = self.screen.size();
+        tui::Sizes {
+            cell_size,
+            pix_size: cell_size.combine(self.font_size, u16::saturating_mul),
+        }
+    }
+
+    /// Sends an event as if it came from the terminal.
+    pub fn send(&self, ev: TermEvent) -> anyhow::Result<()> {
+        self.conn.send(ev)
+    }
+
+    /// Sends a mouse event at the cell `pos`, which is reported as the center of the
+    /// cell if the panel has [`tui::TermFeatures::pixel_mouse`].
+    pub fn mouse(
+        &self,
+        kind: crossterm::event::MouseEventKind,
+        pos: tui::Vec2<u16>,
+    ) -> anyhow::Result<()> {
+        let pos = if self.features.pixel_mouse {
+            pos.combine(self.font_size, |p, f| {
+                p.saturating_mul(f).saturating_add(f / 2)
+            })
+        } else {
+            pos
+        };
+        self.send(TermEvent::Crossterm(crossterm::event::Event::Mouse(
+            crossterm::event::MouseEvent {
+                kind,
+                column: pos.x,
+                row: pos.y,
+                modifiers: crossterm::event::KeyModifiers::NONE,
+            },
+        )))
+    }
+    pub fn hover(&self, pos: tui::Vec2<u16>) -> anyhow::Result<()> {
+        self.mouse(crossterm::event::MouseEventKind::Moved, pos)
+    }
+    pub fn click(
+        &self,
+        pos: tui::Vec2<u16>,
+        button: crossterm::event::MouseButton,
+    ) -> anyhow::Result<()> {
+        self.mouse(crossterm::event::MouseEventKind::Down(button), pos)?;
+        self.mouse(crossterm::event::MouseEventKind::Up(button), pos)
+    }
+
+    /// Resizes the screen and reports it like a terminal would.
+    pub fn resize(&mut self, cell_size: tui::Vec2<u16>) -> anyhow::Result<()> {
+        self.screen.resize(cell_size);
+        self.send(TermEvent::Sizes(self.sizes()))?;
+        self.send(TermEvent::Crossterm(crossterm::event::Event::Resize(
+            cell_size.x,
+            cell_size.y,
+        )))
+    }
+
+    /// Applies updates until none arrive for a short time.
+    pub async fn settle(&mut self) -> anyhow::Result<()> {
+        while let Ok(upd) = self.conn.recv().timeout(SETTLE_TIMEOUT).await {
+            self.apply(upd.context("Panel was closed")?)?;
+        }
+        Ok(())
+    }
+
+    /// Applies updates until a frame was drawn.
+    pub async fn next_frame(&mut self) -> anyhow::Result<()> {
+        loop {
+            let upd = self.conn.recv().await.context("Panel was closed")?;
+            let is_frame = matches!(upd, TermUpdate::Frame(_));
+            self.apply(upd)?;
+            if is_frame {
+                return Ok(());
+            }
+        }
+    }
+
+    fn apply(&mut self, upd: TermUpdate) -> anyhow::Result<()> {
+        match upd {
+            TermUpdate::Print(bytes) | TermUpdate::Frame(bytes) => self.screen.feed(&bytes),
+            TermUpdate::Flush => {}
+            TermUpdate::Request { id, req } => {
+                let result = match req {
+                    TermRequest::RemoteControl(cmd) => self
+                        .remote_control(cmd)
+                        .map(|()| TermReply::RemoteControl(None)),
+                    req => Err(format!("{req:?} is not supported by the headless backend")),
+                };
+                self.send(TermEvent::Reply { id, result })?;
+            }
+            upd => log::warn!("Ignoring unsupported update {upd:?}"),
+        }
+        Ok(())
+    }
+
+    /// Converts scaled pixels of a margin to physical pixels.
+    fn unscale(&self, margin: u32) -> u32 {
+        (f64::from(margin) * self.monitor.scale).round() as u32
+    }
+
+    fn remote_control(&mut self, cmd: KittyCommand) -> Result<(), String> {
+        let action = match cmd {
+            KittyCommand::ResizeOsWindow { action, .. } => action,
+            KittyCommand::SetBackgroundOpacity(opacity) => {
+                self.background_opacity = opacity;
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        match action {
+            ResizeAction::Show => self.visible = true,
+            ResizeAction::Hide => self.visible = false,
+            ResizeAction::ToggleVisibility => self.visible = !self.visible,
+            ResizeAction::OsPanel(settings) => {
+                let mut size = self.screen.size();
+                let mut margins = None::<(u32, u32)>;
+                for setting in settings {
+                    match setting {
+                        PanelSetting::Lines(n) => size.y = n.try_into().unwrap_or(u16::MAX),
+                        PanelSetting::Columns(n) => size.x = n.try_into().unwrap_or(u16::MAX),
+                        PanelSetting::MarginLeft(n) => margins.get_or_insert_default().0 = n,
+                        PanelSetting::MarginRight(n) => margins.get_or_insert_default().1 = n,
+                        PanelSetting::MarginTop(n) => self.position.y = self.unscale(n),
+                        PanelSetting::MarginBottom(_) => {}
+                    }
+                }
+                if let Some((left, right)) = margins {
+                    self.position.x = self.unscale(left);
+                    // Margins are in scaled pixels, like with kitty
+                    let margins = f64::from(left + right) * self.monitor.scale;
+                    let width = (f64::from(self.monitor.width) - margins).max(0.0);
+                    size.x = (width / f64::from(self.font_size.x)) as u16;
+                }
+                if size != self.screen.size() {
+                    self.resize(size).map_err(|err| format!("{err:#}"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+impl Drop for HeadlessPanel {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
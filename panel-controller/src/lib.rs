@@ -1,10 +1,25 @@
+mod backend;
+mod headless;
 mod monitors;
+mod popups;
 pub(crate) use bar_common::*;
 
-use bar_proc_mgr::{TermEvent, TermUpdate};
+pub use backend::{
+    BackendCapabilities, KittyPanelBackend, MenuPlacement, PanelBackend, PanelKind, SpawnArgs,
+    TerminalWindowBackend, start_panel_process,
+};
+pub use headless::{HeadlessBackend, HeadlessPanel};
+pub use monitors::{MonitorEvent, MonitorInfo, fake as fake_monitors};
+
+use bar_proc_mgr::{RequestId, TermEvent, TermUpdTx, TermUpdate};
 use tempfile::TempDir;
 
-use std::{collections::HashMap, ffi::OsString, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::ControlFlow,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Context;
 use futures::StreamExt;
@@ -12,40 +27,128 @@ use tokio::task::JoinSet;
 use tokio_util::{sync::CancellationToken, time::FutureExt as _};
 
 use crate::{
-    monitors::MonitorInfo,
-    tui,
+    popups::{PixRect, Popup, PopupId, PopupStack, ShowMenu},
+    timing, tui,
     tui::MenuKind,
     utils::{
-        CancelDropGuard, ReloadTx, ResultExt, UnbRx, UnbTx, WatchRx, run_or_retry, unb_chan,
+        CancelDropGuard, ReloadTx, ResultExt, RetryPolicy, UnbRx, WatchRx, run_or_retry, unb_chan,
         watch_chan,
     },
 };
 
-// FIXME: Add to args of run_manager
-const EDGE: &str = "top";
-
-/// Adds an extra line and centers the content of the menu with padding of half a cell.
-const VERTICAL_PADDING: bool = true;
 const HORIZONTAL_PADDING: u16 = 4;
 
+/// How long to wait for the terminal to acknowledge a resize of the menu before drawing anyway.
+const MENU_RESIZE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The number of menu panels per monitor, i.e. how many popups can be open at once.
+pub const MENU_PANELS: usize = 3;
+
+/// How long to wait for another popup to gain focus after one lost it, e.g. when
+/// clicking from a menu into a popup that was opened from it.
+const FOCUS_LOSS_GRACE: Duration = Duration::from_millis(50);
+
+/// How long after an event of one panel an event of another panel may still have
+/// happened before it, since the events of different panels are not ordered.
+const POINTER_HANDOFF_GRACE: Duration = Duration::from_millis(50);
+
+/// Env var that makes the bar show how long the last frame took, from receiving the
+/// update that caused it to sending it to the terminal, and how many frames of this
+/// monitor were replaced by newer ones before the panels received them.
+pub const FRAME_TIME_OVERLAY_VAR: &str = "BAR_FRAME_TIME_OVERLAY";
+
+fn with_frame_time(
+    tui: &tui::Elem,
+    frame_time: Duration,
+    dropped_frames: u64,
+    theme: &tui::Theme,
+) -> tui::Elem {
+    let mut text = format!(" {:.1}ms", frame_time.as_secs_f64() * 1000.0);
+    if dropped_frames > 0 {
+        text += &format!(" {dropped_frames} dropped");
+    }
+    tui::Elem::build_stack(tui::Axis::X, |stack| {
+        stack.fill(1, tui.clone());
+        stack.fit(
+            tui::RawPrint::plain(text)
+                .styled(theme.style(tui::ThemeRole::Muted))
+                .into(),
+        );
+    })
+}
+
+/// When tooltips ([`MenuKind::Tooltip`]) are shown and hidden.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TooltipConfig {
+    /// How long the pointer has to rest on an element before its tooltip is shown.
+    /// If a tooltip is already shown, moving to another element switches immediately.
+    pub show_delay: Duration,
+    /// How long a tooltip stays open after the pointer has left the bar.
+    pub hide_delay: Duration,
+    /// Keep a tooltip open while the pointer is on it, so that its elements can be
+    /// interacted with. Otherwise, it closes once the pointer reaches it.
+    pub sticky: bool,
+}
+impl TooltipConfig {
+    /// Show tooltips on the first hover and close them on the first move away.
+    pub const INSTANT: Self = Self {
+        show_delay: Duration::ZERO,
+        hide_delay: Duration::ZERO,
+        sticky: false,
+    };
+}
+impl Default for TooltipConfig {
+    fn default() -> Self {
+        Self {
+            show_delay: Duration::from_millis(300),
+            hide_delay: Duration::from_millis(200),
+            sticky: true,
+        }
+    }
+}
+
 pub struct BarTuiState {
     // FIXME: Use Option<Elem> to hide
     pub by_monitor: HashMap<Arc<str>, tui::Elem>,
     pub fallback: tui::Elem,
+    pub tooltips: TooltipConfig,
+    /// The longest time between clicks that count as a double or triple click (see
+    /// [`tui::InteractArgs::clicks`]).
+    pub multi_click_interval: Duration,
+    /// The colors of the panels and of [`tui::Elem::themed`] elements.
+    pub theme: tui::Theme,
 }
 impl Default for BarTuiState {
     fn default() -> Self {
         Self {
             by_monitor: Default::default(),
             fallback: tui::Elem::empty(),
+            tooltips: Default::default(),
+            multi_click_interval: Duration::from_millis(400),
+            theme: Default::default(),
         }
     }
 }
 
-pub async fn run_controller(tui_rx: WatchRx<BarTuiState>, mut reload_tx: ReloadTx) {
+pub async fn run_controller(
+    tui_rx: WatchRx<BarTuiState>,
+    reload_tx: ReloadTx,
+    backend: Arc<dyn PanelBackend>,
+) {
+    run_controller_with_monitors(tui_rx, reload_tx, backend, crate::monitors::connect()).await
+}
+
+/// Like [`run_controller`], but with panels for the monitors reported by `monitor_rx`
+/// (e.g. [`fake_monitors`]) instead of those reported by `wlr-randr`.
+pub async fn run_controller_with_monitors(
+    tui_rx: WatchRx<BarTuiState>,
+    mut reload_tx: ReloadTx,
+    backend: Arc<dyn PanelBackend>,
+    monitor_rx: impl futures::Stream<Item = MonitorEvent>,
+) {
     let mut monitors_auto_cancel = HashMap::new();
 
-    let mut monitor_rx = crate::monitors::connect();
+    tokio::pin!(monitor_rx);
 
     while let Some(ev) = monitor_rx.next().await {
         for monitor in ev.removed() {
@@ -57,6 +160,7 @@ pub async fn run_controller(tui_rx: WatchRx<BarTuiState>, mut reload_tx: ReloadT
                 monitor: monitor.clone(),
                 cancel_monitor: cancel.clone(),
                 bar_rx: tui_rx.clone(),
+                backend: backend.clone(),
             }));
             monitors_auto_cancel.insert(monitor.name.clone(), CancelDropGuard::from(cancel));
         }
@@ -69,6 +173,7 @@ struct RunMonitorArgs {
     monitor: MonitorInfo,
     cancel_monitor: CancellationToken,
     bar_rx: WatchRx<BarTuiState>,
+    backend: Arc<dyn PanelBackend>,
 }
 
 async fn run_monitor(args: RunMonitorArgs) {
@@ -79,7 +184,7 @@ async fn run_monitor(args: RunMonitorArgs) {
         try_run_monitor,
         args,
         |it| it.with_context(|| format!("Failed to run panels for monitor {monitor}")),
-        Duration::from_secs(10),
+        RetryPolicy::default(),
         None,
     )
     .await;
@@ -88,25 +193,75 @@ async fn run_monitor(args: RunMonitorArgs) {
 
 struct Term {
     term_ev_rx: UnbRx<TermEvent>,
-    term_upd_tx: UnbTx<TermUpdate>,
+    term_upd_tx: TermUpdTx,
     sizes: tui::Sizes,
     layout: Option<tui::RenderedLayout>,
+    /// A frame that is drawn once the terminal has acknowledged the resize for it.
+    pending_draw: Option<PendingDraw>,
+}
+
+struct PendingDraw {
+    /// The request that resizes the panel for the frame.
+    resize: RequestId,
+    /// When to draw the frame even without a reply, see [`MENU_RESIZE_TIMEOUT`].
+    deadline: tokio::time::Instant,
+    frame: Vec<u8>,
 }
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum TermKind {
-    Menu,
+enum PanelId {
     Bar,
+    /// The menu panel at this index of [`StartedMonitorEnv::menus`].
+    Menu(usize),
 }
+
 enum Upd {
     BarTui,
-    Term(TermKind, TermEvent),
+    Term(PanelId, TermEvent),
+    Tooltip(TooltipTimer),
+    /// [`FOCUS_LOSS_GRACE`] has passed since a popup lost focus.
+    FocusSettled,
+    /// [`MENU_RESIZE_TIMEOUT`] has passed without a reply to a resize of this menu.
+    ResizeTimedOut(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TooltipTimer {
+    /// The pointer has rested on an element for [`TooltipConfig::show_delay`].
+    Show,
+    /// The pointer has been off the tooltip and the panel that it was opened from for
+    /// [`TooltipConfig::hide_delay`].
+    Hide(PopupId),
 }
 
 struct StartedMonitorEnv {
     bar: Term,
-    menu: Term,
+    menus: Vec<Term>,
     intern_upd_rx: UnbRx<Upd>,
     bar_tui_rx: WatchRx<tui::Elem>,
+    bar_state_rx: WatchRx<BarTuiState>,
+    /// The theme that the panels were started with.
+    theme: tui::Theme,
+}
+impl StartedMonitorEnv {
+    fn term(&mut self, panel: PanelId) -> &mut Term {
+        match panel {
+            PanelId::Bar => &mut self.bar,
+            PanelId::Menu(slot) => &mut self.menus[slot],
+        }
+    }
+}
+
+/// Waits for the next event of any menu panel that has not exited.
+fn next_menu_event(menus: &mut [Term]) -> impl Future<Output = (usize, TermEvent)> + '_ {
+    futures::future::poll_fn(move |cx| {
+        for (slot, menu) in menus.iter_mut().enumerate() {
+            if let std::task::Poll::Ready(Some(ev)) = menu.term_ev_rx.poll_next_unpin(cx) {
+                return std::task::Poll::Ready((slot, ev));
+            }
+        }
+        std::task::Poll::Pending
+    })
 }
 
 async fn try_run_monitor(args: &mut RunMonitorArgs) -> anyhow::Result<()> {
@@ -115,8 +270,19 @@ async fn try_run_monitor(args: &mut RunMonitorArgs) -> anyhow::Result<()> {
     let mut required_tasks = JoinSet::<anyhow::Result<std::convert::Infallible>>::new();
     let cancel = args.cancel_monitor.child_token();
     let _auto_cancel = CancelDropGuard::from(cancel.clone());
-    let env = try_init_monitor(&args.monitor, &args.bar_rx, &mut required_tasks, &cancel).await?;
-    required_tasks.spawn(run_monitor_mainloop(args.monitor.clone(), env));
+    let env = try_init_monitor(
+        &args.monitor,
+        &args.bar_rx,
+        &*args.backend,
+        &mut required_tasks,
+        &cancel,
+    )
+    .await?;
+    required_tasks.spawn(run_monitor_mainloop(
+        args.monitor.clone(),
+        args.backend.clone(),
+        env,
+    ));
 
     if let Some(Some(res)) = required_tasks
         .join_next()
@@ -140,235 +306,420 @@ async fn try_run_monitor(args: &mut RunMonitorArgs) -> anyhow::Result<()> {
 
 async fn run_monitor_mainloop(
     monitor: MonitorInfo,
+    backend: Arc<dyn PanelBackend>,
     mut env: StartedMonitorEnv,
 ) -> anyhow::Result<std::convert::Infallible> {
-    #[derive(Debug)]
-    struct ShowMenu {
-        kind: MenuKind,
-        pix_location: tui::Vec2<u32>,
-        cached_size: tui::Vec2<u16>,
-        sizing: tui::SizingArgs,
-        tui: tui::Elem,
-    }
-    let mut show_menu = None::<ShowMenu>;
+    let features = backend.capabilities().features;
+    let mut popups = PopupStack::new(env.menus.len());
+    // A tooltip that is shown once its timer fires, and the popup it was opened from
+    let mut pending_tooltip = None::<(Option<PopupId>, ShowMenu)>;
+    let mut tooltip_timer = None::<(tokio::time::Instant, TooltipTimer)>;
+    // The sticky tooltip that the pointer is on, and when it was last seen there
+    let mut pointer_on_tooltip = None::<(PopupId, tokio::time::Instant)>;
+    // Popups that lost focus, which are closed after FOCUS_LOSS_GRACE
+    let mut focus_lost = Vec::<PopupId>::new();
+    let mut focus_timer = None::<tokio::time::Instant>;
     let mut show_bar = Some(tui::Elem::empty());
+    let frame_time_overlay = std::env::var_os(FRAME_TIME_OVERLAY_VAR).is_some();
+    let mut last_frame_time = None::<Duration>;
+    let mut dropped_frames = 0;
+    // Kept across changes of the bar's tui, unlike the state of popups
+    let bar_states = tui::WidgetStates::default();
+    // The panels were started with this theme
+    let mut theme = env.theme;
+    // Updates to handle after the current one was rendered
+    let mut deferred = VecDeque::<Upd>::new();
     loop {
-        let mut rerender_menu = false;
         let mut rerender_bar = false;
 
+        let resize_deadline = (env.menus.iter().enumerate())
+            .filter_map(|(slot, menu)| Some((menu.pending_draw.as_ref()?.deadline, slot)))
+            .min();
         let upd = tokio::select! {
-            Some(ev) = env.bar.term_ev_rx.next() => Upd::Term(TermKind::Bar, ev),
-            Some(ev) = env.menu.term_ev_rx.next() => Upd::Term(TermKind::Menu, ev),
+            Some(upd) = async { deferred.pop_front() } => upd,
+            Some(ev) = env.bar.term_ev_rx.next() => Upd::Term(PanelId::Bar, ev),
+            (slot, ev) = next_menu_event(&mut env.menus) => Upd::Term(PanelId::Menu(slot), ev),
             Some(upd) = env.intern_upd_rx.next() => upd,
             Ok(()) = env.bar_tui_rx.changed() => Upd::BarTui,
+            Some(timer) = async move {
+                let (deadline, timer) = tooltip_timer?;
+                tokio::time::sleep_until(deadline).await;
+                Some(timer)
+            } => {
+                tooltip_timer = None;
+                Upd::Tooltip(timer)
+            }
+            Some(()) = async move {
+                tokio::time::sleep_until(focus_timer?).await;
+                Some(())
+            } => {
+                focus_timer = None;
+                Upd::FocusSettled
+            }
+            Some(slot) = async move {
+                let (deadline, slot) = resize_deadline?;
+                tokio::time::sleep_until(deadline).await;
+                Some(slot)
+            } => Upd::ResizeTimedOut(slot),
         };
+        let frame_span = timing::span("controller::frame");
         match upd {
             Upd::BarTui => {
                 if let Some(bar) = &mut show_bar {
                     *bar = env.bar_tui_rx.borrow_and_update().clone();
                     rerender_bar = true;
                 }
+                let new_theme = env.bar_state_rx.borrow().theme;
+                if new_theme != theme {
+                    theme = new_theme;
+                    for term in std::iter::once(&env.bar).chain(&env.menus) {
+                        set_term_colors(term, theme);
+                    }
+                    if let Some(req) = backend.set_menu_opacity(theme.menu_opacity) {
+                        for menu in &env.menus {
+                            menu.term_upd_tx.request(req.clone()).ok_or_debug();
+                        }
+                    }
+                    for slot in 0..env.menus.len() {
+                        if let Some(popup) = popups.in_slot_mut(slot) {
+                            popup.menu.sizing.theme = theme;
+                            popup.menu.cached_size =
+                                tui::calc_min_size(&popup.menu.tui, &popup.menu.sizing);
+                            popups.dirty.insert(slot);
+                        }
+                    }
+                }
             }
-            Upd::Term(term_kind, TermEvent::Crossterm(ev)) => match ev {
-                crossterm::event::Event::Mouse(ev) => {
-                    let Some(layout) = (match term_kind {
-                        TermKind::Menu => env.menu.layout.as_mut(),
-                        TermKind::Bar => env.bar.layout.as_mut(),
-                    }) else {
-                        continue;
-                    };
+            Upd::Term(panel, TermEvent::Crossterm(crossterm::event::Event::Mouse(ev))) => {
+                // The popup that the event is for, or `None` for the bar
+                let source = match panel {
+                    PanelId::Bar => None,
+                    PanelId::Menu(slot) => match popups.in_slot(slot) {
+                        Some(popup) => Some(popup.id),
+                        None => continue,
+                    },
+                };
+                let (tooltips, multi_click_interval) = {
+                    let state = env.bar_state_rx.borrow();
+                    (state.tooltips, state.multi_click_interval)
+                };
+                let term = env.term(panel);
+                let font_size = term.sizes.font_size();
+                let Some(layout) = &mut term.layout else {
+                    continue;
+                };
+                let states = layout.states().clone();
+                let sizing = tui::SizingArgs {
+                    font_size,
+                    features,
+                    states: states.clone(),
+                    theme,
+                };
 
-                    let tui::MouseEventResult {
-                        interact,
-                        callback,
-                        empty,
-                        changed,
-                        rerender,
-                        pix_location,
-                    } = layout.interpret_mouse_event(ev, env.bar.sizes.font_size());
-                    let is_hover = interact.kind == tui::InteractKind::Hover;
+                let tui::MouseEventResult {
+                    kind,
+                    targets,
+                    empty,
+                    changed,
+                    rerender,
+                    scrolled,
+                    pointer: _,
+                    pix_pointer,
+                } = layout.interpret_mouse_event(ev, &sizing, multi_click_interval);
+                let is_hover = kind == tui::InteractKind::Hover;
+                if scrolled {
+                    // Hovers whatever has moved under the pointer
+                    deferred.push_back(Upd::Term(
+                        panel,
+                        TermEvent::Crossterm(crossterm::event::Event::Mouse(
+                            crossterm::event::MouseEvent {
+                                kind: crossterm::event::MouseEventKind::Moved,
+                                ..ev
+                            },
+                        )),
+                    ));
+                }
 
-                    if term_kind == TermKind::Menu
-                        && let Some(menu) = &show_menu
-                        && menu.kind == MenuKind::Tooltip
+                if let Some(id) = source
+                    && popups
+                        .get(id)
+                        .is_some_and(|it| it.menu.kind == MenuKind::Tooltip)
+                {
+                    if tooltips.sticky {
+                        // The pointer has reached the tooltip, so it stays open
+                        pointer_on_tooltip = Some((id, tokio::time::Instant::now()));
+                        if let Some((_, TooltipTimer::Hide(hide))) = tooltip_timer
+                            && hide == id
+                        {
+                            tooltip_timer = None;
+                        }
+                    } else {
+                        popups.close(id);
+                    }
+                }
+                if rerender {
+                    match panel {
+                        PanelId::Bar => rerender_bar = true,
+                        PanelId::Menu(slot) => {
+                            popups.dirty.insert(slot);
+                        }
+                    }
+                }
+
+                // NOTE: This is not limited to changes of the hovered element, since the
+                // pointer may have returned from the tooltip to where it left the panel.
+                if empty {
+                    if !is_hover {
+                        popups.close_children(source, |_| true);
+                    } else if tooltips.hide_delay.is_zero() {
+                        popups.close_children(source, |it| it.menu.kind == MenuKind::Tooltip);
+                    } else if let Some(tooltip) = popups
+                        .children(source)
+                        .find(|it| it.menu.kind == MenuKind::Tooltip)
+                        && !matches!(
+                            tooltip_timer,
+                            Some((_, TooltipTimer::Hide(id))) if id == tooltip.id
+                        )
+                        // Events shortly after the pointer was on the tooltip may be
+                        // from before it got there, e.g. leaving the element that
+                        // opened it. Only later ones mean that it has returned.
+                        && !pointer_on_tooltip.is_some_and(|(id, seen)| {
+                            id == tooltip.id && seen.elapsed() < POINTER_HANDOFF_GRACE
+                        })
                     {
-                        show_menu = None;
-                        rerender_menu = true;
+                        pointer_on_tooltip = None;
+                        tooltip_timer = Some((
+                            tokio::time::Instant::now() + tooltips.hide_delay,
+                            TooltipTimer::Hide(tooltip.id),
+                        ));
                     }
+                }
 
-                    if rerender {
-                        match term_kind {
-                            TermKind::Menu => rerender_menu = true,
-                            TermKind::Bar => rerender_bar = true,
+                if changed || !is_hover {
+                    if pending_tooltip
+                        .as_ref()
+                        .is_some_and(|(parent, _)| *parent == source)
+                    {
+                        // The pointer has left the element whose tooltip is pending
+                        pending_tooltip = None;
+                        if let Some((_, TooltipTimer::Show)) = tooltip_timer {
+                            tooltip_timer = None;
                         }
                     }
 
-                    if changed || !is_hover {
-                        if empty
-                            && term_kind == TermKind::Bar
-                            && let Some(menu) = &show_menu
-                            && (!is_hover || menu.kind == MenuKind::Tooltip)
+                    // The first target that does not pass the event on handles it
+                    let handled = {
+                        let _span = timing::span("tui::InteractCallback::call");
+                        targets.into_iter().find_map(|target| {
+                            let (pix_location, pix_size) = (target.pix_location, target.pix_size);
+                            match target.call() {
+                                ControlFlow::Break(menu) => Some((menu, pix_location, pix_size)),
+                                ControlFlow::Continue(()) => None,
+                            }
+                        })
+                    };
+                    if let Some((
+                        Some(tui::OpenMenu {
+                            tui,
+                            menu_kind,
+                            anchor,
+                        }),
+                        pix_location,
+                        pix_size,
+                    )) = handled
+                    {
+                        let sizing = tui::SizingArgs {
+                            font_size: env.menus[0].sizes.font_size(),
+                            features,
+                            // Each popup has its own widget state
+                            states: Default::default(),
+                            theme,
+                        };
+                        let element = PixRect {
+                            pos: pix_location.combine(pix_size, |loc, size| loc - size / 2),
+                            size: pix_size,
+                        };
+                        let (element, pointer) = match source.and_then(|id| popups.get(id)) {
+                            // Menus of the bar are placed below it
+                            None => (
+                                PixRect {
+                                    pos: tui::Vec2 {
+                                        x: element.pos.x,
+                                        y: 0,
+                                    },
+                                    size: tui::Vec2 {
+                                        x: element.size.x,
+                                        y: 0,
+                                    },
+                                },
+                                tui::Vec2 {
+                                    x: pix_pointer.x,
+                                    y: 0,
+                                },
+                            ),
+                            Some(parent) => (
+                                PixRect {
+                                    pos: parent.origin.combine(element.pos, |o, p| o + p),
+                                    ..element
+                                },
+                                parent.origin.combine(pix_pointer, |o, p| o + p),
+                            ),
+                        };
+                        let menu = ShowMenu {
+                            cached_size: tui::calc_min_size(&tui, &sizing),
+                            sizing,
+                            tui,
+                            kind: menu_kind,
+                            anchor,
+                            element,
+                            pointer,
+                        };
+                        let tooltip_shown = popups
+                            .children(source)
+                            .any(|it| it.menu.kind == MenuKind::Tooltip);
+                        if menu_kind == MenuKind::Tooltip
+                            && !tooltip_shown
+                            && !tooltips.show_delay.is_zero()
                         {
-                            show_menu = None;
-                            rerender_menu = true;
+                            pending_tooltip = Some((source, menu));
+                            tooltip_timer = Some((
+                                tokio::time::Instant::now() + tooltips.show_delay,
+                                TooltipTimer::Show,
+                            ));
+                        } else {
+                            pending_tooltip = None;
+                            if let Some((_, TooltipTimer::Show)) = tooltip_timer {
+                                tooltip_timer = None;
+                            }
+                            // A context menu replaces everything that was opened from
+                            // the same panel, a tooltip only replaces tooltips.
+                            popups.close_children(source, |it| {
+                                menu_kind == MenuKind::Context || it.menu.kind == MenuKind::Tooltip
+                            });
+                            popups.open(source, menu);
                         }
+                    }
+                }
 
-                        if let Some(callback) = callback
-                            && let Some(tui::OpenMenu { tui, menu_kind }) = callback.call(interact)
-                        {
-                            let sizing = tui::SizingArgs {
-                                font_size: env.menu.sizes.font_size(),
-                            };
-                            show_menu = Some(ShowMenu {
-                                cached_size: tui::calc_min_size(&tui, &sizing),
-                                sizing,
-                                tui,
-                                kind: menu_kind,
-                                pix_location,
-                            });
-                            rerender_menu = true;
+                // A callback has changed the state of a widget
+                if states.take_changed() {
+                    match panel {
+                        PanelId::Bar => rerender_bar = true,
+                        PanelId::Menu(slot) => {
+                            if let Some(popup) = popups.in_slot_mut(slot) {
+                                let menu = &mut popup.menu;
+                                menu.cached_size = tui::calc_min_size(&menu.tui, &menu.sizing);
+                            }
+                            popups.dirty.insert(slot);
                         }
                     }
                 }
-                _ => {
-                    //
+            }
+            Upd::Term(_, TermEvent::Crossterm(_)) => {}
+            Upd::Tooltip(TooltipTimer::Show) => {
+                if let Some((parent, menu)) = pending_tooltip.take() {
+                    popups.close_children(parent, |it| it.menu.kind == MenuKind::Tooltip);
+                    popups.open(parent, menu);
                 }
-            },
-            Upd::Term(TermKind::Menu, TermEvent::Sizes(sizes)) => {
-                if sizes.font_size() != env.menu.sizes.font_size() {
-                    rerender_menu = true;
+            }
+            Upd::Tooltip(TooltipTimer::Hide(id)) => popups.close(id),
+            Upd::FocusSettled => {
+                for id in std::mem::take(&mut focus_lost) {
+                    if let Some(root) = popups.unfocused_root(id) {
+                        popups.close(root);
+                    }
                 }
-                env.menu.sizes = sizes;
             }
-            Upd::Term(TermKind::Bar, TermEvent::Sizes(sizes)) => {
+            Upd::Term(PanelId::Menu(slot), TermEvent::Sizes(sizes)) => {
+                let menu = &mut env.menus[slot];
+                if sizes.font_size() != menu.sizes.font_size() && popups.in_slot(slot).is_some() {
+                    popups.dirty.insert(slot);
+                }
+                menu.sizes = sizes;
+            }
+            Upd::Term(PanelId::Bar, TermEvent::Sizes(sizes)) => {
                 env.bar.sizes = sizes;
                 rerender_bar = true;
             }
-            Upd::Term(term_kind, TermEvent::FocusChange { is_focused }) => {
-                // FIXME: This only works because the menu doesnt lose focus while we are
+            Upd::Term(PanelId::Menu(slot), TermEvent::Reply { id, result })
+                if env.menus[slot]
+                    .pending_draw
+                    .as_ref()
+                    .is_some_and(|it| it.resize == id) =>
+            {
+                if let Err(err) = result {
+                    log::error!("Failed to resize menu: {err}");
+                }
+                finish_draw(&*backend, &mut env.menus[slot]);
+            }
+            Upd::Term(panel, TermEvent::Reply { id, result }) => match result {
+                Ok(reply) => log::trace!("{panel:?} request {id:?} succeeded: {reply:?}"),
+                Err(err) => log::error!("{panel:?} request {id:?} failed: {err}"),
+            },
+            Upd::ResizeTimedOut(slot) => {
+                log::error!("Timed out resizing menu");
+                finish_draw(&*backend, &mut env.menus[slot]);
+            }
+            Upd::Term(PanelId::Menu(slot), TermEvent::FocusChange { is_focused }) => {
+                // FIXME: This only works because the menus dont lose focus while we are
                 // on the bar, which forbids focus.
-                if !is_focused && term_kind == TermKind::Menu {
-                    show_menu = None;
-                    if let Some(layout) = &mut env.bar.layout
-                        && layout.ext_focus_loss()
-                    {
-                        rerender_bar = true;
-                    }
+                let Some(id) = popups.in_slot(slot).map(|it| it.id) else {
+                    continue;
+                };
+                if let Some(popup) = popups.get_mut(id) {
+                    popup.focused = is_focused;
+                }
+                if !is_focused {
+                    // Focus may have moved to a popup opened from this one
+                    focus_lost.push(id);
+                    focus_timer = Some(tokio::time::Instant::now() + FOCUS_LOSS_GRACE);
                 }
             }
+            Upd::Term(PanelId::Bar, TermEvent::FocusChange { .. }) => {}
+            // Unreachable: intercepted and logged by `run_term_inst_mgr`, never forwarded
+            Upd::Term(_, TermEvent::Log(_)) => {}
         }
 
-        if rerender_menu {
-            if show_menu.is_none()
-                && let Some(layout) = &mut env.bar.layout
+        for parent in std::mem::take(&mut popups.abandoned) {
+            let panel = match parent {
+                None => PanelId::Bar,
+                Some(id) => match popups.get(id) {
+                    Some(popup) => PanelId::Menu(popup.slot),
+                    None => continue,
+                },
+            };
+            if let Some(layout) = &mut env.term(panel).layout
                 && layout.ext_focus_loss()
             {
-                rerender_bar = true;
-            }
-
-            if let Some(ShowMenu {
-                pix_location: location,
-                cached_size: cached_tui_size,
-                ref tui,
-                ref sizing,
-                kind: _,
-            }) = show_menu
-            {
-                // HACK: This minimizes the rounding error for some reason (as far as I can tell).
-                let scale = (monitor.scale * 1000.0).ceil() / 1000.0;
-
-                // NOTE: There is no absolute positioning system, nor a way to directly specify the
-                // geometry (since this is controlled by the compositor). So we have to get creative by
-                // using the right and left margin to control both position and size of the panel.
-
-                let lines = cached_tui_size.y.saturating_add(VERTICAL_PADDING.into());
-
-                // Find the distance between window edge and center
-                let half_pix_w = {
-                    let cell_pix_w = u32::from(env.menu.sizes.font_size().x);
-                    let cell_w = cached_tui_size.x + HORIZONTAL_PADDING;
-                    let pix_w = u32::from(cell_w) * cell_pix_w;
-                    pix_w.div_ceil(2)
-                };
-
-                // Clamp position such that we fit. Note that this does not guarantee
-                // that there is enough space for the entire width.
-                let x = location.x.clamp(
-                    half_pix_w, //
-                    monitor.width.saturating_sub(half_pix_w),
-                );
-
-                // The left margin should be such that half the space is between
-                // left margin and x. Use saturating_sub so that the left
-                // margin becomes zero if the width would reach outside the screen.
-                let mleft = x.saturating_sub(half_pix_w);
-
-                // The right margin is calculated the same way, but starting from the right edge.
-                let mright = (monitor.width - x).saturating_sub(half_pix_w);
-
-                // The font size (on which cell->pixel conversion is based) and the monitor's
-                // size are in physical pixels. This makes sense because different monitors can
-                // have different scales, and the application should not be affected by that
-                // (this is not x11 after all).
-                // However, panels are bound to a monitor and the margins are in scaled pixels,
-                // so we have to make this correction.
-                let margin_left = (f64::from(mleft) / scale) as u32;
-                let margin_right = (f64::from(mright) / scale) as u32;
-
-                env.menu
-                    .term_upd_tx
-                    .send(TermUpdate::RemoteControl(vec![
-                        "resize-os-window".into(),
-                        "--incremental".into(),
-                        "--action=os-panel".into(),
-                        format!("margin-left={margin_left}").into(),
-                        format!("margin-right={margin_right}").into(),
-                        format!("lines={lines}").into(),
-                    ]))
-                    .ok_or_log();
-
-                let mut buf = Vec::new();
-
-                // NOTE: The terminal might not be done resizing at this point,
-                // which would cause issues if passing the terminal's size here.
-                // Passing the tui's desired size sidesteps this because kitty
-                // will rerender it correctly once the resize is done.
-                if let Some(layout) = tui::render(
-                    tui,
-                    tui::Area {
-                        size: cached_tui_size,
-                        pos: tui::Vec2 {
-                            x: HORIZONTAL_PADDING / 2,
-                            y: 0,
-                        },
-                    },
-                    &mut buf,
-                    sizing,
-                    env.menu.layout.as_ref(),
-                )
-                .context("Failed to draw menu")
-                .ok_or_log()
-                {
-                    env.menu.layout = Some(layout);
-                    env.menu
-                        .term_upd_tx
-                        .send(TermUpdate::Print(buf))
-                        .ok_or_log();
-                    env.menu.term_upd_tx.send(TermUpdate::Flush).ok_or_log();
+                match panel {
+                    PanelId::Bar => rerender_bar = true,
+                    PanelId::Menu(slot) => {
+                        popups.dirty.insert(slot);
+                    }
                 }
             }
+        }
 
-            let action = if show_menu.is_some() { "show" } else { "hide" };
-            env.menu
-                .term_upd_tx
-                .send(TermUpdate::RemoteControl(vec![
-                    "resize-os-window".into(),
-                    format!("--action={}", action).into(),
-                ]))
-                .ok_or_debug();
+        let rerender_menus = !popups.dirty.is_empty();
+        for slot in std::mem::take(&mut popups.dirty) {
+            draw_menu(
+                &monitor,
+                env.bar.sizes.pix_size.y,
+                &*backend,
+                &mut env.menus[slot],
+                popups.in_slot_mut(slot),
+            );
         }
 
         if rerender_bar && let Some(tui) = &show_bar {
+            let overlay;
+            let tui = match last_frame_time {
+                Some(frame_time) if frame_time_overlay => {
+                    overlay = with_frame_time(tui, frame_time, dropped_frames, &theme);
+                    &overlay
+                }
+                _ => tui,
+            };
             let mut buf = Vec::new();
             let Some(layout) = tui::render(
                 tui,
@@ -379,6 +730,9 @@ async fn run_monitor_mainloop(
                 &mut buf,
                 &tui::SizingArgs {
                     font_size: env.bar.sizes.font_size(),
+                    features,
+                    states: bar_states.clone(),
+                    theme,
                 },
                 env.bar.layout.as_ref(),
             )
@@ -388,35 +742,192 @@ async fn run_monitor_mainloop(
             };
             env.bar.layout = Some(layout);
 
-            env.bar
-                .term_upd_tx
-                .send(TermUpdate::Print(buf))
-                .ok_or_debug();
-            env.bar.term_upd_tx.send(TermUpdate::Flush).ok_or_debug();
+            env.bar.term_upd_tx.send_frame(buf).ok_or_debug();
+        }
+
+        if rerender_bar || rerender_menus {
+            last_frame_time = Some(frame_span.finish());
+
+            let dropped = std::iter::once(&env.bar)
+                .chain(&env.menus)
+                .map(|term| term.term_upd_tx.dropped_frames())
+                .sum();
+            if dropped != dropped_frames {
+                dropped_frames = dropped;
+                timing::counter("controller::dropped_frames", dropped);
+            }
         }
     }
 }
 
-async fn init_term(
-    sock_path: std::path::PathBuf,
-    log_name: String,
-    extra_args: impl IntoIterator<Item = OsString>,
-    extra_envs: impl IntoIterator<Item = (OsString, OsString)>,
-    cancel: &CancellationToken,
-) -> anyhow::Result<Term> {
-    let (term_upd_tx, term_upd_rx) = unb_chan();
-    let (term_ev_tx, mut term_ev_rx) = unb_chan();
+/// Places and draws the popup in its menu panel, or hides the panel if there is none.
+/// If the panel is resized, the frame is only drawn once the resize is acknowledged,
+/// see [`finish_draw`].
+fn draw_menu(
+    monitor: &MonitorInfo,
+    bar_pix_h: u16,
+    backend: &dyn PanelBackend,
+    menu: &mut Term,
+    popup: Option<&mut Popup>,
+) {
+    // Replaced by this draw
+    menu.pending_draw = None;
+    let Some(popup) = popup else {
+        menu.layout = None;
+        match backend.set_menu_visible(false) {
+            Some(req) => {
+                menu.term_upd_tx.request(req).ok_or_debug();
+            }
+            None => {
+                let mut buf = Vec::new();
+                crossterm::queue!(
+                    buf,
+                    crossterm::terminal::Clear(crossterm::terminal::ClearType::All)
+                )
+                .expect("writing to a Vec does not fail");
+                menu.term_upd_tx.send_frame(buf).ok_or_debug();
+            }
+        }
+        return;
+    };
+    let ShowMenu {
+        anchor,
+        element,
+        pointer,
+        cached_size: mut tui_size,
+        ref tui,
+        ref sizing,
+        kind: _,
+    } = popup.menu;
 
-    bar_proc_mgr::start_generic_panel(
-        &sock_path,
-        &log_name,
-        term_upd_rx,
-        extra_args,
-        extra_envs,
-        term_ev_tx,
-        cancel.clone(),
+    // HACK: This minimizes the rounding error for some reason (as far as I can tell).
+    let scale = (monitor.scale * 1000.0).ceil() / 1000.0;
+
+    // NOTE: There is no absolute positioning system, nor a way to directly specify the
+    // geometry (since this is controlled by the compositor). So we have to get creative by
+    // using the right and left margin to control both position and size of the panel.
+
+    let font_size = menu.sizes.font_size();
+    let screen = tui::Vec2 {
+        x: monitor.width,
+        y: monitor.height.saturating_sub(bar_pix_h.into()),
+    };
+    // Menus taller than the screen are cut off at the bottom instead of growing
+    // past the monitor. Use scroll elements to avoid this.
+    let max_lines = screen.y / u32::from(font_size.y).max(1);
+    tui_size.y = tui_size.y.min(max_lines.try_into().unwrap_or(u16::MAX));
+    let pix_size = tui::Vec2 {
+        x: u32::from(tui_size.x + HORIZONTAL_PADDING) * u32::from(font_size.x),
+        y: u32::from(tui_size.y) * u32::from(font_size.y),
+    };
+    let origin = popups::menu_position(anchor, element, pointer, pix_size, screen);
+    popup.origin = origin;
+
+    // The left margin is the position, the right margin is whatever space is left
+    // to the right of the menu. Use saturating_sub so that the right margin becomes
+    // zero if the width would reach outside the screen.
+    let mleft = origin.x;
+    let mright = monitor.width.saturating_sub(origin.x + pix_size.x);
+
+    // The font size (on which cell->pixel conversion is based) and the monitor's
+    // size are in physical pixels. This makes sense because different monitors can
+    // have different scales, and the application should not be affected by that
+    // (this is not x11 after all).
+    // However, panels are bound to a monitor and the margins are in scaled pixels,
+    // so we have to make this correction.
+    let margin_left = (f64::from(mleft) / scale) as u32;
+    let margin_right = (f64::from(mright) / scale) as u32;
+    let margin_top = (f64::from(origin.y) / scale) as u32;
+
+    let resize = backend
+        .place_menu(MenuPlacement {
+            margin_left,
+            margin_right,
+            margin_top,
+            lines: tui_size.y,
+        })
+        .and_then(|req| menu.term_upd_tx.request(req).ok_or_log());
+
+    let mut buf = Vec::new();
+
+    // NOTE: The compositor might not have applied the resize when the terminal
+    // acknowledges it, so the terminal's size can still be stale. Passing the
+    // tui's desired size sidesteps this because kitty will rerender it correctly
+    // once the resize is done.
+    let Some(layout) = tui::render(
+        tui,
+        tui::Area {
+            size: tui_size,
+            pos: tui::Vec2 {
+                x: HORIZONTAL_PADDING / 2,
+                y: 0,
+            },
+        },
+        &mut buf,
+        sizing,
+        menu.layout.as_ref(),
     )
-    .await?;
+    .context("Failed to draw menu")
+    .ok_or_log() else {
+        return;
+    };
+    menu.layout = Some(layout);
+    match resize {
+        Some(resize) => {
+            menu.pending_draw = Some(PendingDraw {
+                resize,
+                deadline: tokio::time::Instant::now() + MENU_RESIZE_TIMEOUT,
+                frame: buf,
+            });
+        }
+        None => show_frame(backend, menu, buf),
+    }
+}
+
+/// Draws the frame that [`draw_menu`] left pending, if any.
+fn finish_draw(backend: &dyn PanelBackend, menu: &mut Term) {
+    if let Some(PendingDraw { frame, .. }) = menu.pending_draw.take() {
+        show_frame(backend, menu, frame);
+    }
+}
+
+/// Sends the frame and shows the panel.
+fn show_frame(backend: &dyn PanelBackend, menu: &Term, frame: Vec<u8>) {
+    menu.term_upd_tx.send_frame(frame).ok_or_log();
+    if let Some(req) = backend.set_menu_visible(true) {
+        menu.term_upd_tx.request(req).ok_or_debug();
+    }
+}
+
+/// Sets the default colors of the panel to those of the theme.
+fn set_term_colors(term: &Term, theme: tui::Theme) {
+    let mut buf = Vec::new();
+    crossterm::queue!(buf, tui::SetThemeColors(theme)).expect("writing to a Vec does not fail");
+    term.term_upd_tx.send(TermUpdate::Print(buf)).ok_or_log();
+    term.term_upd_tx.send(TermUpdate::Flush).ok_or_log();
+}
+
+async fn init_term(backend: &dyn PanelBackend, spawn_args: SpawnArgs<'_>) -> anyhow::Result<Term> {
+    let (term_ev_tx, mut term_ev_rx) = unb_chan();
+
+    let kind = spawn_args.kind;
+    let log_name = spawn_args.log_name();
+    let term_upd_tx = backend.start_panel(spawn_args, term_ev_tx).await?;
+
+    if backend.capabilities().remote_control
+        && !term_upd_tx.has_capability(bar_proc_mgr::CAP_REMOTE_CONTROL)
+    {
+        anyhow::bail!("{log_name} does not support remote control");
+    }
+    for req in backend.init_requests(kind) {
+        term_upd_tx.request(req).ok_or_log();
+    }
+    if backend.capabilities().features.pixel_mouse {
+        let mut buf = Vec::new();
+        crossterm::queue!(buf, tui::EnablePixelMouse).expect("writing to a Vec does not fail");
+        term_upd_tx.send(TermUpdate::Print(buf)).ok_or_log();
+        term_upd_tx.send(TermUpdate::Flush).ok_or_log();
+    }
 
     let sizes = loop {
         match term_ev_rx.next().await {
@@ -433,6 +944,7 @@ async fn init_term(
     anyhow::Ok(Term {
         sizes,
         layout: Default::default(),
+        pending_draw: None,
         term_ev_rx,
         term_upd_tx,
     })
@@ -441,168 +953,126 @@ async fn init_term(
 async fn try_init_monitor(
     monitor: &MonitorInfo,
     bar_rx: &WatchRx<BarTuiState>,
+    backend: &dyn PanelBackend,
     required_tasks: &mut JoinSet<anyhow::Result<std::convert::Infallible>>,
     cancel: &CancellationToken,
 ) -> anyhow::Result<StartedMonitorEnv> {
+    let bar_state_rx = bar_rx.clone();
     let mut bar_rx = bar_rx.clone();
     let monitor = monitor.clone();
+    let theme = bar_rx.borrow().theme;
 
     let (intern_upd_tx, intern_upd_rx) = unb_chan();
 
     let tmpdir = tokio::task::spawn_blocking(TempDir::new).await??;
 
     let bar_fut = init_term(
-        tmpdir.path().join("bar-term-socket.sock"),
-        format!("BAR@{}", monitor.name),
-        [
-            format!("--output-name={}", monitor.name).into(),
-            // Allow logging to $KITTY_STDIO_FORWARDED
-            "-o=forward_stdio=yes".into(),
-            // Do not use the system's kitty.conf
-            "--config=NONE".into(),
-            // Basic look of the bar
-            "-o=foreground=white".into(),
-            "-o=background=black".into(),
-            // location of the bar
-            format!("--edge={}", EDGE).into(),
-            // disable hiding the mouse
-            "-o=mouse_hide_wait=0".into(),
-        ],
-        [],
-        cancel,
+        backend,
+        SpawnArgs {
+            kind: PanelKind::Bar,
+            index: 0,
+            monitor: &monitor,
+            tmpdir: tmpdir.path(),
+            watcher_sock: None,
+            theme: &theme,
+            cancel,
+        },
     );
 
-    let menu_fut = async {
-        let watcher_py = tmpdir.path().join("menu_watcher.py");
-
-        tokio::fs::write(&watcher_py, include_bytes!("menu_watcher.py")).await?;
-
-        let watcher_sock_path = tmpdir.path().join("menu_watcher.sock");
-        let watcher_sock = tokio::net::UnixListener::bind(&watcher_sock_path)?;
+    let menu_futs = (0..MENU_PANELS).map(|index| {
+        let (monitor, tmpdir) = (&monitor, tmpdir.path());
+        async move {
+            let watcher = if backend.capabilities().focus_watcher {
+                let path = tmpdir.join(format!("menu{index}_watcher.sock"));
+                let listener = tokio::net::UnixListener::bind(&path)?;
+                Some((path, listener))
+            } else {
+                None
+            };
 
-        let menu = init_term(
-            tmpdir.path().join("menu-term-socket.sock"),
-            format!("MENU@{}", monitor.name),
-            [
-                {
-                    let mut arg = OsString::from("-o=watcher=");
-                    arg.push(watcher_py);
-                    arg
+            let menu = init_term(
+                backend,
+                SpawnArgs {
+                    kind: PanelKind::Menu,
+                    index,
+                    monitor,
+                    tmpdir,
+                    watcher_sock: watcher.as_ref().map(|(path, _)| path.as_path()),
+                    theme: &theme,
+                    cancel,
                 },
-                format!("--output-name={}", monitor.name).into(),
-                // Configure remote control via socket
-                "-o=allow_remote_control=socket-only".into(),
-                "--listen-on=unix:/tmp/kitty-bar-menu-panel.sock".into(),
-                // Allow logging to $KITTY_STDIO_FORWARDED
-                "-o=forward_stdio=yes".into(),
-                // Do not use the system's kitty.conf
-                "--config=NONE".into(),
-                // Basic look of the menu
-                "-o=background_opacity=0.85".into(),
-                "-o=background=black".into(),
-                "-o=foreground=white".into(),
-                // Center within leftover pixels if cell size does not divide window size.
-                "-o=placement_strategy=center".into(),
-                // location of the menu
-                "--edge=top".into(),
-                // disable hiding the mouse
-                "-o=mouse_hide_wait=0".into(),
-                // Window behavior of the menu panel. Makes panel
-                // act as an overlay on top of other windows.
-                // We do not want tilers to dedicate space to it.
-                // Taken from the args that quick-access-terminal uses.
-                "--exclusive-zone=0".into(),
-                "--override-exclusive-zone".into(),
-                "--layer=overlay".into(),
-                // Focus behavior of the panel. Since we cannot tell from
-                // mouse events alone when the cursor leaves the panel
-                // (since terminal mouse capture only gives us mouse
-                // events inside the panel), we need external support for
-                // hiding it automatically. We use a watcher to be able
-                // to reset the menu state when this happens.
-                "--focus-policy=on-demand".into(),
-                "--hide-on-focus-loss".into(),
-                // Since we control resizes from the program and not from
-                // a somewhat continuous drag-resize, debouncing between
-                // resize and reloads is completely inappropriate and
-                // just results in a larger delay between resize and
-                // the old menu content being replaced with the new one.
-                "-o=resize_debounce_time=0 0".into(),
-                // TODO: Mess with repaint_delay, input_delay
-            ],
-            [("BAR_MENU_WATCHER_SOCK".into(), watcher_sock_path.into())],
-            cancel,
-        )
-        .await?;
-        menu.term_upd_tx
-            .send(TermUpdate::RemoteControl(vec![
-                "resize-os-window".into(),
-                "--action=hide".into(),
-            ]))
-            .ok_or_log();
-        if VERTICAL_PADDING {
-            // HACK: For some reason, using half font height padding at top and bottom
-            // shrinks the height by 2 cells. This way of doing it only works assuming
-            // that we do not have more than 1 pixel to spare for the padding and it
-            // can only be used for vertical padding of 1 cell in total.
-            menu.term_upd_tx
-                .send(TermUpdate::RemoteControl(vec![
-                    "set-spacing".into(),
-                    "padding-top=1".into(),
-                    "padding-bottom=1".into(),
-                ]))
-                .ok_or_log();
-        }
+            )
+            .await?;
 
-        let (s, _) = watcher_sock.accept().await?;
-        anyhow::Ok((menu, s))
-    };
+            let watcher_stream = match watcher {
+                Some((_, listener)) => Some(listener.accept().await?.0),
+                None => None,
+            };
+            anyhow::Ok((menu, watcher_stream))
+        }
+    });
 
-    let res = async { tokio::try_join!(bar_fut, menu_fut) }
+    let res = async { tokio::try_join!(bar_fut, futures::future::try_join_all(menu_futs)) }
         .timeout(Duration::from_secs(10))
         .await;
 
     // We have connected to the sockets, there is no need to keep the files around.
     tokio::task::spawn_blocking(move || drop(tmpdir));
 
-    let (bar, (menu, mut watcher_stream)) = res??;
+    let (bar, menus) = res??;
 
-    required_tasks.spawn({
-        let upd_tx = intern_upd_tx.clone();
-        async move {
-            use tokio::io::AsyncReadExt as _;
-            loop {
-                let byte = watcher_stream
-                    .read_u8()
-                    .await
-                    .context("Failed to read from watcher stream")?;
-
-                let parsed = match byte {
-                    0 => Upd::Term(TermKind::Menu, TermEvent::FocusChange { is_focused: false }),
-                    1 => Upd::Term(TermKind::Menu, TermEvent::FocusChange { is_focused: true }),
-                    _ => {
-                        log::error!("Unknown watcher event {byte}");
-                        continue;
-                    }
-                };
+    let mut menu_terms = Vec::with_capacity(menus.len());
+    for (slot, (menu, watcher_stream)) in menus.into_iter().enumerate() {
+        menu_terms.push(menu);
+        let Some(mut watcher_stream) = watcher_stream else {
+            continue;
+        };
+        required_tasks.spawn({
+            let upd_tx = intern_upd_tx.clone();
+            async move {
+                use tokio::io::AsyncReadExt as _;
+                loop {
+                    let byte = watcher_stream
+                        .read_u8()
+                        .await
+                        .context("Failed to read from watcher stream")?;
+
+                    let is_focused = match byte {
+                        0 => false,
+                        1 => true,
+                        _ => {
+                            log::error!("Unknown watcher event {byte}");
+                            continue;
+                        }
+                    };
 
-                upd_tx.send(parsed).ok_or_log();
+                    upd_tx
+                        .send(Upd::Term(
+                            PanelId::Menu(slot),
+                            TermEvent::FocusChange { is_focused },
+                        ))
+                        .ok_or_log();
+                }
             }
-        }
-    });
+        });
+    }
 
     let (bar_tui_tx, bar_tui_rx) = watch_chan(tui::Elem::empty());
     tokio::spawn(async move {
+        let mut last_theme = theme;
         while let Ok(()) = bar_rx.changed().await {
-            let tui = {
+            let (tui, theme) = {
                 let lock = bar_rx.borrow_and_update();
-                lock.by_monitor
+                let tui = lock
+                    .by_monitor
                     .get(&monitor.name)
                     .unwrap_or(&lock.fallback)
-                    .clone()
+                    .clone();
+                (tui, lock.theme)
             };
+            let theme_changed = std::mem::replace(&mut last_theme, theme) != theme;
             bar_tui_tx.send_if_modified(|cur| {
-                if cur.is_identical(&tui) {
+                if cur.is_identical(&tui) && !theme_changed {
                     return false;
                 }
                 *cur = tui;
@@ -613,8 +1083,10 @@ async fn try_init_monitor(
 
     Ok(StartedMonitorEnv {
         bar,
-        menu,
+        menus: menu_terms,
         intern_upd_rx,
         bar_tui_rx,
+        bar_state_rx,
+        theme,
     })
 }
@@ -2,7 +2,7 @@ use std::{collections::HashMap, sync::Arc};
 
 use futures::Stream;
 
-use bar_common::utils::unb_chan;
+use bar_common::utils::{UnbTx, unb_chan};
 
 #[derive(PartialEq, Clone, Debug)]
 pub struct MonitorInfo {
@@ -73,28 +73,30 @@ impl State {
             anyhow::anyhow!("Failed to deserialize output of wlr-randr --json: {err}")
         })?;
 
-        let data = Arc::new(
-            data.into_iter()
-                .filter(|md| md.enabled)
-                .filter_map(|md| {
-                    let MonitorData {
-                        name, scale, modes, ..
-                    } = md;
-                    let MonitorMode { width, height, .. } =
-                        modes.into_iter().find(|it| it.current)?;
-                    Some((
-                        name.clone(),
-                        MonitorInfo {
-                            name,
-                            scale,
-                            width,
-                            height,
-                        },
-                    ))
+        Ok(
+            self.update(data.into_iter().filter(|md| md.enabled).filter_map(|md| {
+                let MonitorData {
+                    name, scale, modes, ..
+                } = md;
+                let MonitorMode { width, height, .. } = modes.into_iter().find(|it| it.current)?;
+                Some(MonitorInfo {
+                    name,
+                    scale,
+                    width,
+                    height,
                 })
+            })),
+        )
+    }
+
+    fn update(&mut self, monitors: impl IntoIterator<Item = MonitorInfo>) -> Option<MonitorEvent> {
+        let data = Arc::new(
+            monitors
+                .into_iter()
+                .map(|it| (it.name.clone(), it))
                 .collect(),
         );
-        Ok(if data != self.data {
+        if data != self.data {
             let old_data = std::mem::replace(&mut self.data, data.clone());
             Some(MonitorEvent {
                 data,
@@ -102,7 +104,7 @@ impl State {
             })
         } else {
             None
-        })
+        }
     }
 }
 
@@ -129,3 +131,16 @@ pub fn connect() -> impl Stream<Item = MonitorEvent> {
     });
     rx
 }
+
+/// A monitor source that reports the monitors sent through the returned sender
+/// instead of querying `wlr-randr`, e.g. for tests.
+pub fn fake() -> (UnbTx<Vec<MonitorInfo>>, impl Stream<Item = MonitorEvent>) {
+    let (tx, rx) = unb_chan();
+    let events = futures::StreamExt::filter_map(
+        futures::StreamExt::scan(rx, State::default(), |state, monitors| {
+            std::future::ready(Some(state.update(monitors)))
+        }),
+        std::future::ready,
+    );
+    (tx, events)
+}
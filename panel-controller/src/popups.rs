@@ -0,0 +1,419 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    tui,
+    tui::{Corner, MenuAlign, MenuAnchor, MenuKind},
+};
+
+pub(crate) type PopupId = u64;
+
+/// A rectangle in physical pixels, relative to the top left corner of the space
+/// below the bar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct PixRect {
+    pub pos: tui::Vec2<u32>,
+    pub size: tui::Vec2<u32>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ShowMenu {
+    pub kind: MenuKind,
+    pub anchor: MenuAnchor,
+    /// The element that the popup was opened from. Elements of the bar have a
+    /// height of zero at the top edge.
+    pub element: PixRect,
+    /// Where the pointer was when the popup was opened (see [`PixRect`]).
+    pub pointer: tui::Vec2<u32>,
+    pub cached_size: tui::Vec2<u16>,
+    pub sizing: tui::SizingArgs,
+    pub tui: tui::Elem,
+}
+
+#[derive(Debug)]
+pub(crate) struct Popup {
+    pub id: PopupId,
+    /// The popup that this was opened from, or `None` if it was opened from the bar.
+    pub parent: Option<PopupId>,
+    /// The index of the menu panel that shows this popup.
+    pub slot: usize,
+    pub menu: ShowMenu,
+    /// The top left corner of the panel (see [`PixRect`]), once it has been placed.
+    pub origin: tui::Vec2<u32>,
+    pub focused: bool,
+}
+
+/// The open popups of a monitor, each shown in one of a fixed number of menu panels.
+///
+/// Closing a popup also closes the popups that were opened from it. Changes are
+/// recorded, so that the affected panels can be redrawn.
+#[derive(Debug)]
+pub(crate) struct PopupStack {
+    /// Parents always come before their children.
+    popups: Vec<Popup>,
+    next_id: PopupId,
+    slots: usize,
+    /// Menu panels that have to be drawn, placed or hidden.
+    pub dirty: BTreeSet<usize>,
+    /// The panels (`None` for the bar) whose last popup was closed. Their hover state
+    /// may be stale, since the pointer was on the popup.
+    pub abandoned: Vec<Option<PopupId>>,
+}
+impl PopupStack {
+    pub fn new(slots: usize) -> Self {
+        Self {
+            popups: Vec::new(),
+            next_id: 0,
+            slots,
+            dirty: BTreeSet::new(),
+            abandoned: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, id: PopupId) -> Option<&Popup> {
+        self.popups.iter().find(|it| it.id == id)
+    }
+    pub fn get_mut(&mut self, id: PopupId) -> Option<&mut Popup> {
+        self.popups.iter_mut().find(|it| it.id == id)
+    }
+    pub fn in_slot(&self, slot: usize) -> Option<&Popup> {
+        self.popups.iter().find(|it| it.slot == slot)
+    }
+    pub fn in_slot_mut(&mut self, slot: usize) -> Option<&mut Popup> {
+        self.popups.iter_mut().find(|it| it.slot == slot)
+    }
+    pub fn children(&self, parent: Option<PopupId>) -> impl Iterator<Item = &Popup> {
+        self.popups.iter().filter(move |it| it.parent == parent)
+    }
+
+    /// `id` and all popups that were opened from it, directly or indirectly.
+    fn subtree(&self, id: PopupId) -> Vec<PopupId> {
+        let mut ids = vec![id];
+        for popup in &self.popups {
+            if popup.parent.is_some_and(|it| ids.contains(&it)) {
+                ids.push(popup.id);
+            }
+        }
+        ids
+    }
+    fn ancestors(&self, mut id: Option<PopupId>) -> Vec<PopupId> {
+        let mut ids = Vec::new();
+        while let Some(popup) = id.and_then(|it| self.get(it)) {
+            ids.push(popup.id);
+            id = popup.parent;
+        }
+        ids
+    }
+
+    /// Whether `id` or any popup opened from it has focus.
+    pub fn subtree_focused(&self, id: PopupId) -> bool {
+        let ids = self.subtree(id);
+        self.popups
+            .iter()
+            .any(|it| it.focused && ids.contains(&it.id))
+    }
+
+    /// The outermost popup on the path from `id` to the bar that does not contain
+    /// the focus, i.e. what should be closed after `id` lost focus.
+    pub fn unfocused_root(&self, id: PopupId) -> Option<PopupId> {
+        let mut found = None;
+        for ancestor in self.ancestors(Some(id)) {
+            if self.subtree_focused(ancestor) {
+                break;
+            }
+            found = Some(ancestor);
+        }
+        found
+    }
+
+    /// Closes the popup and the popups that were opened from it.
+    pub fn close(&mut self, id: PopupId) {
+        let Some(parent) = self.get(id).map(|it| it.parent) else {
+            return;
+        };
+        let ids = self.subtree(id);
+        let dirty = &mut self.dirty;
+        self.popups.retain(|popup| {
+            let keep = !ids.contains(&popup.id);
+            if !keep {
+                dirty.insert(popup.slot);
+            }
+            keep
+        });
+        if self.children(parent).next().is_none() {
+            self.abandoned.push(parent);
+        }
+    }
+
+    /// Closes the popups opened from `parent` for which `pred` is true.
+    pub fn close_children(&mut self, parent: Option<PopupId>, pred: impl Fn(&Popup) -> bool) {
+        let ids: Vec<_> = self
+            .children(parent)
+            .filter(|it| pred(it))
+            .map(|it| it.id)
+            .collect();
+        for id in ids {
+            self.close(id);
+        }
+    }
+
+    /// Opens a popup from `parent`. If every menu panel is in use, the most recent
+    /// popup that `parent` was not opened from is closed to make room.
+    pub fn open(&mut self, parent: Option<PopupId>, menu: ShowMenu) -> Option<PopupId> {
+        if parent.is_some_and(|it| self.get(it).is_none()) {
+            return None;
+        }
+        let slot = match (0..self.slots).find(|&slot| self.in_slot(slot).is_none()) {
+            Some(slot) => slot,
+            None => {
+                let keep = self.ancestors(parent);
+                let Some(evict) = self.popups.iter().rev().find(|it| !keep.contains(&it.id)) else {
+                    log::warn!(
+                        "Not opening popup, all {} menu panels are in use",
+                        self.slots
+                    );
+                    return None;
+                };
+                let (evict, slot) = (evict.id, evict.slot);
+                self.close(evict);
+                slot
+            }
+        };
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.popups.push(Popup {
+            id,
+            parent,
+            slot,
+            menu,
+            origin: Default::default(),
+            focused: false,
+        });
+        self.dirty.insert(slot);
+        Some(id)
+    }
+}
+
+/// The top left corner of a menu of `size` on a `screen` of the given size (see
+/// [`PixRect`]), placed according to `anchor`.
+pub(crate) fn menu_position(
+    anchor: MenuAnchor,
+    element: PixRect,
+    pointer: tui::Vec2<u32>,
+    size: tui::Vec2<u32>,
+    screen: tui::Vec2<u32>,
+) -> tui::Vec2<u32> {
+    // The largest position at which the menu still fits
+    let max = screen.combine(size, u32::saturating_sub);
+    let fits_x = |x: Option<u32>| x.filter(|&x| x <= max.x);
+
+    let pos = match anchor {
+        MenuAnchor::Element(align) => {
+            let start = Some(element.pos.x);
+            let end = (element.pos.x + element.size.x).checked_sub(size.x);
+            // Flip to the other side of the element if the menu does not fit
+            let x = match align {
+                MenuAlign::Start => fits_x(start).or(fits_x(end)).or(start),
+                MenuAlign::End => fits_x(end).or(fits_x(start)).or(end),
+                MenuAlign::Center => (element.pos.x + element.size.x / 2).checked_sub(size.x / 2),
+            };
+            let below = element.pos.y + element.size.y;
+            let y = match element.pos.y.checked_sub(size.y) {
+                Some(above) if below > max.y => above,
+                _ => below,
+            };
+            tui::Vec2 {
+                x: x.unwrap_or(0),
+                y,
+            }
+        }
+        MenuAnchor::Corner(corner) => {
+            let (right, bottom) = match corner {
+                Corner::TopLeft => (false, false),
+                Corner::TopRight => (true, false),
+                Corner::BottomLeft => (false, true),
+                Corner::BottomRight => (true, true),
+            };
+            tui::Vec2 {
+                x: if right { max.x } else { 0 },
+                y: if bottom { max.y } else { 0 },
+            }
+        }
+        MenuAnchor::Fixed(offset) => offset,
+        MenuAnchor::Pointer => {
+            let flip = |pos: u32, size: u32, max: u32| match pos.checked_sub(size) {
+                Some(flipped) if pos > max => flipped,
+                _ => pos,
+            };
+            tui::Vec2 {
+                x: flip(pointer.x, size.x, max.x),
+                y: flip(pointer.y, size.y, max.y),
+            }
+        }
+    };
+
+    // Clamp the position such that we fit. Note that this does not guarantee
+    // that there is enough space for the entire menu.
+    pos.combine(max, u32::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show_menu() -> ShowMenu {
+        ShowMenu {
+            kind: MenuKind::Context,
+            anchor: MenuAnchor::default(),
+            element: PixRect::default(),
+            pointer: Default::default(),
+            cached_size: Default::default(),
+            sizing: tui::SizingArgs {
+                font_size: tui::Vec2 { x: 10, y: 20 },
+                features: tui::TermFeatures::all(),
+                states: Default::default(),
+                theme: Default::default(),
+            },
+            tui: tui::RawPrint::plain("menu").into(),
+        }
+    }
+
+    fn position(anchor: MenuAnchor, element: PixRect, pointer: tui::Vec2<u32>) -> tui::Vec2<u32> {
+        let size = tui::Vec2 { x: 100, y: 40 };
+        let screen = tui::Vec2 { x: 800, y: 580 };
+        menu_position(anchor, element, pointer, size, screen)
+    }
+
+    fn rect(x: u32, y: u32, w: u32, h: u32) -> PixRect {
+        PixRect {
+            pos: tui::Vec2 { x, y },
+            size: tui::Vec2 { x: w, y: h },
+        }
+    }
+
+    #[test]
+    fn menus_align_with_their_element() {
+        let pos =
+            |align, element| position(MenuAnchor::Element(align), element, Default::default());
+        let element = rect(300, 0, 60, 0);
+        assert_eq!(pos(MenuAlign::Start, element), tui::Vec2 { x: 300, y: 0 });
+        assert_eq!(pos(MenuAlign::End, element), tui::Vec2 { x: 260, y: 0 });
+        assert_eq!(pos(MenuAlign::Center, element), tui::Vec2 { x: 280, y: 0 });
+        // Below an element of another popup
+        let element = rect(300, 100, 60, 20);
+        assert_eq!(pos(MenuAlign::Start, element), tui::Vec2 { x: 300, y: 120 });
+    }
+
+    #[test]
+    fn menus_flip_to_the_other_side_of_their_element() {
+        let pos =
+            |align, element| position(MenuAnchor::Element(align), element, Default::default());
+        assert_eq!(
+            pos(MenuAlign::Start, rect(740, 0, 60, 0)),
+            tui::Vec2 { x: 700, y: 0 }
+        );
+        assert_eq!(
+            pos(MenuAlign::End, rect(10, 0, 60, 0)),
+            tui::Vec2 { x: 10, y: 0 }
+        );
+        // Above the element if there is no room below
+        assert_eq!(
+            pos(MenuAlign::Start, rect(300, 550, 60, 20)),
+            tui::Vec2 { x: 300, y: 510 }
+        );
+    }
+
+    #[test]
+    fn menus_are_clamped_to_the_screen() {
+        let pos = |anchor| position(anchor, rect(760, 0, 40, 0), Default::default());
+        assert_eq!(
+            pos(MenuAnchor::Element(MenuAlign::Center)),
+            tui::Vec2 { x: 700, y: 0 }
+        );
+        assert_eq!(
+            pos(MenuAnchor::Fixed(tui::Vec2 { x: 900, y: 900 })),
+            tui::Vec2 { x: 700, y: 540 }
+        );
+    }
+
+    #[test]
+    fn menus_at_corners_and_offsets() {
+        let pos = |anchor| position(anchor, PixRect::default(), Default::default());
+        assert_eq!(
+            pos(MenuAnchor::Corner(Corner::TopLeft)),
+            tui::Vec2 { x: 0, y: 0 }
+        );
+        assert_eq!(
+            pos(MenuAnchor::Corner(Corner::BottomRight)),
+            tui::Vec2 { x: 700, y: 540 }
+        );
+        assert_eq!(
+            pos(MenuAnchor::Fixed(tui::Vec2 { x: 100, y: 50 })),
+            tui::Vec2 { x: 100, y: 50 }
+        );
+    }
+
+    #[test]
+    fn menus_flip_to_the_other_side_of_the_pointer() {
+        let pos = |pointer| position(MenuAnchor::Pointer, PixRect::default(), pointer);
+        assert_eq!(
+            pos(tui::Vec2 { x: 300, y: 100 }),
+            tui::Vec2 { x: 300, y: 100 }
+        );
+        assert_eq!(
+            pos(tui::Vec2 { x: 750, y: 560 }),
+            tui::Vec2 { x: 650, y: 520 }
+        );
+    }
+
+    #[test]
+    fn closing_a_popup_closes_its_children() {
+        let mut popups = PopupStack::new(3);
+        let root = popups.open(None, show_menu()).unwrap();
+        let child = popups.open(Some(root), show_menu()).unwrap();
+        let other = popups.open(None, show_menu()).unwrap();
+        assert_eq!(popups.dirty, BTreeSet::from([0, 1, 2]));
+        popups.dirty.clear();
+
+        popups.close(root);
+        assert!(popups.get(root).is_none() && popups.get(child).is_none());
+        assert!(popups.get(other).is_some());
+        assert_eq!(popups.dirty, BTreeSet::from([0, 1]));
+        // The bar still has a popup
+        assert!(popups.abandoned.is_empty());
+
+        popups.close(other);
+        assert_eq!(popups.abandoned, [None]);
+        // Children of closed popups are not opened
+        assert_eq!(popups.open(Some(root), show_menu()), None);
+    }
+
+    #[test]
+    fn opening_evicts_the_most_recent_unrelated_popup() {
+        let mut popups = PopupStack::new(2);
+        let root = popups.open(None, show_menu()).unwrap();
+        let other = popups.open(None, show_menu()).unwrap();
+        let child = popups.open(Some(root), show_menu()).unwrap();
+        assert!(popups.get(other).is_none());
+        assert_eq!(popups.get(child).unwrap().slot, 1);
+
+        // The parent and its ancestors are kept
+        let grandchild = popups.open(Some(child), show_menu());
+        assert_eq!(grandchild, None);
+        assert!(popups.get(root).is_some() && popups.get(child).is_some());
+    }
+
+    #[test]
+    fn unfocused_root_stops_at_the_focus() {
+        let mut popups = PopupStack::new(3);
+        let root = popups.open(None, show_menu()).unwrap();
+        let child = popups.open(Some(root), show_menu()).unwrap();
+        let grandchild = popups.open(Some(child), show_menu()).unwrap();
+        assert_eq!(popups.unfocused_root(grandchild), Some(root));
+
+        popups.get_mut(child).unwrap().focused = true;
+        assert_eq!(popups.unfocused_root(grandchild), Some(grandchild));
+        assert!(popups.subtree_focused(root));
+        assert_eq!(popups.unfocused_root(child), None);
+    }
+}
@@ -0,0 +1,670 @@
+use std::{sync::Arc, time::Duration};
+
+use bar_common::{
+    tui,
+    utils::{ReloadTx, WatchTx},
+};
+use bar_panel_controller::{
+    BarTuiState, HeadlessBackend, HeadlessPanel, MENU_PANELS, MonitorInfo, PanelKind,
+    TooltipConfig, fake_monitors, run_controller_with_monitors,
+};
+use bar_proc_mgr::TermEvent;
+use crossterm::event::MouseButton;
+use futures::StreamExt as _;
+
+struct Harness {
+    bar: HeadlessPanel,
+    /// The first menu, which shows popups while no other popup is open.
+    menu: HeadlessPanel,
+    /// The other menus, by index.
+    extra_menus: Vec<HeadlessPanel>,
+    monitors_tx: bar_common::utils::UnbTx<Vec<MonitorInfo>>,
+    tui_tx: WatchTx<BarTuiState>,
+    _controller: tokio_util::task::AbortOnDropHandle<()>,
+}
+
+async fn start(features: tui::TermFeatures, bar_tui: tui::Elem) -> Harness {
+    start_with(features, bar_tui, TooltipConfig::INSTANT).await
+}
+
+async fn start_with(
+    features: tui::TermFeatures,
+    bar_tui: tui::Elem,
+    tooltips: TooltipConfig,
+) -> Harness {
+    let (backend, mut panels_rx) = HeadlessBackend::new(features, tui::Vec2 { x: 10, y: 20 });
+    let (monitors_tx, monitors) = fake_monitors();
+    let tui_tx = WatchTx::new(BarTuiState::default());
+    let controller =
+        tokio_util::task::AbortOnDropHandle::new(tokio::spawn(run_controller_with_monitors(
+            tui_tx.subscribe(),
+            ReloadTx::new(),
+            Arc::new(backend),
+            monitors,
+        )));
+    monitors_tx
+        .send(vec![MonitorInfo {
+            name: "HEADLESS-1".into(),
+            scale: 1.0,
+            width: 800,
+            height: 600,
+        }])
+        .unwrap();
+
+    let (mut bar, mut menus) = (None, Vec::new());
+    while bar.is_none() || menus.len() < MENU_PANELS {
+        let panel = panels_rx.next().await.expect("backend was dropped");
+        match panel.kind() {
+            PanelKind::Bar => bar = Some(panel),
+            PanelKind::Menu => menus.push(panel),
+        }
+    }
+    let mut bar = bar.unwrap();
+    menus.sort_by_key(HeadlessPanel::index);
+    let mut extra_menus = menus.split_off(1);
+    let mut menu = menus.pop().unwrap();
+
+    tui_tx.send_replace(BarTuiState {
+        fallback: bar_tui,
+        tooltips,
+        ..Default::default()
+    });
+    bar.settle().await.unwrap();
+    menu.settle().await.unwrap();
+    for menu in &mut extra_menus {
+        menu.settle().await.unwrap();
+    }
+
+    Harness {
+        bar,
+        menu,
+        extra_menus,
+        monitors_tx,
+        tui_tx,
+        _controller: controller,
+    }
+}
+
+/// Lets the controller handle the events that were sent so far, advancing the time
+/// by a millisecond.
+async fn catch_up() {
+    tokio::time::sleep(Duration::from_millis(1)).await;
+}
+
+impl Harness {
+    /// Settles all panels at once, since the controller only draws a menu once it
+    /// has replied to being resized.
+    async fn settle_all(&mut self) {
+        let mut panels = vec![&mut self.bar, &mut self.menu];
+        panels.extend(&mut self.extra_menus);
+        futures::future::try_join_all(panels.into_iter().map(HeadlessPanel::settle))
+            .await
+            .unwrap();
+    }
+}
+
+fn bar_tui() -> tui::Elem {
+    tui::Elem::build_stack(tui::Axis::X, |stack| {
+        stack.fit(tui::Elem::from(tui::RawPrint::plain("[tip]")).on_interact(
+            |args: tui::InteractArgs| {
+                (args.kind == tui::InteractKind::Hover)
+                    .then(|| tui::OpenMenu::tooltip(tui::RawPrint::plain("tooltip text").into()))
+            },
+            None,
+        ));
+        stack.spacing(2);
+        stack.fit(tui::Elem::from(tui::RawPrint::plain("[menu]")).on_interact(
+            |args: tui::InteractArgs| {
+                (args.kind == tui::InteractKind::Click(tui::MouseButton::Left))
+                    .then(|| tui::OpenMenu::context(tui::PlainLines::new("first\nsecond").into()))
+            },
+            tui::Elem::from(tui::RawPrint::plain("<menu>")),
+        ));
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn renders_bar() {
+    let h = start(tui::TermFeatures::all(), bar_tui()).await;
+
+    assert_eq!(h.bar.screen().size(), tui::Vec2 { x: 80, y: 1 });
+    assert_eq!(h.bar.screen().text(), "[tip]  [menu]");
+    assert!(!h.menu.is_visible());
+}
+
+#[tokio::test(start_paused = true)]
+async fn tooltip_follows_hover() {
+    let mut h = start(tui::TermFeatures::all(), bar_tui()).await;
+
+    h.bar.hover(h.bar.screen().find("[tip]").unwrap()).unwrap();
+    h.menu.settle().await.unwrap();
+    assert!(h.menu.is_visible());
+    assert_eq!(h.menu.screen().text(), "  tooltip text");
+    assert_eq!(h.menu.screen().size(), tui::Vec2 { x: 16, y: 1 });
+
+    h.bar.hover(tui::Vec2 { x: 40, y: 0 }).unwrap();
+    h.menu.settle().await.unwrap();
+    assert!(!h.menu.is_visible());
+}
+
+#[tokio::test(start_paused = true)]
+async fn tooltip_waits_for_show_delay() {
+    let mut h = start_with(
+        tui::TermFeatures::all(),
+        bar_tui(),
+        TooltipConfig {
+            show_delay: Duration::from_millis(300),
+            ..TooltipConfig::INSTANT
+        },
+    )
+    .await;
+    let tip = h.bar.screen().find("[tip]").unwrap();
+
+    // Passing over the element does not show the tooltip
+    h.bar.hover(tip).unwrap();
+    h.bar.hover(tui::Vec2 { x: 40, y: 0 }).unwrap();
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    h.menu.settle().await.unwrap();
+    assert!(!h.menu.is_visible());
+
+    h.bar.hover(tip).unwrap();
+    h.menu.settle().await.unwrap();
+    assert!(!h.menu.is_visible());
+    tokio::time::timeout(Duration::from_secs(5), h.menu.next_frame())
+        .await
+        .expect("tooltip was not shown")
+        .unwrap();
+    h.menu.settle().await.unwrap();
+    assert!(h.menu.is_visible());
+    assert_eq!(h.menu.screen().text(), "  tooltip text");
+}
+
+#[tokio::test(start_paused = true)]
+async fn sticky_tooltip_stays_open_on_hover() {
+    let mut h = start_with(
+        tui::TermFeatures::all(),
+        bar_tui(),
+        TooltipConfig {
+            hide_delay: Duration::from_millis(300),
+            sticky: true,
+            ..TooltipConfig::INSTANT
+        },
+    )
+    .await;
+
+    h.bar.hover(h.bar.screen().find("[tip]").unwrap()).unwrap();
+    h.menu.settle().await.unwrap();
+    assert!(h.menu.is_visible());
+
+    // The pointer reaches the tooltip before the bar reports that it left the
+    // element, since events of different panels are not ordered
+    h.menu
+        .hover(h.menu.screen().find("tooltip").unwrap())
+        .unwrap();
+    catch_up().await;
+    h.bar.hover(tui::Vec2 { x: 40, y: 0 }).unwrap();
+    catch_up().await;
+    tokio::time::advance(Duration::from_millis(400)).await;
+    h.menu.settle().await.unwrap();
+    assert!(h.menu.is_visible());
+
+    // Returning to the bar hides it
+    h.bar.hover(tui::Vec2 { x: 41, y: 0 }).unwrap();
+    catch_up().await;
+    tokio::time::advance(Duration::from_millis(400)).await;
+    h.menu.settle().await.unwrap();
+    assert!(!h.menu.is_visible());
+}
+
+#[tokio::test(start_paused = true)]
+async fn context_menu_opens_on_click_and_closes_on_focus_loss() {
+    let mut h = start(tui::TermFeatures::all(), bar_tui()).await;
+
+    let pos = h.bar.screen().find("[menu]").unwrap();
+    h.bar.click(pos, MouseButton::Left).unwrap();
+    h.menu.settle().await.unwrap();
+    h.bar.settle().await.unwrap();
+    assert!(h.menu.is_visible());
+    assert_eq!(h.menu.screen().text(), "  first\n  second");
+    assert_eq!(h.bar.screen().text(), "[tip]  <menu>");
+
+    h.menu
+        .send(TermEvent::FocusChange { is_focused: false })
+        .unwrap();
+    h.bar.settle().await.unwrap();
+    assert_eq!(h.bar.screen().text(), "[tip]  [menu]");
+}
+
+#[tokio::test(start_paused = true)]
+async fn tooltip_and_context_menu_coexist() {
+    let mut h = start(tui::TermFeatures::all(), bar_tui()).await;
+
+    let pos = h.bar.screen().find("[menu]").unwrap();
+    h.bar.click(pos, MouseButton::Left).unwrap();
+    h.settle_all().await;
+    h.bar.hover(h.bar.screen().find("[tip]").unwrap()).unwrap();
+    h.settle_all().await;
+
+    assert!(h.menu.is_visible());
+    assert_eq!(h.menu.screen().text(), "  first\n  second");
+    assert!(h.extra_menus[0].is_visible());
+    assert_eq!(h.extra_menus[0].screen().text(), "  tooltip text");
+}
+
+#[tokio::test(start_paused = true)]
+async fn drags_and_multi_clicks_are_reported() {
+    use crossterm::event::MouseEventKind as MK;
+
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let slider = tui::Elem::from(tui::RawPrint::plain("[slider]")).on_interact(
+        {
+            let events = events.clone();
+            move |args: tui::InteractArgs| {
+                if args.kind != tui::InteractKind::Hover {
+                    events
+                        .lock()
+                        .unwrap()
+                        .push((args.kind, args.clicks, args.pos.x));
+                }
+                None
+            }
+        },
+        None,
+    );
+    let bar_tui = tui::Elem::build_stack(tui::Axis::X, |stack| stack.fit(slider));
+    let mut h = start(tui::TermFeatures::all(), bar_tui).await;
+    let at = |x| tui::Vec2 { x, y: 0 };
+
+    h.bar.click(at(1), MouseButton::Left).unwrap();
+    h.bar.click(at(1), MouseButton::Left).unwrap();
+    // Dragging past the end of the element
+    h.bar.mouse(MK::Down(MouseButton::Left), at(3)).unwrap();
+    h.bar.mouse(MK::Drag(MouseButton::Left), at(5)).unwrap();
+    h.bar.mouse(MK::Drag(MouseButton::Left), at(20)).unwrap();
+    h.bar.mouse(MK::Up(MouseButton::Left), at(20)).unwrap();
+    h.bar.settle().await.unwrap();
+
+    use tui::{InteractKind as IK, MouseButton::Left};
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            (IK::Click(Left), 1, 15),
+            (IK::Release(Left), 0, 15),
+            (IK::Click(Left), 2, 15),
+            (IK::Release(Left), 0, 15),
+            (IK::Click(Left), 1, 35),
+            (IK::DragStart(Left), 0, 55),
+            (IK::Drag(Left), 0, 80),
+            (IK::DragEnd(Left), 0, 80),
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn slider_reports_the_value_under_the_pointer() {
+    let values = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let slider = tui::Elem::from(tui::Slider::new(tui::Axis::X, 8, 0.3)).on_interact(
+        {
+            let values = values.clone();
+            move |args: tui::InteractArgs| {
+                if let tui::InteractKind::Click(_) | tui::InteractKind::Drag(_) = args.kind {
+                    values.lock().unwrap().push(args.slider_value(tui::Axis::X));
+                }
+                None
+            }
+        },
+        None,
+    );
+    let bar_tui = tui::Elem::build_stack(tui::Axis::X, |stack| stack.fit(slider));
+    let mut h = start(tui::TermFeatures::all(), bar_tui).await;
+
+    assert_eq!(h.bar.screen().text(), "██▍");
+
+    h.bar
+        .click(tui::Vec2 { x: 1, y: 0 }, MouseButton::Left)
+        .unwrap();
+    h.bar
+        .click(tui::Vec2 { x: 7, y: 0 }, MouseButton::Left)
+        .unwrap();
+    h.bar.settle().await.unwrap();
+    assert_eq!(*values.lock().unwrap(), [15.0 / 80.0, 75.0 / 80.0]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn events_bubble_from_nested_elements() {
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let record = |name: &'static str, bubble_right: bool| {
+        let events = events.clone();
+        move |args: tui::InteractArgs| {
+            if let tui::InteractKind::Click(button) = &args.kind {
+                if bubble_right && *button == tui::MouseButton::Right {
+                    args.bubble();
+                } else {
+                    events.lock().unwrap().push(name);
+                }
+            }
+            None
+        }
+    };
+    let icon =
+        tui::Elem::from(tui::RawPrint::plain("[icon]")).on_interact(record("icon", true), None);
+    let row = tui::Elem::build_stack(tui::Axis::X, |stack| {
+        stack.fit(tui::RawPrint::plain("row ").into());
+        stack.fit(icon);
+    })
+    .on_interact(record("row", false), None);
+    let bar_tui = tui::Elem::build_stack(tui::Axis::X, |stack| stack.fit(row));
+    let mut h = start(tui::TermFeatures::all(), bar_tui).await;
+
+    let icon = h.bar.screen().find("[icon]").unwrap();
+    h.bar.click(icon, MouseButton::Left).unwrap();
+    h.bar.click(icon, MouseButton::Right).unwrap();
+    h.bar
+        .click(tui::Vec2 { x: 0, y: 0 }, MouseButton::Left)
+        .unwrap();
+    h.bar.settle().await.unwrap();
+    assert_eq!(*events.lock().unwrap(), ["icon", "row", "row"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn widget_state_survives_rerenders() {
+    let expander = tui::Elem::stateful("expander", |expanded: &bool| {
+        let label = if *expanded { "[-] details" } else { "[+]" };
+        tui::Elem::from(tui::RawPrint::plain(label)).on_interact(
+            |args: tui::InteractArgs| {
+                if args.kind == tui::InteractKind::Click(tui::MouseButton::Left) {
+                    args.states
+                        .update("expander".into(), |expanded: &mut bool| *expanded ^= true);
+                }
+                None
+            },
+            None,
+        )
+    });
+    let bar_tui = tui::Elem::build_stack(tui::Axis::X, |stack| stack.fit(expander));
+    let mut h = start(tui::TermFeatures::all(), bar_tui.clone()).await;
+    assert_eq!(h.bar.screen().text(), "[+]");
+
+    h.bar
+        .click(tui::Vec2 { x: 1, y: 0 }, MouseButton::Left)
+        .unwrap();
+    h.bar.settle().await.unwrap();
+    assert_eq!(h.bar.screen().text(), "[-] details");
+
+    // Like a module pushing a new tui
+    h.tui_tx.send_replace(BarTuiState {
+        fallback: bar_tui,
+        tooltips: TooltipConfig::INSTANT,
+        ..Default::default()
+    });
+    h.bar.settle().await.unwrap();
+    assert_eq!(h.bar.screen().text(), "[-] details");
+
+    h.bar
+        .click(tui::Vec2 { x: 1, y: 0 }, MouseButton::Left)
+        .unwrap();
+    h.bar.settle().await.unwrap();
+    assert_eq!(h.bar.screen().text(), "[+]");
+}
+
+#[tokio::test(start_paused = true)]
+async fn themed_elements_follow_the_theme() {
+    let bar_tui = tui::Elem::build_stack(tui::Axis::X, |stack| {
+        stack.fit(tui::Elem::themed(|theme| {
+            tui::RawPrint::plain("1")
+                .styled(theme.style(tui::ThemeRole::Accent))
+                .into()
+        }))
+    });
+    let mut h = start(tui::TermFeatures::all(), bar_tui).await;
+    let accent = |h: &Harness| {
+        h.bar
+            .screen()
+            .cell(tui::Vec2 { x: 0, y: 0 })
+            .unwrap()
+            .style
+            .fg
+    };
+    assert_eq!(accent(&h), Some(tui::Theme::dark().accent));
+
+    h.tui_tx
+        .send_modify(|state| state.theme = tui::Theme::light());
+    h.bar.settle().await.unwrap();
+    assert_eq!(accent(&h), Some(tui::Theme::light().accent));
+}
+
+#[tokio::test(start_paused = true)]
+async fn menus_follow_the_theme_opacity() {
+    let mut h = start(tui::TermFeatures::all(), bar_tui()).await;
+    let opacities = |h: &Harness| {
+        std::iter::once(&h.menu)
+            .chain(&h.extra_menus)
+            .map(HeadlessPanel::background_opacity)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(h.bar.background_opacity(), 1.0);
+    assert_eq!(
+        opacities(&h),
+        [tui::Theme::dark().menu_opacity; MENU_PANELS]
+    );
+
+    h.tui_tx
+        .send_modify(|state| state.theme = tui::Theme::light());
+    h.settle_all().await;
+    assert_eq!(
+        opacities(&h),
+        [tui::Theme::light().menu_opacity; MENU_PANELS]
+    );
+    assert_eq!(h.bar.background_opacity(), 1.0);
+}
+
+fn menu_button(menu: tui::Elem) -> tui::Elem {
+    tui::Elem::from(tui::RawPrint::plain("[menu]")).on_interact(
+        move |args: tui::InteractArgs| {
+            (args.kind == tui::InteractKind::Click(tui::MouseButton::Left))
+                .then(|| tui::OpenMenu::context(menu.clone()))
+        },
+        None,
+    )
+}
+
+#[tokio::test(start_paused = true)]
+async fn long_menus_scroll_and_fit_the_monitor() {
+    use crossterm::event::MouseEventKind as MK;
+
+    let lines = |n: usize| (0..n).map(|i| tui::Elem::from(tui::RawPrint::plain(format!("{i}"))));
+    let scroll = tui::Elem::scroll("list", tui::Axis::Y, 3, lines(5));
+    let mut h = start(tui::TermFeatures::all(), menu_button(scroll)).await;
+    h.bar
+        .click(h.bar.screen().find("[menu]").unwrap(), MouseButton::Left)
+        .unwrap();
+    h.settle_all().await;
+    assert_eq!(h.menu.screen().text(), "  0┃\n  1┃\n  2│");
+
+    for _ in 0..3 {
+        h.menu
+            .mouse(MK::ScrollDown, tui::Vec2 { x: 2, y: 1 })
+            .unwrap();
+        h.settle_all().await;
+    }
+    // Stops at the last item and keeps the menu open
+    assert!(h.menu.is_visible());
+    assert_eq!(h.menu.screen().text(), "  2│\n  3┃\n  4┃");
+
+    h.menu
+        .mouse(MK::ScrollUp, tui::Vec2 { x: 2, y: 1 })
+        .unwrap();
+    h.settle_all().await;
+    assert_eq!(h.menu.screen().text(), "  1┃\n  2┃\n  3│");
+
+    // The item that scrolls under the pointer is hovered
+    let hovered = Arc::new(std::sync::Mutex::new(None));
+    let items = (0..5).map(|i| {
+        let hovered = hovered.clone();
+        tui::Elem::from(tui::RawPrint::plain(format!("{i}"))).on_interact(
+            move |args: tui::InteractArgs| {
+                if args.kind == tui::InteractKind::Hover {
+                    *hovered.lock().unwrap() = Some(i);
+                }
+                None
+            },
+            None,
+        )
+    });
+    let scroll = tui::Elem::scroll("list", tui::Axis::Y, 3, items);
+    let mut h = start(tui::TermFeatures::all(), menu_button(scroll)).await;
+    h.bar
+        .click(h.bar.screen().find("[menu]").unwrap(), MouseButton::Left)
+        .unwrap();
+    h.settle_all().await;
+    h.menu.hover(tui::Vec2 { x: 2, y: 1 }).unwrap();
+    h.settle_all().await;
+    assert_eq!(*hovered.lock().unwrap(), Some(1));
+    h.menu
+        .mouse(MK::ScrollDown, tui::Vec2 { x: 2, y: 1 })
+        .unwrap();
+    h.settle_all().await;
+    assert_eq!(h.menu.screen().text(), "  1┃\n  2┃\n  3│");
+    assert_eq!(*hovered.lock().unwrap(), Some(2));
+
+    // 600 pixels minus the bar are 29 lines
+    let long = tui::Elem::build_stack(tui::Axis::Y, |stack| lines(40).for_each(|it| stack.fit(it)));
+    let mut h = start(tui::TermFeatures::all(), menu_button(long)).await;
+    h.bar
+        .click(h.bar.screen().find("[menu]").unwrap(), MouseButton::Left)
+        .unwrap();
+    h.settle_all().await;
+    assert_eq!(h.menu.screen().text().lines().count(), 29);
+    assert_eq!(h.menu.screen().text().lines().last(), Some("  28"));
+}
+
+/// A bar with a menu at its right edge, like a tray icon.
+fn right_edge_menu_tui(anchor: tui::MenuAnchor) -> tui::Elem {
+    tui::Elem::build_stack(tui::Axis::X, |stack| {
+        stack.spacing(74);
+        stack.fit(tui::Elem::from(tui::RawPrint::plain("[menu]")).on_interact(
+            move |args: tui::InteractArgs| {
+                (args.kind == tui::InteractKind::Click(tui::MouseButton::Left)).then(|| {
+                    tui::OpenMenu::context(tui::PlainLines::new("first\nsecond").into())
+                        .anchored(anchor)
+                })
+            },
+            None,
+        ));
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn menus_are_placed_by_their_anchor() {
+    // The placement itself is tested with `menu_position`. This checks the pixel
+    // positions of the element and the pointer that it gets. The menu is 10 cells
+    // wide, the element 6 cells at the end of 80 cells.
+    let cases = [
+        (
+            tui::MenuAnchor::Element(tui::MenuAlign::End),
+            tui::Vec2 { x: 700, y: 0 },
+        ),
+        (
+            tui::MenuAnchor::Corner(tui::Corner::BottomLeft),
+            tui::Vec2 { x: 0, y: 540 },
+        ),
+        // Menus of the bar open below the pointer, which is in the center of the
+        // clicked cell. This one is flipped to the left of it.
+        (tui::MenuAnchor::Pointer, tui::Vec2 { x: 645, y: 0 }),
+    ];
+
+    for (anchor, pos) in cases {
+        let mut h = start(tui::TermFeatures::all(), right_edge_menu_tui(anchor)).await;
+        h.bar
+            .click(h.bar.screen().find("[menu]").unwrap(), MouseButton::Left)
+            .unwrap();
+        h.settle_all().await;
+        assert!(h.menu.is_visible());
+        assert_eq!(h.menu.screen().text(), "  first\n  second");
+        assert_eq!(h.menu.position(), pos, "{anchor:?}");
+    }
+}
+
+fn nested_menu_tui() -> tui::Elem {
+    let menu = tui::Elem::from(tui::RawPrint::plain("[sub]")).on_interact(
+        |args: tui::InteractArgs| {
+            (args.kind == tui::InteractKind::Click(tui::MouseButton::Left))
+                .then(|| tui::OpenMenu::context(tui::RawPrint::plain("nested").into()))
+        },
+        None,
+    );
+    tui::Elem::from(tui::RawPrint::plain("[menu]")).on_interact(
+        move |args: tui::InteractArgs| {
+            (args.kind == tui::InteractKind::Click(tui::MouseButton::Left))
+                .then(|| tui::OpenMenu::context(menu.clone()))
+        },
+        None,
+    )
+}
+
+#[tokio::test(start_paused = true)]
+async fn nested_popups_close_with_their_parent() {
+    let mut h = start(tui::TermFeatures::all(), nested_menu_tui()).await;
+    let focus = |is_focused| TermEvent::FocusChange { is_focused };
+
+    let pos = h.bar.screen().find("[menu]").unwrap();
+    h.bar.click(pos, MouseButton::Left).unwrap();
+    h.settle_all().await;
+    let pos = h.menu.screen().find("[sub]").unwrap();
+    h.menu.click(pos, MouseButton::Left).unwrap();
+    h.settle_all().await;
+    assert!(h.menu.is_visible());
+    assert!(h.extra_menus[0].is_visible());
+    assert_eq!(h.extra_menus[0].screen().text(), "  nested");
+
+    // Focus moves into the nested menu and back
+    h.menu.send(focus(false)).unwrap();
+    h.extra_menus[0].send(focus(true)).unwrap();
+    h.settle_all().await;
+    assert!(h.menu.is_visible());
+    assert!(h.extra_menus[0].is_visible());
+
+    h.extra_menus[0].send(focus(false)).unwrap();
+    h.menu.send(focus(true)).unwrap();
+    h.settle_all().await;
+    assert!(h.menu.is_visible());
+    assert!(!h.extra_menus[0].is_visible());
+
+    // Clicking elsewhere closes the menu and everything opened from it
+    h.menu.click(pos, MouseButton::Left).unwrap();
+    h.settle_all().await;
+    assert!(h.extra_menus[0].is_visible());
+    h.menu.send(focus(false)).unwrap();
+    h.settle_all().await;
+    assert!(!h.menu.is_visible());
+    assert!(!h.extra_menus[0].is_visible());
+}
+
+#[tokio::test(start_paused = true)]
+async fn images_degrade_without_graphics() {
+    let image = || {
+        tui::Elem::image(
+            image::RgbaImage::new(20, 20),
+            tui::ImageSizeMode::FillAxis(tui::Axis::Y, 1),
+        )
+    };
+
+    let h = start(tui::TermFeatures::all(), image()).await;
+    assert_eq!(h.bar.screen().images().len(), 1);
+    assert_eq!(h.bar.screen().text(), "");
+
+    let h = start(tui::TermFeatures::none(), image()).await;
+    assert!(h.bar.screen().images().is_empty());
+    assert_eq!(h.bar.screen().text(), "▒▒");
+}
+
+#[tokio::test(start_paused = true)]
+async fn panels_exit_with_monitor() {
+    let mut h = start(tui::TermFeatures::all(), bar_tui()).await;
+
+    h.monitors_tx.send(Vec::new()).unwrap();
+    let res = tokio::time::timeout(Duration::from_secs(5), h.bar.next_frame())
+        .await
+        .expect("bar did not exit");
+    assert!(res.is_err());
+}
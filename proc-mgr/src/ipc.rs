@@ -1,5 +1,7 @@
-use std::ffi::OsString;
+use std::{ffi::OsString, path::PathBuf};
 
+use crate::kitty::KittyCommand;
+use anyhow::Context as _;
 use bar_common::utils::CancelDropGuard;
 use futures::{Stream, StreamExt as _};
 use serde::{Deserialize, Serialize};
@@ -8,20 +10,174 @@ use tokio_util::{sync::CancellationToken, time::FutureExt as _};
 pub(crate) const SOCK_PATH_VAR: &str = "BAR_TERM_INSTANCE_SOCK_PATH";
 pub(crate) const PROC_LOG_NAME_VAR: &str = "BAR_TERM_INSTANCE_NAME";
 
+/// Must be bumped whenever the serialized format of [`TermUpdate`] or [`TermEvent`] changes.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+/// How many updates that are not frames may be queued on either side before
+/// sending fails. Frames do not count, since they replace each other.
+pub(crate) const UPDATE_QUEUE_CAPACITY: usize = 256;
+
+/// Kitty's remote control is available, i.e. [`TermRequest::RemoteControl`] can succeed.
+pub const CAP_REMOTE_CONTROL: &str = "remote-control";
+/// [`TermRequest::Shell`] is supported.
+pub const CAP_SHELL: &str = "shell";
+
+/// The first message sent in each direction, before any [`TermUpdate`] or [`TermEvent`].
+///
+/// The layout of this struct must never change, since it is used to detect whether the
+/// controller and `bar-proc-mgr` were built from incompatible versions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub crate_version: String,
+    pub capabilities: Vec<String>,
+}
+impl Hello {
+    pub(crate) fn new(capabilities: Vec<String>) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").into(),
+            capabilities,
+        }
+    }
+
+    pub(crate) fn check_compatible(&self, peer: &Self) -> anyhow::Result<()> {
+        if self.protocol_version != peer.protocol_version {
+            anyhow::bail!(
+                "Incompatible protocol versions: ours is {} (version {}), the peer's is {} (version {}). \
+                Make sure that the controller and bar-proc-mgr are built from the same commit.",
+                self.protocol_version,
+                self.crate_version,
+                peer.protocol_version,
+                peer.crate_version,
+            );
+        }
+        if self.crate_version != peer.crate_version {
+            log::warn!(
+                "Crate version {} differs from the peer's {}, but the protocol is compatible",
+                self.crate_version,
+                peer.crate_version,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Sends our [`Hello`], reads the peer's and checks that they are compatible.
+///
+/// Reads byte-by-byte so that nothing after the peer's hello is consumed.
+pub(crate) async fn handshake(
+    stream: &mut tokio::net::UnixStream,
+    ours: &Hello,
+) -> anyhow::Result<Hello> {
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    let buf = postcard::to_stdvec_cobs(ours).context("Failed to serialize hello")?;
+    stream
+        .write_all(&buf)
+        .await
+        .context("Failed to send hello")?;
+
+    let mut buf = Vec::new();
+    loop {
+        let byte = stream.read_u8().await.context("Failed to read hello")?;
+        buf.push(byte);
+        if byte == 0 {
+            break;
+        }
+    }
+    let peer: Hello = postcard::from_bytes_cobs(&mut buf).context(
+        "Failed to deserialize hello. The peer was likely built from an incompatible version.",
+    )?;
+
+    ours.check_compatible(&peer)?;
+    Ok(peer)
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[non_exhaustive]
 pub enum TermUpdate {
     Print(Vec<u8>),
     Flush,
-    RemoteControl(Vec<OsString>),
-    Shell(OsString, Vec<OsString>), // TODO: Envs
+    /// Print and flush a complete frame. Frames that have not been printed
+    /// yet are dropped when a newer one arrives.
+    Frame(Vec<u8>),
+    /// A request that is answered with a [`TermEvent::Reply`] carrying the same id.
+    /// Use [`crate::TermUpdTx::request`] to create one.
+    Request {
+        id: RequestId,
+        req: TermRequest,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub enum TermRequest {
+    RemoteControl(KittyCommand),
+    Shell(ShellCommand),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ShellCommand {
+    pub program: OsString,
+    pub args: Vec<OsString>,
+    /// Variables to set, or to remove if the value is `None`.
+    pub envs: Vec<(OsString, Option<OsString>)>,
+    pub cwd: Option<PathBuf>,
+    pub stdin: Option<Vec<u8>>,
+    /// Do not wait for the command and do not capture its output. The command is
+    /// started in its own session so that it outlives the panel, which is what
+    /// launching an application should use.
+    pub detach: bool,
 }
 
+/// The outcome of a [`ShellCommand`]. Everything is empty for detached commands.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ShellOutput {
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(pub(crate) u64);
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum TermEvent {
     Crossterm(crossterm::event::Event),
     Sizes(bar_common::tui::Sizes),
-    FocusChange { is_focused: bool },
+    FocusChange {
+        is_focused: bool,
+    },
+    /// The outcome of a [`TermUpdate::Request`] that nobody waits for.
+    Reply {
+        id: RequestId,
+        result: Result<TermReply, String>,
+    },
+    /// A record logged by `bar-proc-mgr`. The connection to the panel logs these
+    /// in the controller process as they arrive and never passes them on.
+    Log(LogRecord),
+}
+
+/// A [`log::Record`] that is sent to the controller.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogRecord {
+    pub level: log::Level,
+    pub target: String,
+    pub module_path: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub message: String,
+    pub pid: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub enum TermReply {
+    /// The json `data` of kitty's reply, if any.
+    RemoteControl(Option<String>),
+    Shell(ShellOutput),
 }
 
 pub(crate) async fn read_cobs_sock<T: serde::de::DeserializeOwned>(
@@ -44,6 +200,7 @@ pub(crate) async fn read_cobs_sock<T: serde::de::DeserializeOwned>(
                 Ok(n) => log::trace!("Received {n} bytes"),
             }
 
+            let _span = bar_common::timing::span("ipc::read");
             match postcard::from_bytes_cobs(&mut buf) {
                 Err(err) => {
                     log::error!(
@@ -73,6 +230,7 @@ pub(crate) async fn write_cobs_sock<T: serde::Serialize>(
         use tokio::io::AsyncWriteExt as _;
         tokio::pin!(stream);
         while let Some(item) = stream.next().await {
+            let _span = bar_common::timing::span("ipc::write");
             let Ok(buf) = postcard::to_stdvec_cobs(&item)
                 .map_err(|err| log::error!("Failed to serialize update: {err}"))
             else {
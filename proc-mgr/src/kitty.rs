@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+/// A command for kitty's remote control protocol.
+///
+/// See <https://sw.kovidgoyal.net/kitty/rc_protocol/> for the wire format and
+/// <https://sw.kovidgoyal.net/kitty/remote-control/> for the semantics of each command.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum KittyCommand {
+    ResizeOsWindow {
+        action: ResizeAction,
+        incremental: bool,
+    },
+    /// Set paddings or margins. `None` resets the value to the configured one.
+    SetSpacing(Vec<(Spacing, Option<f64>)>),
+    /// Set colors by their kitty.conf name, e.g. `background` or `color1`.
+    /// `None` resets nullable colors like `cursor`.
+    SetColors(Vec<(String, Option<Rgb>)>),
+    SetBackgroundOpacity(f32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ResizeAction {
+    Show,
+    Hide,
+    ToggleVisibility,
+    OsPanel(Vec<PanelSetting>),
+}
+
+/// A setting of `kitten panel` that can be changed at runtime.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanelSetting {
+    Lines(u32),
+    Columns(u32),
+    MarginLeft(u32),
+    MarginRight(u32),
+    MarginTop(u32),
+    MarginBottom(u32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spacing {
+    pub kind: SpacingKind,
+    pub edge: Edge,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpacingKind {
+    Padding,
+    Margin,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
@@ -1,53 +1,88 @@
 mod ipc;
+pub mod kitty;
+mod requests;
 
-use bar_common::utils::{CancelDropGuard, ResultExt as _, UnbTx};
+use bar_common::utils::{CancelDropGuard, FrameRx, ResultExt as _, UnbTx, frame_chan};
 
 use std::ffi::OsString;
-use std::{ffi::OsStr, path::Path, time::Duration};
+use std::sync::Arc;
+use std::{path::Path, time::Duration};
 
 use anyhow::Context as _;
 use futures::Stream;
 use tokio::task::JoinSet;
 use tokio_util::{sync::CancellationToken, time::FutureExt as _};
 
-pub use ipc::{TermEvent, TermUpdate};
+pub use ipc::{
+    CAP_REMOTE_CONTROL, CAP_SHELL, Hello, PROTOCOL_VERSION, RequestId, ShellCommand, ShellOutput,
+    TermEvent, TermReply, TermRequest, TermUpdate,
+};
+pub use requests::TermUpdTx;
 
-// FIXME: Return channels instead of taking streams as args, also return
+/// Env var that specifies the `bar-proc-mgr` binary to run inside the terminal.
+/// If unset, it is looked up in `$PATH`.
+pub const PROC_MGR_PATH_VAR: &str = "BAR_PROC_MGR_PATH";
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The `bar-proc-mgr` binary, see [`PROC_MGR_PATH_VAR`].
+pub fn proc_mgr_program() -> OsString {
+    std::env::var_os(PROC_MGR_PATH_VAR).unwrap_or_else(|| "bar-proc-mgr".into())
+}
+
+/// Starts `terminal`, which is expected to run [`proc_mgr_program`] and connect to it.
+// FIXME: Return the event channel instead of taking it as an arg, also return
 // initial sizes
 pub async fn start_generic_panel(
     sock_path: &Path,
     log_name: &str,
-    upd_rx: impl Stream<Item = TermUpdate> + 'static + Send,
-    extra_args: impl IntoIterator<Item: AsRef<OsStr>>,
-    extra_envs: impl IntoIterator<Item = (OsString, OsString)>,
+    mut terminal: tokio::process::Command,
     term_ev_tx: UnbTx<TermEvent>,
     cancel: CancellationToken,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<TermUpdTx> {
     let socket = tokio::net::UnixListener::bind(sock_path)?;
 
-    let mut child = tokio::process::Command::new("kitten")
-        .arg("panel")
-        .args(extra_args)
-        .arg("bar-proc-mgr")
-        .envs(extra_envs)
+    let proc_mgr = proc_mgr_program();
+
+    let mut child = terminal
         .env(ipc::SOCK_PATH_VAR, sock_path)
         .env(ipc::PROC_LOG_NAME_VAR, log_name)
+        .envs(bar_common::logging::child_env())
         .env("PATH", std::env::var_os("PATH").unwrap())
         .kill_on_drop(true)
         .stdout(std::io::stderr())
         .spawn()
         .context("Failed to spawn terminal")?;
 
-    let (socket, _) = socket
+    let (mut socket, _) = socket
         .accept()
         .await
         .context("Failed to accept socket connection")?;
 
+    let peer = ipc::handshake(&mut socket, &Hello::new(Vec::new()))
+        .timeout(HANDSHAKE_TIMEOUT)
+        .await
+        .context("Timed out during handshake")
+        .and_then(|it| it)
+        .with_context(|| format!("Handshake with {proc_mgr:?} failed for {log_name}"))?;
+    log::debug!("{log_name} connected: {peer:?}");
+
+    let (upd_tx, upd_rx) = frame_chan(ipc::UPDATE_QUEUE_CAPACITY);
+    let upd_tx = TermUpdTx {
+        tx: upd_tx,
+        pending: Default::default(),
+        peer: Arc::new(peer),
+    };
+
+    let pending = upd_tx.pending.clone();
+    let log_name = log_name.to_owned();
     tokio::spawn(async move {
         let mut mgr = tokio_util::task::AbortOnDropHandle::new(tokio::spawn(run_term_inst_mgr(
             socket,
+            log_name,
             term_ev_tx,
-            upd_rx,
+            pending.clone(),
+            upd_rx.into_stream(),
             cancel.clone(),
         )));
         tokio::select! {
@@ -63,6 +98,7 @@ pub async fn start_generic_panel(
         };
         cancel.cancel();
         drop(mgr);
+        pending.clear();
 
         // Child should exit by itself because the socket connection is closed.
         let child_res = child.wait().timeout(Duration::from_secs(10)).await;
@@ -86,11 +122,86 @@ pub async fn start_generic_panel(
         }
     });
 
-    Ok(())
+    Ok(upd_tx)
 }
+/// Creates a panel that is driven in-process through the returned [`VirtualPanel`]
+/// instead of by `bar-proc-mgr` in a terminal, e.g. for tests.
+pub fn start_virtual_panel(
+    capabilities: Vec<String>,
+    term_ev_tx: UnbTx<TermEvent>,
+) -> (TermUpdTx, VirtualPanel) {
+    let (upd_tx, upd_rx) = frame_chan(ipc::UPDATE_QUEUE_CAPACITY);
+    let upd_tx = TermUpdTx {
+        tx: upd_tx,
+        pending: Default::default(),
+        peer: Arc::new(Hello::new(capabilities)),
+    };
+    let panel = VirtualPanel {
+        upd_rx,
+        ev_tx: term_ev_tx,
+        pending: upd_tx.pending.clone(),
+    };
+    (upd_tx, panel)
+}
+
+/// The terminal side of [`start_virtual_panel`].
+pub struct VirtualPanel {
+    upd_rx: FrameRx<TermUpdate>,
+    ev_tx: UnbTx<TermEvent>,
+    pending: Arc<requests::PendingReplies>,
+}
+impl VirtualPanel {
+    /// Returns `None` once the [`TermUpdTx`] is gone.
+    pub async fn recv(&mut self) -> Option<TermUpdate> {
+        self.upd_rx.recv().await
+    }
+
+    /// Sends an event as if it came from the terminal. Replies are routed like
+    /// those of a real terminal.
+    pub fn send(&self, ev: TermEvent) -> anyhow::Result<()> {
+        if let Some(ev) = self.pending.resolve(ev) {
+            self.ev_tx
+                .send(ev)
+                .context("Failed to send terminal event")?;
+        }
+        Ok(())
+    }
+}
+impl Drop for VirtualPanel {
+    fn drop(&mut self) {
+        self.pending.clear();
+    }
+}
+
+fn log_panel_record(log_name: &str, record: ipc::LogRecord) {
+    let ipc::LogRecord {
+        level,
+        target,
+        module_path,
+        file,
+        line,
+        message,
+        pid,
+    } = record;
+    bar_common::logging::log_from_panel(
+        log_name,
+        pid,
+        &log::Record::builder()
+            .args(format_args!("{message}"))
+            .level(level)
+            .target(&target)
+            .module_path(module_path.as_deref())
+            .file(file.as_deref())
+            .line(line)
+            .build(),
+    );
+}
+
 async fn run_term_inst_mgr(
     connection: tokio::net::UnixStream,
+    log_name: String,
     ev_tx: UnbTx<TermEvent>,
+    pending: Arc<requests::PendingReplies>,
     updates: impl Stream<Item = TermUpdate> + Send + 'static,
     cancel: CancellationToken,
 ) -> anyhow::Result<()> {
@@ -102,8 +213,13 @@ async fn run_term_inst_mgr(
 
     tasks.spawn(ipc::read_cobs_sock::<TermEvent>(
         read_half,
-        move |x| {
-            ev_tx.send(x).ok_or_debug();
+        move |x| match x {
+            TermEvent::Log(record) => log_panel_record(&log_name, record),
+            x => {
+                if let Some(x) = pending.resolve(x) {
+                    ev_tx.send(x).ok_or_debug();
+                }
+            }
         },
         cancel.clone(),
     ));
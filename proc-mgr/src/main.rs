@@ -1,12 +1,15 @@
 mod ipc;
+mod kitty;
+mod remote_control;
+mod shell;
 
 use anyhow::Context as _;
 use bar_common::{
-    tui,
-    utils::{CancelDropGuard, ResultExt as _, unb_chan},
+    timing, tui,
+    utils::{CancelDropGuard, ResultExt as _, frame_chan, unb_chan},
 };
 use futures::StreamExt as _;
-use ipc::{TermEvent, TermUpdate};
+use ipc::{TermEvent, TermRequest, TermUpdate};
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
 
@@ -17,7 +20,12 @@ fn main() -> anyhow::Result<()> {
             Err(err) => ("UNKNOWN".into(), Err(err)),
         };
 
-    bar_common::logging::init_logger(bar_common::logging::ProcKind::Panel, log_name);
+    // The controller passes its config through the environment
+    bar_common::logging::init_logger(
+        bar_common::logging::ProcKind::Panel,
+        log_name,
+        bar_common::logging::LogConfig::from_env(),
+    );
 
     res.ok_or_log();
 
@@ -37,26 +45,55 @@ fn main() -> anyhow::Result<()> {
 async fn term_proc_main_inner() -> anyhow::Result<()> {
     let mut tasks = JoinSet::new();
     let cancel = CancellationToken::new();
-    let (ev_tx, upd_rx);
+    let (ev_tx, mut upd_rx);
     {
         let socket = std::env::var_os(ipc::SOCK_PATH_VAR).context("Missing socket path env var")?;
-        let socket = tokio::net::UnixStream::connect(socket)
+        let mut socket = tokio::net::UnixStream::connect(socket)
             .await
             .context("Failed to connect to socket")?;
+
+        let mut capabilities = vec![ipc::CAP_SHELL.to_owned()];
+        if std::env::var_os("KITTY_LISTEN_ON").is_some() {
+            capabilities.push(ipc::CAP_REMOTE_CONTROL.to_owned());
+        }
+        ipc::handshake(&mut socket, &ipc::Hello::new(capabilities))
+            .await
+            .context("Handshake with controller failed")?;
+
         let (read, write) = socket.into_split();
 
         let (upd_tx, ev_rx);
         (ev_tx, ev_rx) = unb_chan::<TermEvent>();
-        (upd_tx, upd_rx) = std::sync::mpsc::channel::<TermUpdate>();
+        (upd_tx, upd_rx) = frame_chan::<TermUpdate>(ipc::UPDATE_QUEUE_CAPACITY);
 
         tasks.spawn(ipc::read_cobs_sock(
             read,
             move |x| {
-                upd_tx.send(x).ok_or_debug();
+                let res = match x {
+                    TermUpdate::Frame(_) => upd_tx.send_frame(x),
+                    _ => upd_tx.send(x),
+                };
+                res.context("Failed to queue update").ok_or_log();
             },
             cancel.clone(),
         ));
         tasks.spawn(ipc::write_cobs_sock(write, ev_rx, cancel.clone()));
+
+        // From now on, the controller does the logging
+        let log_tx = ev_tx.clone();
+        bar_common::logging::set_log_forwarder(move |record| {
+            log_tx
+                .send(TermEvent::Log(ipc::LogRecord {
+                    level: record.level(),
+                    target: record.target().to_owned(),
+                    module_path: record.module_path().map(Into::into),
+                    file: record.file().map(Into::into),
+                    line: record.line(),
+                    message: record.args().to_string(),
+                    pid: std::process::id(),
+                }))
+                .is_ok()
+        });
     }
 
     crossterm::execute!(
@@ -75,6 +112,10 @@ async fn term_proc_main_inner() -> anyhow::Result<()> {
         .send(TermEvent::Sizes(init_sizes))
         .context("Failed to send initial font size while starting panel. Exiting.")?;
 
+    let (rc_tx, rc_rx) = unb_chan();
+    tasks.spawn(remote_control::run_remote_control(rc_rx, ev_tx.clone()));
+    let shell_ev_tx = ev_tx.clone();
+
     tasks.spawn(async move {
         let events = crossterm::event::EventStream::new()
             .filter_map(async |res| res.context("Crossterm error").ok_or_log());
@@ -95,33 +136,15 @@ async fn term_proc_main_inner() -> anyhow::Result<()> {
         }
     });
 
-    fn run_cmd(cmd: &mut std::process::Command) {
-        if let Err(err) = (|| {
-            let std::process::Output {
-                status,
-                stdout: _,
-                stderr,
-            } = cmd.output()?;
-
-            if !status.success() {
-                anyhow::bail!(
-                    "Exited with status {status}. Stderr:\n{}",
-                    String::from_utf8_lossy(&stderr)
-                );
-            }
-            Ok(())
-        })() {
-            log::error!("Failed to run command {cmd:?}: {err}")
-        }
-    }
-
     let cancel_blocking = cancel.clone();
+    let runtime = tokio::runtime::Handle::current();
     std::thread::spawn(move || {
         let auto_cancel = CancelDropGuard::from(cancel_blocking);
         use std::io::Write as _;
         let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+        let mut dropped_frames = 0;
         while !auto_cancel.inner.is_cancelled()
-            && let Ok(upd) = upd_rx.recv()
+            && let Some(upd) = runtime.block_on(upd_rx.recv())
         {
             match upd {
                 TermUpdate::Print(bytes) => {
@@ -133,26 +156,35 @@ async fn term_proc_main_inner() -> anyhow::Result<()> {
                 TermUpdate::Flush => {
                     stdout.flush().context("Failed to flush").ok_or_log();
                 }
-                TermUpdate::RemoteControl(args) => {
-                    let Some(listen_on) = std::env::var_os("KITTY_LISTEN_ON")
-                        .context("Missing KITTY_LISTEN_ON")
-                        .ok_or_log()
-                    else {
-                        continue;
-                    };
-                    run_cmd(
-                        std::process::Command::new("kitten")
-                            .arg("@")
-                            .arg("--to")
-                            .arg(listen_on)
-                            .args(args),
-                    );
+                TermUpdate::Frame(bytes) => {
+                    stdout
+                        .write_all(&bytes)
+                        .and_then(|()| stdout.flush())
+                        .context("Failed to print frame")
+                        .ok_or_log();
+
+                    let dropped = upd_rx.dropped_frames();
+                    if dropped != dropped_frames {
+                        dropped_frames = dropped;
+                        timing::counter("proc_mgr::dropped_frames", dropped);
+                    }
+                }
+                TermUpdate::Request {
+                    id,
+                    req: TermRequest::RemoteControl(cmd),
+                } => {
+                    rc_tx.send((id, cmd)).ok_or_debug();
                 }
-                TermUpdate::Shell(cmd, args) => {
-                    run_cmd(std::process::Command::new(cmd).args(args));
+                TermUpdate::Request {
+                    id,
+                    req: TermRequest::Shell(cmd),
+                } => {
+                    // Run off this thread so that slow commands do not block rendering.
+                    runtime.spawn(shell::run_shell(id, cmd, shell_ev_tx.clone()));
                 }
             }
         }
+        log::debug!("Dropped {} stale frames", upd_rx.dropped_frames());
     });
 
     tokio::select! {
@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bar_common::utils::{ResultExt as _, UnbRx, UnbTx};
+use futures::StreamExt as _;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _};
+use tokio_util::time::FutureExt as _;
+
+use crate::{
+    ipc::{RequestId, TermEvent, TermReply},
+    kitty::{Edge, KittyCommand, PanelSetting, ResizeAction, SpacingKind},
+};
+
+const LISTEN_ON_VAR: &str = "KITTY_LISTEN_ON";
+
+// https://sw.kovidgoyal.net/kitty/rc_protocol/
+const CMD_PREFIX: &[u8] = b"\x1bP@kitty-cmd";
+const CMD_SUFFIX: &[u8] = b"\x1b\\";
+/// The version of kitty that the payloads are written against. `resize-os-window`
+/// only supports `os-panel` since 0.42.
+const PROTOCOL_VERSION: [u32; 3] = [0, 42, 0];
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs the commands in order, since e.g. `hide` after `os-panel` must not overtake it.
+pub(crate) async fn run_remote_control(
+    cmd_rx: UnbRx<(RequestId, KittyCommand)>,
+    ev_tx: UnbTx<TermEvent>,
+) {
+    let listen_on =
+        std::env::var(LISTEN_ON_VAR).with_context(|| format!("Missing {LISTEN_ON_VAR}"));
+
+    tokio::pin!(cmd_rx);
+    while let Some((id, cmd)) = cmd_rx.next().await {
+        let res = match &listen_on {
+            Ok(listen_on) => send_command(listen_on, &cmd)
+                .await
+                .with_context(|| format!("Failed to run remote control command {cmd:?}")),
+            Err(err) => Err(anyhow::anyhow!("{err}")),
+        };
+        if let Err(err) = &res {
+            log::debug!("{err:?}");
+        }
+        ev_tx
+            .send(TermEvent::Reply {
+                id,
+                result: res
+                    .map(TermReply::RemoteControl)
+                    .map_err(|err| format!("{err:#}")),
+            })
+            .ok_or_debug();
+    }
+}
+
+async fn connect(listen_on: &str) -> anyhow::Result<tokio::net::UnixStream> {
+    let addr = listen_on
+        .strip_prefix("unix:")
+        .with_context(|| format!("Unsupported {LISTEN_ON_VAR} {listen_on:?}"))?;
+
+    if let Some(name) = addr.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt as _;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        let stream = std::os::unix::net::UnixStream::connect_addr(&addr)?;
+        stream.set_nonblocking(true)?;
+        Ok(tokio::net::UnixStream::from_std(stream)?)
+    } else {
+        Ok(tokio::net::UnixStream::connect(addr).await?)
+    }
+}
+
+/// Sends the command and returns the `data` of the reply as a json string, if any.
+async fn send_command(listen_on: &str, cmd: &KittyCommand) -> anyhow::Result<Option<String>> {
+    let _span = bar_common::timing::span("kitty::remote_control");
+    let (name, payload) = to_payload(cmd);
+    let msg = json!({
+        "cmd": name,
+        "version": PROTOCOL_VERSION,
+        "no_response": false,
+        "payload": payload,
+    });
+
+    let mut buf = Vec::from(CMD_PREFIX);
+    serde_json::to_writer(&mut buf, &msg)?;
+    buf.extend_from_slice(CMD_SUFFIX);
+
+    let mut stream = tokio::io::BufReader::new(connect(listen_on).await?);
+    stream.write_all(&buf).await?;
+
+    let mut reply = Vec::new();
+    while !reply.ends_with(CMD_SUFFIX) {
+        let n = stream
+            .read_until(CMD_SUFFIX[1], &mut reply)
+            .timeout(REPLY_TIMEOUT)
+            .await
+            .context("Timed out waiting for reply")??;
+        if n == 0 {
+            anyhow::bail!("Connection closed before reply was complete");
+        }
+    }
+
+    let reply = reply
+        .strip_prefix(CMD_PREFIX)
+        .and_then(|it| it.strip_suffix(CMD_SUFFIX))
+        .with_context(|| format!("Malformed reply {:?}", String::from_utf8_lossy(&reply)))?;
+
+    #[derive(serde::Deserialize)]
+    struct Reply {
+        ok: bool,
+        #[serde(default)]
+        data: Option<Value>,
+        #[serde(default)]
+        error: Option<String>,
+    }
+    let Reply { ok, data, error } = serde_json::from_slice(reply).context("Invalid reply")?;
+
+    if !ok {
+        anyhow::bail!("{}", error.as_deref().unwrap_or("Unknown error"));
+    }
+    Ok(data.map(|it| it.to_string()))
+}
+
+fn to_payload(cmd: &KittyCommand) -> (&'static str, Value) {
+    match cmd {
+        KittyCommand::ResizeOsWindow {
+            action,
+            incremental,
+        } => {
+            let mut payload = json!({
+                "incremental": incremental,
+                "action": match action {
+                    ResizeAction::Show => "show",
+                    ResizeAction::Hide => "hide",
+                    ResizeAction::ToggleVisibility => "toggle-visibility",
+                    ResizeAction::OsPanel(_) => "os-panel",
+                },
+            });
+            if let ResizeAction::OsPanel(settings) = action {
+                payload["os_panel"] = settings
+                    .iter()
+                    .map(|setting| match *setting {
+                        PanelSetting::Lines(n) => format!("lines={n}"),
+                        PanelSetting::Columns(n) => format!("columns={n}"),
+                        PanelSetting::MarginLeft(n) => format!("margin-left={n}"),
+                        PanelSetting::MarginRight(n) => format!("margin-right={n}"),
+                        PanelSetting::MarginTop(n) => format!("margin-top={n}"),
+                        PanelSetting::MarginBottom(n) => format!("margin-bottom={n}"),
+                    })
+                    .collect();
+            }
+            ("resize-os-window", payload)
+        }
+        KittyCommand::SetSpacing(settings) => {
+            let settings: serde_json::Map<_, _> = settings
+                .iter()
+                .map(|(spacing, value)| {
+                    let kind = match spacing.kind {
+                        SpacingKind::Padding => "padding",
+                        SpacingKind::Margin => "margin",
+                    };
+                    let edge = match spacing.edge {
+                        Edge::Top => "top",
+                        Edge::Bottom => "bottom",
+                        Edge::Left => "left",
+                        Edge::Right => "right",
+                    };
+                    (format!("{kind}-{edge}"), json!(value))
+                })
+                .collect();
+            ("set-spacing", json!({ "settings": settings }))
+        }
+        KittyCommand::SetColors(colors) => {
+            let colors: serde_json::Map<_, _> = colors
+                .iter()
+                .map(|(name, color)| {
+                    let color = color.map(|c| u32::from_be_bytes([0, c.r, c.g, c.b]));
+                    (name.clone(), json!(color))
+                })
+                .collect();
+            ("set-colors", json!({ "colors": colors }))
+        }
+        KittyCommand::SetBackgroundOpacity(opacity) => {
+            ("set-background-opacity", json!({ "opacity": opacity }))
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, atomic::AtomicU64},
+};
+
+use anyhow::Context as _;
+use bar_common::utils::FrameTx;
+use tokio::sync::oneshot;
+
+use crate::ipc::{Hello, RequestId, TermEvent, TermReply, TermRequest, TermUpdate};
+
+type ReplyTx = oneshot::Sender<Result<TermReply, String>>;
+
+#[derive(Default)]
+pub(crate) struct PendingReplies {
+    next_id: AtomicU64,
+    waiting: Mutex<HashMap<RequestId, ReplyTx>>,
+}
+impl PendingReplies {
+    fn next_id(&self) -> RequestId {
+        RequestId(
+            self.next_id
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+        )
+    }
+
+    /// Resolves the request that `ev` replies to, if someone is waiting for it.
+    /// Returns the event if it should be passed on.
+    pub(crate) fn resolve(&self, ev: TermEvent) -> Option<TermEvent> {
+        let TermEvent::Reply { id, result } = ev else {
+            return Some(ev);
+        };
+        let waiting = self
+            .waiting
+            .lock()
+            .unwrap_or_else(|it| it.into_inner())
+            .remove(&id);
+        match waiting {
+            Some(tx) => {
+                // The receiver may have given up waiting, that's fine.
+                _ = tx.send(result);
+                None
+            }
+            None => Some(TermEvent::Reply { id, result }),
+        }
+    }
+
+    /// Fails all requests that are still waiting, e.g. because the terminal exited.
+    pub(crate) fn clear(&self) {
+        self.waiting
+            .lock()
+            .unwrap_or_else(|it| it.into_inner())
+            .clear();
+    }
+}
+
+/// Removes a request from [`PendingReplies::waiting`] when dropped.
+struct WaitingGuard<'a> {
+    pending: &'a PendingReplies,
+    id: RequestId,
+}
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.pending
+            .waiting
+            .lock()
+            .unwrap_or_else(|it| it.into_inner())
+            .remove(&self.id);
+    }
+}
+
+/// Sends updates to a terminal started with [`crate::start_generic_panel`].
+#[derive(Clone)]
+pub struct TermUpdTx {
+    pub(crate) tx: FrameTx<TermUpdate>,
+    pub(crate) pending: Arc<PendingReplies>,
+    pub(crate) peer: Arc<Hello>,
+}
+impl TermUpdTx {
+    /// The [`Hello`] that `bar-proc-mgr` sent during the handshake.
+    pub fn peer(&self) -> &Hello {
+        &self.peer
+    }
+
+    pub fn has_capability(&self, cap: &str) -> bool {
+        self.peer.capabilities.iter().any(|it| it == cap)
+    }
+
+    pub fn send(&self, upd: TermUpdate) -> anyhow::Result<()> {
+        self.tx.send(upd).context("Failed to send terminal update")
+    }
+
+    /// Sends a [`TermUpdate::Frame`], replacing any frame that has not been sent yet.
+    pub fn send_frame(&self, frame: Vec<u8>) -> anyhow::Result<()> {
+        self.tx
+            .send_frame(TermUpdate::Frame(frame))
+            .context("Failed to send frame")
+    }
+
+    /// The number of frames that were replaced by a newer one before being sent.
+    pub fn dropped_frames(&self) -> u64 {
+        self.tx.dropped_frames()
+    }
+
+    /// Sends a request without waiting for it. The reply is delivered as [`TermEvent::Reply`].
+    pub fn request(&self, req: TermRequest) -> anyhow::Result<RequestId> {
+        let id = self.pending.next_id();
+        self.send(TermUpdate::Request { id, req })?;
+        Ok(id)
+    }
+
+    /// Sends a request and waits for its reply. The reply is not delivered as a [`TermEvent`].
+    pub async fn send_and_wait(&self, req: TermRequest) -> anyhow::Result<TermReply> {
+        let id = self.pending.next_id();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.pending
+            .waiting
+            .lock()
+            .unwrap_or_else(|it| it.into_inner())
+            .insert(id, reply_tx);
+        // Stops waiting if sending fails or the caller gives up, e.g. on a timeout
+        let _guard = WaitingGuard {
+            pending: &self.pending,
+            id,
+        };
+
+        self.send(TermUpdate::Request { id, req })?;
+
+        reply_rx
+            .await
+            .context("Terminal exited before replying")?
+            .map_err(anyhow::Error::msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::StreamExt as _;
+    use tokio_util::time::FutureExt as _;
+
+    use super::*;
+    use crate::kitty::{KittyCommand, ResizeAction};
+
+    fn waiting(tx: &TermUpdTx) -> usize {
+        tx.pending.waiting.lock().unwrap().len()
+    }
+
+    fn request() -> TermRequest {
+        TermRequest::RemoteControl(KittyCommand::ResizeOsWindow {
+            action: ResizeAction::Show,
+            incremental: false,
+        })
+    }
+
+    #[tokio::test]
+    async fn replies_resolve_the_waiting_request() {
+        let (ev_tx, mut ev_rx) = bar_common::utils::unb_chan();
+        let (tx, mut panel) = crate::start_virtual_panel(Vec::new(), ev_tx);
+
+        let (reply, ()) = tokio::join!(tx.send_and_wait(request()), async {
+            let Some(TermUpdate::Request { id, .. }) = panel.recv().await else {
+                panic!("expected a request");
+            };
+            panel
+                .send(TermEvent::Reply {
+                    id,
+                    result: Ok(TermReply::RemoteControl(None)),
+                })
+                .unwrap();
+        });
+        assert!(matches!(reply, Ok(TermReply::RemoteControl(None))));
+        assert_eq!(waiting(&tx), 0);
+
+        // Nobody waits for these
+        let id = tx.request(request()).unwrap();
+        panel
+            .send(TermEvent::Reply {
+                id,
+                result: Err("failed".into()),
+            })
+            .unwrap();
+        assert!(matches!(
+            ev_rx.next().await,
+            Some(TermEvent::Reply { id: reply_id, result: Err(_) }) if reply_id == id
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancelled_requests_stop_waiting() {
+        let (ev_tx, _ev_rx) = bar_common::utils::unb_chan();
+        let (tx, _panel) = crate::start_virtual_panel(Vec::new(), ev_tx);
+
+        let res = tx
+            .send_and_wait(request())
+            .timeout(Duration::from_millis(10))
+            .await;
+        assert!(res.is_err());
+        assert_eq!(waiting(&tx), 0);
+    }
+}
@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bar_common::utils::{ResultExt as _, UnbTx};
+use futures::FutureExt as _;
+use tokio::io::AsyncWriteExt as _;
+use tokio_util::time::FutureExt as _;
+
+use crate::ipc::{RequestId, ShellCommand, ShellOutput, TermEvent, TermReply};
+
+/// How long to keep writing the input of a detached command that does not read it.
+const DETACHED_STDIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Runs the command and sends its outcome as a [`TermEvent::Reply`].
+pub(crate) async fn run_shell(id: RequestId, cmd: ShellCommand, ev_tx: UnbTx<TermEvent>) {
+    let res = try_run_shell(&cmd)
+        .await
+        .with_context(|| format!("Failed to run command {cmd:?}"));
+    if let Err(err) = &res {
+        log::debug!("{err:?}");
+    }
+    ev_tx
+        .send(TermEvent::Reply {
+            id,
+            result: res.map(TermReply::Shell).map_err(|err| format!("{err:#}")),
+        })
+        .ok_or_debug();
+}
+
+async fn try_run_shell(cmd: &ShellCommand) -> anyhow::Result<ShellOutput> {
+    use std::process::Stdio;
+
+    let ShellCommand {
+        program,
+        args,
+        envs,
+        cwd,
+        stdin,
+        detach,
+    } = cmd;
+
+    let mut command = tokio::process::Command::new(program);
+    command.args(args);
+    for (key, val) in envs {
+        match val {
+            Some(val) => command.env(key, val),
+            None => command.env_remove(key),
+        };
+    }
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+    command.stdin(if stdin.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    });
+
+    if *detach {
+        // Start a new session so that the child has no controlling terminal and
+        // does not receive the terminal's SIGHUP when the panel exits.
+        // SAFETY: setsid is async-signal-safe and nothing else runs in the fork.
+        unsafe {
+            command.pre_exec(|| match libc::setsid() {
+                -1 => Err(std::io::Error::last_os_error()),
+                _ => Ok(()),
+            });
+        }
+        command.stdout(Stdio::null()).stderr(Stdio::null());
+        let mut child = command.spawn()?;
+        if let Some(pipe) = child.stdin.take() {
+            // The child might never read its input, which must not hold up the reply.
+            tokio::spawn(
+                write_stdin(pipe, stdin.clone().unwrap_or_default())
+                    .timeout(DETACHED_STDIN_TIMEOUT)
+                    .map(|res| {
+                        res.context("Timed out writing stdin of detached command")
+                            .flatten()
+                            .ok_or_debug()
+                    }),
+            );
+        }
+        // tokio reaps the child in the background once it is dropped.
+        return Ok(ShellOutput::default());
+    }
+
+    command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = command.spawn()?;
+    let pipe = child.stdin.take();
+    // Writing the input while collecting the output, since the child might not read
+    // more input before its output was read.
+    let (written, output) = tokio::join!(
+        async {
+            match pipe {
+                Some(pipe) => write_stdin(pipe, stdin.clone().unwrap_or_default()).await,
+                None => Ok(()),
+            }
+        },
+        child.wait_with_output(),
+    );
+    let std::process::Output {
+        status,
+        stdout,
+        stderr,
+    } = output?;
+    written?;
+
+    use std::os::unix::process::ExitStatusExt as _;
+    Ok(ShellOutput {
+        code: status.code(),
+        signal: status.signal(),
+        stdout,
+        stderr,
+    })
+}
+
+/// Writes the input and closes the pipe. A child that exits without reading all of
+/// its input is not an error.
+async fn write_stdin(mut pipe: tokio::process::ChildStdin, input: Vec<u8>) -> anyhow::Result<()> {
+    match pipe.write_all(&input).await {
+        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        res => res.context("Failed to write stdin"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> ShellCommand {
+        ShellCommand {
+            program: "sh".into(),
+            args: vec!["-c".into(), script.into()],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn output_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().canonicalize().unwrap();
+        let out = try_run_shell(&ShellCommand {
+            envs: vec![
+                ("BAR_TEST_VAR".into(), Some("set".into())),
+                ("HOME".into(), None),
+            ],
+            cwd: Some(cwd.clone()),
+            stdin: Some(b"input".to_vec()),
+            ..sh(r#"echo "$BAR_TEST_VAR ${HOME-unset} $(pwd -P)"; cat; echo err >&2; exit 3"#)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(out.code, Some(3));
+        assert_eq!(out.signal, None);
+        assert_eq!(
+            String::from_utf8(out.stdout).unwrap(),
+            format!("set unset {}\ninput", cwd.display()),
+        );
+        assert_eq!(out.stderr, b"err\n");
+    }
+
+    #[tokio::test]
+    async fn large_input_does_not_block_on_output() {
+        // Larger than the pipe buffers, so the child blocks on writing its output
+        // until it is read
+        let input = vec![b'x'; 1 << 20];
+        let out = try_run_shell(&ShellCommand {
+            stdin: Some(input.clone()),
+            ..sh("cat")
+        })
+        .timeout(Duration::from_secs(10))
+        .await
+        .expect("reading and writing deadlocked")
+        .unwrap();
+        assert_eq!(out.code, Some(0));
+        assert_eq!(out.stdout, input);
+    }
+
+    #[tokio::test]
+    async fn ignored_input_is_not_an_error() {
+        let out = try_run_shell(&ShellCommand {
+            stdin: Some(vec![b'x'; 1 << 20]),
+            ..sh("exit 0")
+        })
+        .await
+        .unwrap();
+        assert_eq!(out.code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn detached_commands_do_not_wait_for_input() {
+        let out = try_run_shell(&ShellCommand {
+            stdin: Some(vec![b'x'; 1 << 20]),
+            detach: true,
+            ..sh("sleep 1")
+        })
+        .timeout(Duration::from_secs(1))
+        .await
+        .expect("waited for the detached command");
+        assert_eq!(out.unwrap().code, None);
+    }
+
+    #[tokio::test]
+    async fn detached_commands_lead_their_own_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session");
+        // Writes the process and session id
+        let script = format!(
+            "cut -d' ' -f1,6 /proc/$$/stat > {0}.tmp && mv {0}.tmp {0}",
+            path.display()
+        );
+        try_run_shell(&sh(&script)).await.unwrap();
+        let not_detached = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        try_run_shell(&ShellCommand {
+            detach: true,
+            ..sh(&script)
+        })
+        .await
+        .unwrap();
+        let detached = async {
+            loop {
+                if let Ok(text) = std::fs::read_to_string(&path) {
+                    return text;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        .timeout(Duration::from_secs(5))
+        .await
+        .expect("detached command did not run");
+
+        let ids = |text: &str| -> (String, String) {
+            let (pid, sid) = text.trim().split_once(' ').unwrap();
+            (pid.to_owned(), sid.to_owned())
+        };
+        let (pid, sid) = ids(&not_detached);
+        assert_ne!(pid, sid);
+        let (pid, sid) = ids(&detached);
+        assert_eq!(pid, sid);
+    }
+
+    #[tokio::test]
+    async fn signals_are_reported() {
+        let out = try_run_shell(&sh("kill -TERM $$")).await.unwrap();
+        assert_eq!(out.code, None);
+        assert_eq!(out.signal, Some(libc::SIGTERM));
+    }
+}